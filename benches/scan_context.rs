@@ -0,0 +1,24 @@
+//! Scanning with a fresh context each time, as `find_windows` does, against
+//! one `ScanContext` reused across scans the way a long-running watcher would.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use panel_detector::window_search::{find_windows, ScanContext, WindowSearchCriteria};
+
+fn scans(c: &mut Criterion) {
+    let criteria = WindowSearchCriteria::new();
+    let mut group = c.benchmark_group("scan");
+
+    group.bench_function("per_scan_setup", |b| {
+        b.iter(|| find_windows(&criteria).expect("window list"))
+    });
+
+    let mut context = ScanContext::new();
+    group.bench_function("reused_context", |b| {
+        b.iter(|| context.find_windows(&criteria).expect("window list"))
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, scans);
+criterion_main!(benches);
@@ -0,0 +1,210 @@
+use std::fmt;
+
+use objc2_app_kit::{NSApplicationActivationOptions, NSRunningApplication};
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "accessibility")]
+use crate::ax::AxError;
+use crate::window_search::{Pid, WindowNumber};
+
+#[derive(Debug)]
+pub enum ActivationError {
+    /// The app has quit, or the pid was never an app.
+    NoApplication(Pid),
+    ActivationFailed(Pid),
+    /// The app is running but AX can't find the window, e.g. it has closed.
+    #[cfg(feature = "accessibility")]
+    WindowNotFound(WindowNumber),
+    #[cfg(feature = "accessibility")]
+    RaiseFailed(WindowNumber, AxError),
+}
+
+impl fmt::Display for ActivationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ActivationError::NoApplication(pid) => {
+                write!(f, "No running application with pid {}", pid)
+            }
+            ActivationError::ActivationFailed(pid) => {
+                write!(f, "Failed to activate application with pid {}", pid)
+            }
+            #[cfg(feature = "accessibility")]
+            ActivationError::WindowNotFound(window_number) => {
+                write!(f, "Window {} not found via accessibility", window_number)
+            }
+            #[cfg(feature = "accessibility")]
+            ActivationError::RaiseFailed(window_number, error) => {
+                write!(f, "Failed to raise window {}: {}", window_number, error)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ActivationError {}
+
+/// Just enough of a `WindowInfo` to bring the window back later, e.g. from a
+/// window switcher's saved state. The pid and window number are only valid
+/// while the app keeps running; `bundle_id` and `title` help recognise it after
+/// a relaunch.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ActivationTarget {
+    pub pid: Pid,
+    pub bundle_id: Option<String>,
+    pub window_number: WindowNumber,
+    pub title: Option<String>,
+}
+
+impl ActivationTarget {
+    pub fn activate(&self) -> Result<(), ActivationError> {
+        activate_window(self.pid, self.window_number)
+    }
+}
+
+/// Brings the app owning `pid` to the front. With the `accessibility` feature the
+/// specific window is raised above the app's other windows as well; without it
+/// only the app is activated, leaving its own window order untouched.
+pub fn activate_window(pid: Pid, window_number: WindowNumber) -> Result<(), ActivationError> {
+    activate_window_with(pid, window_number, activate_app, raise_window)
+}
+
+// `activate_app` is `None` when no app has the pid, else whether it came
+// forward; the window is only raised once it has.
+fn activate_window_with(
+    pid: Pid,
+    window_number: WindowNumber,
+    activate_app: impl FnOnce(Pid) -> Option<bool>,
+    raise_window: impl FnOnce(Pid, WindowNumber) -> Result<(), ActivationError>,
+) -> Result<(), ActivationError> {
+    match activate_app(pid) {
+        None => Err(ActivationError::NoApplication(pid)),
+        Some(false) => Err(ActivationError::ActivationFailed(pid)),
+        Some(true) => raise_window(pid, window_number),
+    }
+}
+
+fn activate_app(pid: Pid) -> Option<bool> {
+    NSRunningApplication::runningApplicationWithProcessIdentifier(pid.0)
+        .map(|app| app.activateWithOptions(NSApplicationActivationOptions::empty()))
+}
+
+#[cfg(feature = "accessibility")]
+fn raise_window(pid: Pid, window_number: WindowNumber) -> Result<(), ActivationError> {
+    let window = crate::ax::find_window_element(pid.0, window_number.0)
+        .ok_or(ActivationError::WindowNotFound(window_number))?;
+
+    window
+        .perform_action("AXRaise")
+        .map_err(|error| ActivationError::RaiseFailed(window_number, error))
+}
+
+#[cfg(not(feature = "accessibility"))]
+fn raise_window(_pid: Pid, _window_number: WindowNumber) -> Result<(), ActivationError> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+    use crate::window_search::WindowInfo;
+
+    fn activate(
+        activated: Option<bool>,
+        raised: &Cell<Option<(Pid, WindowNumber)>>,
+    ) -> Result<(), ActivationError> {
+        activate_window_with(
+            Pid(42),
+            WindowNumber(7),
+            |_| activated,
+            |pid, window_number| {
+                raised.set(Some((pid, window_number)));
+                Ok(())
+            },
+        )
+    }
+
+    #[test]
+    fn raises_the_window_once_the_app_is_active() {
+        let raised = Cell::new(None);
+
+        assert!(activate(Some(true), &raised).is_ok());
+        assert_eq!(raised.get(), Some((Pid(42), WindowNumber(7))));
+    }
+
+    #[test]
+    fn does_not_raise_when_the_app_is_gone_or_refuses() {
+        let raised = Cell::new(None);
+
+        assert!(matches!(
+            activate(None, &raised),
+            Err(ActivationError::NoApplication(Pid(42)))
+        ));
+        assert!(matches!(
+            activate(Some(false), &raised),
+            Err(ActivationError::ActivationFailed(Pid(42)))
+        ));
+        assert_eq!(raised.get(), None);
+    }
+
+    #[cfg(feature = "accessibility")]
+    #[test]
+    fn raise_failures_are_returned() {
+        let result = activate_window_with(
+            Pid(42),
+            WindowNumber(7),
+            |_| Some(true),
+            |_, window_number| Err(ActivationError::WindowNotFound(window_number)),
+        );
+
+        assert!(matches!(
+            result,
+            Err(ActivationError::WindowNotFound(WindowNumber(7)))
+        ));
+    }
+
+    #[cfg(not(feature = "accessibility"))]
+    #[test]
+    fn without_accessibility_raising_is_a_no_op() {
+        assert!(raise_window(Pid(42), WindowNumber(7)).is_ok());
+    }
+
+    #[test]
+    fn activation_targets_round_trip_through_json() {
+        let window = WindowInfo::builder()
+            .with_pid(314)
+            .with_window_number(42)
+            .with_bundle_identifier("com.apple.TextEdit")
+            .with_title("Notes.txt")
+            .build();
+        let target = window.activation_target();
+
+        let json = serde_json::to_string(&target).unwrap();
+
+        assert_eq!(
+            json,
+            r#"{"pid":314,"bundle_id":"com.apple.TextEdit","window_number":42,"title":"Notes.txt"}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<ActivationTarget>(&json).unwrap(),
+            target
+        );
+    }
+
+    #[test]
+    fn untitled_targets_without_a_bundle_id_round_trip() {
+        let target = WindowInfo::builder()
+            .with_window_number(7)
+            .without_title()
+            .build()
+            .activation_target();
+
+        let json = serde_json::to_string(&target).unwrap();
+
+        assert_eq!(target.bundle_id, None);
+        assert_eq!(
+            serde_json::from_str::<ActivationTarget>(&json).unwrap(),
+            target
+        );
+    }
+}
@@ -0,0 +1,566 @@
+use std::fmt;
+use std::os::raw::c_void;
+use std::ptr;
+
+use crate::cf::{cf_string_to_string, CfString, CfType};
+use crate::window_search::WindowBounds;
+
+const K_AX_ERROR_SUCCESS: i32 = 0;
+const K_AX_ERROR_FAILURE: i32 = -25200;
+const K_AX_ERROR_INVALID_UI_ELEMENT: i32 = -25202;
+const K_AX_ERROR_CANNOT_COMPLETE: i32 = -25204;
+const K_AX_ERROR_NOTIFICATION_UNSUPPORTED: i32 = -25207;
+const K_AX_ERROR_API_DISABLED: i32 = -25211;
+const K_AX_VALUE_CG_POINT_TYPE: u32 = 1;
+const K_AX_VALUE_CG_SIZE_TYPE: u32 = 2;
+
+type AxObserverCallback = extern "C" fn(
+    observer: *const c_void,
+    element: *const c_void,
+    notification: *const c_void,
+    refcon: *mut c_void,
+);
+
+/// A failed AX call, holding the `AXError` code it returned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AxError(pub i32);
+
+impl AxError {
+    /// The process hasn't been granted the accessibility permission.
+    pub fn is_permission_denied(&self) -> bool {
+        self.0 == K_AX_ERROR_API_DISABLED
+    }
+
+    fn check(code: i32) -> Result<(), AxError> {
+        if code == K_AX_ERROR_SUCCESS {
+            Ok(())
+        } else {
+            Err(AxError(code))
+        }
+    }
+}
+
+impl fmt::Display for AxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            K_AX_ERROR_API_DISABLED => write!(f, "Accessibility permission not granted"),
+            K_AX_ERROR_INVALID_UI_ELEMENT => write!(f, "Element no longer exists"),
+            K_AX_ERROR_CANNOT_COMPLETE => write!(f, "The application did not respond"),
+            K_AX_ERROR_NOTIFICATION_UNSUPPORTED => write!(f, "Notification not supported"),
+            code => write!(f, "AXError {}", code),
+        }
+    }
+}
+
+impl std::error::Error for AxError {}
+
+#[link(name = "ApplicationServices", kind = "framework")]
+extern "C" {
+    fn AXObserverCreate(
+        application: i32,
+        callback: AxObserverCallback,
+        observer: *mut *const c_void,
+    ) -> i32;
+    fn AXObserverAddNotification(
+        observer: *const c_void,
+        element: *const c_void,
+        notification: *const c_void,
+        refcon: *mut c_void,
+    ) -> i32;
+    fn AXObserverRemoveNotification(
+        observer: *const c_void,
+        element: *const c_void,
+        notification: *const c_void,
+    ) -> i32;
+    fn AXObserverGetRunLoopSource(observer: *const c_void) -> *const c_void;
+    fn AXUIElementCreateApplication(pid: i32) -> *const c_void;
+    fn AXUIElementCopyAttributeValue(
+        element: *const c_void,
+        attribute: *const c_void,
+        value: *mut *const c_void,
+    ) -> i32;
+    fn AXUIElementPerformAction(element: *const c_void, action: *const c_void) -> i32;
+    fn AXValueGetValue(value: *const c_void, value_type: u32, value_ptr: *mut c_void) -> bool;
+    // Private but long-stable; the only way to map an AX window to its CG window number.
+    fn _AXUIElementGetWindow(element: *const c_void, window_id: *mut u32) -> i32;
+}
+
+#[link(name = "CoreFoundation", kind = "framework")]
+extern "C" {
+    static kCFRunLoopCommonModes: *const c_void;
+    fn CFRunLoopGetMain() -> *const c_void;
+    fn CFRunLoopAddSource(run_loop: *const c_void, source: *const c_void, mode: *const c_void);
+    fn CFRunLoopRemoveSource(run_loop: *const c_void, source: *const c_void, mode: *const c_void);
+}
+
+pub struct AxElement(CfType);
+
+impl AxElement {
+    pub fn application(pid: i32) -> Option<Self> {
+        unsafe { CfType::from_create_rule(AXUIElementCreateApplication(pid)) }.map(Self)
+    }
+
+    pub fn string_attribute(&self, name: &str) -> Option<String> {
+        self.copy_attribute(name)?.to_string_value()
+    }
+
+    pub fn bool_attribute(&self, name: &str) -> Option<bool> {
+        self.copy_attribute(name)?.to_bool()
+    }
+
+    pub fn element_attribute(&self, name: &str) -> Option<AxElement> {
+        self.copy_attribute(name)
+            .map(|value| AxElement::retain(&value))
+    }
+
+    pub fn element_array_attribute(&self, name: &str) -> Vec<AxElement> {
+        self.copy_attribute(name)
+            .and_then(|value| value.to_array())
+            .map(|array| array.values().iter().map(AxElement::retain).collect())
+            .unwrap_or_default()
+    }
+
+    pub fn perform_action(&self, action: &str) -> Result<(), AxError> {
+        let action = CfString::new(action).ok_or(AxError(K_AX_ERROR_FAILURE))?;
+        AxError::check(unsafe { AXUIElementPerformAction(self.0.as_ptr(), action.as_ptr()) })
+    }
+
+    /// The element's `AXPosition` and `AXSize`. AX reports screen coordinates
+    /// with the origin at the primary display's top-left, the same space as CG
+    /// window bounds.
+    pub fn frame(&self) -> Option<WindowBounds> {
+        let (x, y) = self.pair_attribute("AXPosition", K_AX_VALUE_CG_POINT_TYPE)?;
+        let (width, height) = self.pair_attribute("AXSize", K_AX_VALUE_CG_SIZE_TYPE)?;
+        Some(WindowBounds::new(x, y, width, height))
+    }
+
+    /// A sheet, or a window with a dialog subrole.
+    pub fn is_dialog(&self) -> bool {
+        self.string_attribute("AXRole").as_deref() == Some("AXSheet")
+            || matches!(
+                self.string_attribute("AXSubrole").as_deref(),
+                Some("AXDialog" | "AXSystemDialog")
+            )
+    }
+
+    pub fn window_number(&self) -> Option<i64> {
+        let mut window_id = 0u32;
+        let error = unsafe { _AXUIElementGetWindow(self.0.as_ptr(), &mut window_id) };
+        (error == K_AX_ERROR_SUCCESS).then_some(i64::from(window_id))
+    }
+
+    // CGPoint and CGSize are both a pair of CGFloats.
+    fn pair_attribute(&self, name: &str, value_type: u32) -> Option<(f64, f64)> {
+        let value = self.copy_attribute(name)?;
+        let mut pair = [0.0f64; 2];
+        let ok = unsafe { AXValueGetValue(value.as_ptr(), value_type, pair.as_mut_ptr().cast()) };
+        ok.then_some((pair[0], pair[1]))
+    }
+
+    fn copy_attribute(&self, name: &str) -> Option<CfType> {
+        let attribute = CfString::new(name)?;
+        let mut value = ptr::null();
+        let error = unsafe {
+            AXUIElementCopyAttributeValue(self.0.as_ptr(), attribute.as_ptr(), &mut value)
+        };
+        if error != K_AX_ERROR_SUCCESS {
+            return None;
+        }
+        unsafe { CfType::from_create_rule(value) }
+    }
+
+    // Another reference to the element in `value`.
+    fn retain(value: &CfType) -> AxElement {
+        AxElement(value.to_cf_type())
+    }
+}
+
+/// A change to an observed window, from the AX notification of the same name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AxWindowEvent {
+    Moved,
+    Resized,
+    TitleChanged,
+    /// The window closed. No further events follow.
+    Destroyed,
+}
+
+impl AxWindowEvent {
+    const ALL: [AxWindowEvent; 4] = [
+        AxWindowEvent::Moved,
+        AxWindowEvent::Resized,
+        AxWindowEvent::TitleChanged,
+        AxWindowEvent::Destroyed,
+    ];
+
+    fn notification(self) -> &'static str {
+        match self {
+            AxWindowEvent::Moved => "AXMoved",
+            AxWindowEvent::Resized => "AXResized",
+            AxWindowEvent::TitleChanged => "AXTitleChanged",
+            AxWindowEvent::Destroyed => "AXUIElementDestroyed",
+        }
+    }
+
+    fn from_notification(notification: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|event| event.notification() == notification)
+    }
+}
+
+/// Delivers `AxWindowEvent`s for one window until dropped. Main thread only:
+/// the observer runs on the main run loop.
+pub struct WindowObserver {
+    observer: CfType,
+    window: AxElement,
+    on_event: *mut Box<dyn Fn(AxWindowEvent)>,
+}
+
+/// Calls `on_event` on the main run loop as soon as the window moves, resizes,
+/// retitles or closes, with no polling. Needs the accessibility permission;
+/// fails with `kAXErrorInvalidUIElement` when the window can't be found.
+pub fn observe_window(
+    pid: i32,
+    window_number: i64,
+    on_event: impl Fn(AxWindowEvent) + 'static,
+) -> Result<WindowObserver, AxError> {
+    let window =
+        find_window_element(pid, window_number).ok_or(AxError(K_AX_ERROR_INVALID_UI_ELEMENT))?;
+
+    let mut observer = ptr::null();
+    AxError::check(unsafe { AXObserverCreate(pid, window_event_received, &mut observer) })?;
+    let observer =
+        unsafe { CfType::from_create_rule(observer) }.ok_or(AxError(K_AX_ERROR_FAILURE))?;
+
+    let on_event: *mut Box<dyn Fn(AxWindowEvent)> = Box::into_raw(Box::new(Box::new(on_event)));
+    // From here on, dropping `watch` unregisters and frees everything.
+    let watch = WindowObserver {
+        observer,
+        window,
+        on_event,
+    };
+
+    for event in AxWindowEvent::ALL {
+        let error = watch.with_notification(event, |notification| unsafe {
+            AXObserverAddNotification(
+                watch.observer.as_ptr(),
+                watch.window.0.as_ptr(),
+                notification,
+                on_event.cast(),
+            )
+        });
+        // Some windows can't be retitled; the other notifications still work.
+        if error != K_AX_ERROR_NOTIFICATION_UNSUPPORTED {
+            AxError::check(error)?;
+        }
+    }
+
+    unsafe {
+        CFRunLoopAddSource(
+            CFRunLoopGetMain(),
+            AXObserverGetRunLoopSource(watch.observer.as_ptr()),
+            kCFRunLoopCommonModes,
+        );
+    }
+    Ok(watch)
+}
+
+impl WindowObserver {
+    fn with_notification(&self, event: AxWindowEvent, f: impl FnOnce(*const c_void) -> i32) -> i32 {
+        match CfString::new(event.notification()) {
+            Some(notification) => f(notification.as_ptr()),
+            None => K_AX_ERROR_FAILURE,
+        }
+    }
+}
+
+impl Drop for WindowObserver {
+    fn drop(&mut self) {
+        unsafe {
+            CFRunLoopRemoveSource(
+                CFRunLoopGetMain(),
+                AXObserverGetRunLoopSource(self.observer.as_ptr()),
+                kCFRunLoopCommonModes,
+            );
+        }
+        for event in AxWindowEvent::ALL {
+            // Fails harmlessly once the window is gone.
+            self.with_notification(event, |notification| unsafe {
+                AXObserverRemoveNotification(
+                    self.observer.as_ptr(),
+                    self.window.0.as_ptr(),
+                    notification,
+                )
+            });
+        }
+        // The observer itself is released after this, with the fields.
+        unsafe { drop(Box::from_raw(self.on_event)) };
+    }
+}
+
+extern "C" fn window_event_received(
+    _observer: *const c_void,
+    _element: *const c_void,
+    notification: *const c_void,
+    refcon: *mut c_void,
+) {
+    if refcon.is_null() {
+        return;
+    }
+    let Some(event) =
+        cf_string_to_string(notification).and_then(|name| AxWindowEvent::from_notification(&name))
+    else {
+        return;
+    };
+    let on_event = unsafe { &*(refcon as *const Box<dyn Fn(AxWindowEvent)>) };
+    on_event(event);
+}
+
+/// Looks through the app's `AXWindows` and then the sheets attached to them,
+/// which AX lists among their window's children rather than as windows.
+pub fn find_window_element(pid: i32, window_number: i64) -> Option<AxElement> {
+    let windows = AxElement::application(pid)?.element_array_attribute("AXWindows");
+    let sheets = windows.iter().flat_map(sheets_of).collect::<Vec<_>>();
+    windows
+        .into_iter()
+        .chain(sheets)
+        .find(|window| window.window_number() == Some(window_number))
+}
+
+/// Every sheet and dialog `pid` has open, whatever their titles: dialog
+/// windows from `AXWindows` plus the sheets attached to any window.
+pub fn dialog_elements(pid: i32) -> Vec<AxElement> {
+    let Some(app) = AxElement::application(pid) else {
+        return Vec::new();
+    };
+    let mut dialogs = Vec::new();
+    for window in app.element_array_attribute("AXWindows") {
+        dialogs.extend(sheets_of(&window));
+        if window.is_dialog() {
+            dialogs.push(window);
+        }
+    }
+    dialogs
+}
+
+/// The `AXIdentifier` of the sheet or dialog with this window number, e.g.
+/// "open-panel" for an `NSOpenPanel` or "save-panel" for an `NSSavePanel`;
+/// AppKit sets these on its standard panels in every language.
+pub fn dialog_identifier(pid: i32, window_number: i64) -> Option<String> {
+    dialog_elements(pid)
+        .into_iter()
+        .find(|dialog| dialog.window_number() == Some(window_number))?
+        .string_attribute("AXIdentifier")
+}
+
+fn sheets_of(window: &AxElement) -> Vec<AxElement> {
+    window
+        .element_array_attribute("AXChildren")
+        .into_iter()
+        .filter(|child| child.string_attribute("AXRole").as_deref() == Some("AXSheet"))
+        .collect()
+}
+
+/// Whether the window is minimized to the Dock (`AXMinimized`). `None` when
+/// AX can't find it.
+pub fn is_window_minimized(pid: i32, window_number: i64) -> Option<bool> {
+    find_window_element(pid, window_number)?.bool_attribute("AXMinimized")
+}
+
+/// The window a sheet is attached to: the sheet's `AXParent`, or the parent's
+/// own `AXWindow` when the parent is some element inside a window. `None` for
+/// top-level windows, whose parent is the application.
+pub fn parent_window_number(pid: i32, window_number: i64) -> Option<i64> {
+    attached_window_number(&find_window_element(pid, window_number)?)
+}
+
+// The reads `attached_window_number` makes, so it can walk a fake hierarchy.
+trait AxNode: Sized {
+    fn role(&self) -> Option<String>;
+    fn element(&self, name: &str) -> Option<Self>;
+    fn number(&self) -> Option<i64>;
+}
+
+impl AxNode for AxElement {
+    fn role(&self) -> Option<String> {
+        self.string_attribute("AXRole")
+    }
+
+    fn element(&self, name: &str) -> Option<Self> {
+        self.element_attribute(name)
+    }
+
+    fn number(&self) -> Option<i64> {
+        self.window_number()
+    }
+}
+
+fn attached_window_number<N: AxNode>(window: &N) -> Option<i64> {
+    let parent = window.element("AXParent")?;
+    match parent.role().as_deref() {
+        Some("AXWindow") => parent.number(),
+        Some("AXApplication") | None => None,
+        Some(_) => parent.element("AXWindow")?.number(),
+    }
+}
+
+/// One segment of an element path: the `index`th child (zero-based) whose
+/// `AXRole` is `role`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AxPathStep {
+    pub role: String,
+    pub index: usize,
+}
+
+/// Parses an element path such as `AXWindow/AXToolbar/AXButton[2]`. Each
+/// `/`-separated segment names a role, optionally followed by a zero-based
+/// index among the siblings with that role (default 0). The first segment is
+/// matched against the application element's children, so paths usually start
+/// with `AXWindow` (or `AXWindow[n]` for the nth window, front to back).
+pub fn parse_element_path(path: &str) -> Result<Vec<AxPathStep>, String> {
+    path.split('/')
+        .map(|segment| {
+            let segment = segment.trim();
+            let (role, index) = match segment.strip_suffix(']') {
+                Some(rest) => {
+                    let (role, index) = rest
+                        .split_once('[')
+                        .ok_or_else(|| format!("Malformed path segment '{}'", segment))?;
+                    let index = index
+                        .parse()
+                        .map_err(|_| format!("Invalid index in path segment '{}'", segment))?;
+                    (role, index)
+                }
+                None => (segment, 0),
+            };
+
+            if role.is_empty() {
+                return Err(format!("Empty role in element path '{}'", path));
+            }
+            Ok(AxPathStep {
+                role: role.to_string(),
+                index,
+            })
+        })
+        .collect()
+}
+
+pub fn find_element_by_path(pid: i32, path: &[AxPathStep]) -> Option<AxElement> {
+    let mut element = AxElement::application(pid)?;
+    for step in path {
+        element = element
+            .element_array_attribute("AXChildren")
+            .into_iter()
+            .filter(|child| child.string_attribute("AXRole").as_deref() == Some(step.role.as_str()))
+            .nth(step.index)?;
+    }
+    Some(element)
+}
+
+pub fn is_dialog_window(pid: i32, window_number: i64) -> bool {
+    find_window_element(pid, window_number).is_some_and(|window| window.is_dialog())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // An element of a fake AX hierarchy, with its `AXParent` and `AXWindow`.
+    #[derive(Clone, Default)]
+    struct Node {
+        role: Option<&'static str>,
+        number: Option<i64>,
+        parent: Option<Box<Node>>,
+        window: Option<Box<Node>>,
+    }
+
+    impl AxNode for Node {
+        fn role(&self) -> Option<String> {
+            self.role.map(str::to_string)
+        }
+
+        fn element(&self, name: &str) -> Option<Self> {
+            match name {
+                "AXParent" => self.parent.as_deref().cloned(),
+                "AXWindow" => self.window.as_deref().cloned(),
+                _ => None,
+            }
+        }
+
+        fn number(&self) -> Option<i64> {
+            self.number
+        }
+    }
+
+    fn node(role: &'static str, number: Option<i64>) -> Node {
+        Node {
+            role: Some(role),
+            number,
+            ..Node::default()
+        }
+    }
+
+    fn child_of(parent: Node, role: &'static str, number: i64) -> Node {
+        Node {
+            parent: Some(Box::new(parent)),
+            ..node(role, Some(number))
+        }
+    }
+
+    #[test]
+    fn sheets_report_the_window_they_are_attached_to() {
+        let document = child_of(node("AXApplication", None), "AXWindow", 10);
+        let sheet = child_of(document.clone(), "AXSheet", 11);
+
+        assert_eq!(attached_window_number(&sheet), Some(10));
+        assert_eq!(attached_window_number(&document), None);
+    }
+
+    #[test]
+    fn sheets_inside_an_element_use_its_window() {
+        let document = node("AXWindow", Some(12));
+        let group = Node {
+            window: Some(Box::new(document)),
+            ..node("AXGroup", None)
+        };
+        let sheet = child_of(group, "AXSheet", 13);
+        assert_eq!(attached_window_number(&sheet), Some(12));
+
+        let orphan = child_of(node("AXGroup", None), "AXSheet", 14);
+        assert_eq!(attached_window_number(&orphan), None);
+        let unknown_parent = child_of(Node::default(), "AXSheet", 15);
+        assert_eq!(attached_window_number(&unknown_parent), None);
+        assert_eq!(attached_window_number(&node("AXSheet", Some(16))), None);
+    }
+
+    fn step(role: &str, index: usize) -> AxPathStep {
+        AxPathStep {
+            role: role.to_string(),
+            index,
+        }
+    }
+
+    #[test]
+    fn element_paths_parse_roles_and_indices() {
+        assert_eq!(
+            parse_element_path("AXWindow/AXToolbar/AXButton[2]"),
+            Ok(vec![
+                step("AXWindow", 0),
+                step("AXToolbar", 0),
+                step("AXButton", 2)
+            ])
+        );
+        assert_eq!(
+            parse_element_path(" AXWindow[1] / AXTextArea "),
+            Ok(vec![step("AXWindow", 1), step("AXTextArea", 0)])
+        );
+    }
+
+    #[test]
+    fn malformed_element_paths_are_rejected() {
+        for path in ["", "AXWindow//AXButton", "AXButton[x]", "AXButton]", "[1]"] {
+            assert!(parse_element_path(path).is_err(), "{:?}", path);
+        }
+    }
+}
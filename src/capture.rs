@@ -0,0 +1,300 @@
+use std::fmt;
+use std::io::{self, Cursor};
+use std::os::raw::c_void;
+use std::path::Path;
+
+use image::{ImageFormat, RgbaImage};
+
+use crate::cf::CfType;
+use crate::window_search::{WindowInfo, WindowNumber};
+
+const K_CG_WINDOW_LIST_OPTION_INCLUDING_WINDOW: u32 = 8;
+const K_CG_WINDOW_IMAGE_BOUNDS_IGNORE_FRAMING: u32 = 1;
+const K_CG_WINDOW_IMAGE_BEST_RESOLUTION: u32 = 8;
+const K_CG_BITMAP_ALPHA_INFO_MASK: u32 = 0x1F;
+const K_CG_BITMAP_BYTE_ORDER_MASK: u32 = 0x7000;
+const K_CG_BITMAP_BYTE_ORDER_32_LITTLE: u32 = 2 << 12;
+// `CGImageAlphaInfo` values.
+const K_CG_IMAGE_ALPHA_NONE: u32 = 0;
+const K_CG_IMAGE_ALPHA_PREMULTIPLIED_LAST: u32 = 1;
+const K_CG_IMAGE_ALPHA_PREMULTIPLIED_FIRST: u32 = 2;
+const K_CG_IMAGE_ALPHA_FIRST: u32 = 4;
+const K_CG_IMAGE_ALPHA_NONE_SKIP_LAST: u32 = 5;
+const K_CG_IMAGE_ALPHA_NONE_SKIP_FIRST: u32 = 6;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct CGRect {
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+}
+
+// `CGRectNull`: with `IncludingWindow`, captures exactly the window's bounds.
+const CG_RECT_NULL: CGRect = CGRect {
+    x: f64::INFINITY,
+    y: f64::INFINITY,
+    width: 0.0,
+    height: 0.0,
+};
+
+#[link(name = "CoreGraphics", kind = "framework")]
+extern "C" {
+    fn CGWindowListCreateImage(
+        screen_bounds: CGRect,
+        list_option: u32,
+        window_id: u32,
+        image_option: u32,
+    ) -> *const c_void;
+    fn CGImageGetWidth(image: *const c_void) -> usize;
+    fn CGImageGetHeight(image: *const c_void) -> usize;
+    fn CGImageGetBitsPerPixel(image: *const c_void) -> usize;
+    fn CGImageGetBytesPerRow(image: *const c_void) -> usize;
+    fn CGImageGetBitmapInfo(image: *const c_void) -> u32;
+    fn CGImageGetDataProvider(image: *const c_void) -> *const c_void;
+    fn CGDataProviderCopyData(provider: *const c_void) -> *const c_void;
+    fn CFDataGetBytePtr(data: *const c_void) -> *const u8;
+    fn CFDataGetLength(data: *const c_void) -> isize;
+}
+
+#[derive(Debug)]
+pub enum CaptureError {
+    /// CG returned no image: the window is gone, or screen recording
+    /// permission is missing.
+    ImageUnavailable(WindowNumber),
+    UnsupportedFormat {
+        bits_per_pixel: usize,
+    },
+    /// `rgba` doesn't hold `width_px * height_px` pixels.
+    SizeMismatch,
+    Encode(String),
+    Io(io::Error),
+}
+
+impl fmt::Display for CaptureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CaptureError::ImageUnavailable(window_number) => {
+                write!(f, "No image available for window {}", window_number)
+            }
+            CaptureError::UnsupportedFormat { bits_per_pixel } => {
+                write!(f, "Unsupported {}-bit pixel format", bits_per_pixel)
+            }
+            CaptureError::SizeMismatch => write!(f, "Pixel data does not match image size"),
+            CaptureError::Encode(message) => write!(f, "PNG encoding failed: {}", message),
+            CaptureError::Io(e) => write!(f, "Could not write image: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for CaptureError {}
+
+/// An owned window capture: straight (not premultiplied) RGBA rows, top to
+/// bottom, `width_px * 4` bytes each.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaptureResult {
+    pub width_px: u32,
+    pub height_px: u32,
+    /// Device pixels per point, i.e. the backing scale factor of the display
+    /// the window was captured on.
+    pub scale: f64,
+    pub rgba: Vec<u8>,
+}
+
+impl CaptureResult {
+    pub fn to_png(&self) -> Result<Vec<u8>, CaptureError> {
+        let image = RgbaImage::from_raw(self.width_px, self.height_px, self.rgba.clone())
+            .ok_or(CaptureError::SizeMismatch)?;
+        let mut png = Cursor::new(Vec::new());
+        image
+            .write_to(&mut png, ImageFormat::Png)
+            .map_err(|e| CaptureError::Encode(e.to_string()))?;
+        Ok(png.into_inner())
+    }
+
+    pub fn save_png(&self, path: impl AsRef<Path>) -> Result<(), CaptureError> {
+        std::fs::write(path, self.to_png()?).map_err(CaptureError::Io)
+    }
+}
+
+impl WindowInfo {
+    /// The window's current contents as PNG bytes; see `capture_window_image`.
+    pub fn capture_image(&self) -> Result<Vec<u8>, CaptureError> {
+        capture_window_image(self)?.to_png()
+    }
+}
+
+/// Captures `window` alone, at the display's full resolution and without its
+/// shadow. Needs the screen recording permission.
+pub fn capture_window_image(window: &WindowInfo) -> Result<CaptureResult, CaptureError> {
+    let image = unsafe {
+        CfType::from_create_rule(CGWindowListCreateImage(
+            CG_RECT_NULL,
+            K_CG_WINDOW_LIST_OPTION_INCLUDING_WINDOW,
+            window.window_number.0 as u32,
+            K_CG_WINDOW_IMAGE_BOUNDS_IGNORE_FRAMING | K_CG_WINDOW_IMAGE_BEST_RESOLUTION,
+        ))
+    }
+    .ok_or(CaptureError::ImageUnavailable(window.window_number))?;
+
+    let mut capture = unsafe { copy_image(&image, window.window_number) }?;
+    if window.bounds.width > 0.0 {
+        capture.scale = capture.width_px as f64 / window.bounds.width;
+    }
+    Ok(capture)
+}
+
+/// Copies the `CGImage`'s pixels out as straight RGBA.
+unsafe fn copy_image(
+    image: &CfType,
+    window_number: WindowNumber,
+) -> Result<CaptureResult, CaptureError> {
+    let image = image.as_ptr();
+    let width = CGImageGetWidth(image);
+    let height = CGImageGetHeight(image);
+    let bits_per_pixel = CGImageGetBitsPerPixel(image);
+    if bits_per_pixel != 32 || width == 0 || height == 0 {
+        return Err(CaptureError::UnsupportedFormat { bits_per_pixel });
+    }
+    let bytes_per_row = CGImageGetBytesPerRow(image);
+    let bitmap_info = CGImageGetBitmapInfo(image);
+
+    let data = CfType::from_create_rule(CGDataProviderCopyData(CGImageGetDataProvider(image)))
+        .ok_or(CaptureError::ImageUnavailable(window_number))?;
+    let length = CFDataGetLength(data.as_ptr()).max(0) as usize;
+    let bytes = std::slice::from_raw_parts(CFDataGetBytePtr(data.as_ptr()), length);
+    if bytes_per_row < width * 4 || length < bytes_per_row * height {
+        return Err(CaptureError::SizeMismatch);
+    }
+
+    Ok(CaptureResult {
+        width_px: width as u32,
+        height_px: height as u32,
+        scale: 1.0,
+        rgba: to_rgba(bytes, width, height, bytes_per_row, bitmap_info),
+    })
+}
+
+/// Reorders 32-bit pixels described by `bitmap_info` into straight RGBA,
+/// dropping any row padding.
+fn to_rgba(
+    bytes: &[u8],
+    width: usize,
+    height: usize,
+    bytes_per_row: usize,
+    bitmap_info: u32,
+) -> Vec<u8> {
+    let alpha_info = bitmap_info & K_CG_BITMAP_ALPHA_INFO_MASK;
+    let little_endian =
+        bitmap_info & K_CG_BITMAP_BYTE_ORDER_MASK == K_CG_BITMAP_BYTE_ORDER_32_LITTLE;
+    let alpha_first = matches!(
+        alpha_info,
+        K_CG_IMAGE_ALPHA_PREMULTIPLIED_FIRST
+            | K_CG_IMAGE_ALPHA_FIRST
+            | K_CG_IMAGE_ALPHA_NONE_SKIP_FIRST
+    );
+    let premultiplied = matches!(
+        alpha_info,
+        K_CG_IMAGE_ALPHA_PREMULTIPLIED_FIRST | K_CG_IMAGE_ALPHA_PREMULTIPLIED_LAST
+    );
+    let has_alpha = !matches!(
+        alpha_info,
+        K_CG_IMAGE_ALPHA_NONE | K_CG_IMAGE_ALPHA_NONE_SKIP_LAST | K_CG_IMAGE_ALPHA_NONE_SKIP_FIRST
+    );
+
+    let mut rgba = Vec::with_capacity(width * height * 4);
+    for row in bytes.chunks(bytes_per_row).take(height) {
+        for pixel in row[..width * 4].chunks_exact(4) {
+            // The pixel as a big-endian word: ARGB or RGBA.
+            let [a, b, c, d] = if little_endian {
+                [pixel[3], pixel[2], pixel[1], pixel[0]]
+            } else {
+                [pixel[0], pixel[1], pixel[2], pixel[3]]
+            };
+            let (red, green, blue, alpha) = if alpha_first {
+                (b, c, d, a)
+            } else {
+                (a, b, c, d)
+            };
+            let alpha = if has_alpha { alpha } else { u8::MAX };
+
+            if premultiplied && alpha > 0 && alpha < u8::MAX {
+                let unpremultiply = |component: u8| {
+                    ((component as u32 * 255 + alpha as u32 / 2) / alpha as u32).min(255) as u8
+                };
+                rgba.extend([
+                    unpremultiply(red),
+                    unpremultiply(green),
+                    unpremultiply(blue),
+                    alpha,
+                ]);
+            } else {
+                rgba.extend([red, green, blue, alpha]);
+            }
+        }
+    }
+    rgba
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn png_round_trips_with_the_same_dimensions() {
+        let capture = CaptureResult {
+            width_px: 3,
+            height_px: 2,
+            scale: 2.0,
+            rgba: (0..24).map(|byte| byte * 10).collect(),
+        };
+
+        let png = capture.to_png().unwrap();
+        let decoded = image::load_from_memory_with_format(&png, ImageFormat::Png)
+            .unwrap()
+            .to_rgba8();
+
+        assert_eq!(decoded.dimensions(), (3, 2));
+        assert_eq!(decoded.into_raw(), capture.rgba);
+    }
+
+    #[test]
+    fn png_encoding_rejects_a_size_mismatch() {
+        let capture = CaptureResult {
+            width_px: 2,
+            height_px: 2,
+            scale: 1.0,
+            rgba: vec![0; 12],
+        };
+        assert!(matches!(capture.to_png(), Err(CaptureError::SizeMismatch)));
+    }
+
+    #[test]
+    fn bgra_premultiplied_rows_become_straight_rgba() {
+        // Two rows of two little-endian ARGB pixels, i.e. BGRA in memory,
+        // each followed by four bytes of padding.
+        let bytes = [
+            [0, 0, 255, 255, 25, 50, 100, 128, 0xEE, 0xEE, 0xEE, 0xEE],
+            [255, 255, 255, 255, 0, 0, 0, 0, 0xEE, 0xEE, 0xEE, 0xEE],
+        ]
+        .concat();
+        let bitmap_info = K_CG_BITMAP_BYTE_ORDER_32_LITTLE | K_CG_IMAGE_ALPHA_PREMULTIPLIED_FIRST;
+
+        let rgba = to_rgba(&bytes, 2, 2, 12, bitmap_info);
+
+        assert_eq!(
+            rgba,
+            [255, 0, 0, 255, 199, 100, 50, 128, 255, 255, 255, 255, 0, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn skipped_alpha_reads_as_opaque() {
+        let bytes = [10, 20, 30, 0, 40, 50, 60, 7];
+
+        let rgba = to_rgba(&bytes, 2, 1, 8, K_CG_IMAGE_ALPHA_NONE_SKIP_LAST);
+
+        assert_eq!(rgba, [10, 20, 30, 255, 40, 50, 60, 255]);
+    }
+}
@@ -0,0 +1,371 @@
+//! Owned CoreFoundation objects. Each wrapper holds one reference and releases
+//! it on drop, so early returns can't leak; objects borrowed out of containers
+//! are retained on the way out. Only immutable objects are wrapped, which CF
+//! allows reading and releasing from any thread.
+
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_void};
+use std::ptr;
+
+const K_CF_STRING_ENCODING_UTF8: u32 = 0x08000100;
+const K_CF_NUMBER_DOUBLE_TYPE: i32 = 13;
+
+#[repr(C)]
+struct CFDictionaryCallBacks {
+    _private: [u8; 0],
+}
+
+#[link(name = "CoreFoundation", kind = "framework")]
+extern "C" {
+    static kCFBooleanTrue: *const c_void;
+    static kCFBooleanFalse: *const c_void;
+    static kCFTypeDictionaryKeyCallBacks: CFDictionaryCallBacks;
+    static kCFTypeDictionaryValueCallBacks: CFDictionaryCallBacks;
+    fn CFRetain(cf: *const c_void) -> *const c_void;
+    fn CFRelease(cf: *const c_void);
+    fn CFGetTypeID(cf: *const c_void) -> usize;
+    fn CFCopyTypeIDDescription(type_id: usize) -> *const c_void;
+    fn CFStringGetTypeID() -> usize;
+    fn CFNumberGetTypeID() -> usize;
+    fn CFBooleanGetTypeID() -> usize;
+    fn CFArrayGetTypeID() -> usize;
+    fn CFDictionaryGetTypeID() -> usize;
+    fn CFStringCreateWithCString(
+        allocator: *const c_void,
+        cstr: *const c_char,
+        encoding: u32,
+    ) -> *const c_void;
+    fn CFStringGetLength(string: *const c_void) -> isize;
+    fn CFStringGetCString(
+        string: *const c_void,
+        buffer: *mut c_char,
+        buffer_size: isize,
+        encoding: u32,
+    ) -> bool;
+    fn CFNumberGetValue(number: *const c_void, number_type: i32, value_ptr: *mut c_void) -> bool;
+    fn CFBooleanGetValue(boolean: *const c_void) -> bool;
+    fn CFArrayGetCount(array: *const c_void) -> isize;
+    fn CFArrayGetValueAtIndex(array: *const c_void, idx: isize) -> *const c_void;
+    fn CFDictionaryCreate(
+        allocator: *const c_void,
+        keys: *const *const c_void,
+        values: *const *const c_void,
+        count: isize,
+        key_callbacks: *const CFDictionaryCallBacks,
+        value_callbacks: *const CFDictionaryCallBacks,
+    ) -> *const c_void;
+    fn CFDictionaryGetValue(dict: *const c_void, key: *const c_void) -> *const c_void;
+    fn CFDictionaryGetCount(dict: *const c_void) -> isize;
+    fn CFDictionaryGetKeysAndValues(
+        dict: *const c_void,
+        keys: *mut *const c_void,
+        values: *mut *const c_void,
+    );
+}
+
+#[cfg(test)]
+#[repr(C)]
+struct CFArrayCallBacks {
+    _private: [u8; 0],
+}
+
+// Constructors only tests need, for building window lists without CG.
+#[cfg(test)]
+#[link(name = "CoreFoundation", kind = "framework")]
+extern "C" {
+    static kCFTypeArrayCallBacks: CFArrayCallBacks;
+    fn CFNumberCreate(
+        allocator: *const c_void,
+        number_type: i32,
+        value_ptr: *const c_void,
+    ) -> *const c_void;
+    fn CFArrayCreate(
+        allocator: *const c_void,
+        values: *const *const c_void,
+        count: isize,
+        callbacks: *const CFArrayCallBacks,
+    ) -> *const c_void;
+}
+
+macro_rules! cf_wrapper {
+    ($(#[$meta:meta])* $name:ident, $type_id:expr) => {
+        $(#[$meta])*
+        #[derive(Debug)]
+        pub(crate) struct $name(*const c_void);
+
+        unsafe impl Send for $name {}
+        unsafe impl Sync for $name {}
+
+        // Not every type needs every constructor.
+        #[allow(dead_code)]
+        impl $name {
+            /// Takes over the reference returned by a CF `Create` or `Copy`
+            /// function. `None` for null or an object of another type.
+            ///
+            /// # Safety
+            /// `object` must be null or a CF object the caller owns.
+            pub(crate) unsafe fn from_create_rule(object: *const c_void) -> Option<Self> {
+                if object.is_null() {
+                    return None;
+                }
+                if !Self::is_instance(object) {
+                    CFRelease(object);
+                    return None;
+                }
+                Some(Self(object))
+            }
+
+            /// Retains an object obtained from a CF `Get` function.
+            ///
+            /// # Safety
+            /// `object` must be null or a live CF object.
+            pub(crate) unsafe fn from_get_rule(object: *const c_void) -> Option<Self> {
+                if object.is_null() || !Self::is_instance(object) {
+                    return None;
+                }
+                Some(Self(CFRetain(object)))
+            }
+
+            pub(crate) fn as_ptr(&self) -> *const c_void {
+                self.0
+            }
+
+            /// Another reference to the same object, untyped.
+            pub(crate) fn to_cf_type(&self) -> CfType {
+                unsafe { CfType::from_get_rule(self.0) }.expect("CF objects are never null")
+            }
+
+            unsafe fn is_instance(object: *const c_void) -> bool {
+                let type_id: Option<unsafe extern "C" fn() -> usize> = $type_id;
+                type_id.map_or(true, |type_id| CFGetTypeID(object) == type_id())
+            }
+        }
+
+        impl Drop for $name {
+            fn drop(&mut self) {
+                unsafe { CFRelease(self.0) };
+            }
+        }
+    };
+}
+
+cf_wrapper!(
+    /// Any CF object, for values whose type is only known at runtime.
+    CfType,
+    None
+);
+cf_wrapper!(CfString, Some(CFStringGetTypeID));
+cf_wrapper!(CfNumber, Some(CFNumberGetTypeID));
+cf_wrapper!(CfArray, Some(CFArrayGetTypeID));
+cf_wrapper!(CfDictionary, Some(CFDictionaryGetTypeID));
+
+impl CfType {
+    /// `kCFBooleanTrue` or `kCFBooleanFalse`.
+    pub(crate) fn boolean(value: bool) -> Self {
+        let boolean = unsafe {
+            if value {
+                kCFBooleanTrue
+            } else {
+                kCFBooleanFalse
+            }
+        };
+        unsafe { Self::from_get_rule(boolean) }.expect("CF boolean constants are never null")
+    }
+
+    pub(crate) fn to_string_value(&self) -> Option<String> {
+        unsafe { CfString::from_get_rule(self.0) }.and_then(|string| string.to_string_lossy())
+    }
+
+    pub(crate) fn to_f64(&self) -> Option<f64> {
+        unsafe { CfNumber::from_get_rule(self.0) }.and_then(|number| number.to_f64())
+    }
+
+    pub(crate) fn to_bool(&self) -> Option<bool> {
+        let is_boolean = unsafe { CFGetTypeID(self.0) == CFBooleanGetTypeID() };
+        is_boolean.then(|| unsafe { CFBooleanGetValue(self.0) })
+    }
+
+    pub(crate) fn to_dictionary(&self) -> Option<CfDictionary> {
+        unsafe { CfDictionary::from_get_rule(self.0) }
+    }
+
+    #[cfg(feature = "accessibility")]
+    pub(crate) fn to_array(&self) -> Option<CfArray> {
+        unsafe { CfArray::from_get_rule(self.0) }
+    }
+
+    /// CF's name for the object's type, e.g. "CFArray".
+    pub(crate) fn type_name(&self) -> String {
+        unsafe { CfString::from_create_rule(CFCopyTypeIDDescription(CFGetTypeID(self.0))) }
+            .and_then(|name| name.to_string_lossy())
+            .unwrap_or_else(|| "unknown".into())
+    }
+}
+
+impl CfString {
+    pub(crate) fn new(string: &str) -> Option<Self> {
+        let string = CString::new(string).ok()?;
+        unsafe {
+            Self::from_create_rule(CFStringCreateWithCString(
+                ptr::null(),
+                string.as_ptr(),
+                K_CF_STRING_ENCODING_UTF8,
+            ))
+        }
+    }
+
+    /// The string as UTF-8; `None` only if CF can't convert it.
+    pub(crate) fn to_string_lossy(&self) -> Option<String> {
+        cf_string_to_string(self.0)
+    }
+}
+
+impl CfNumber {
+    pub(crate) fn to_f64(&self) -> Option<f64> {
+        let mut value: f64 = 0.0;
+        let success = unsafe {
+            CFNumberGetValue(
+                self.0,
+                K_CF_NUMBER_DOUBLE_TYPE,
+                &mut value as *mut f64 as *mut c_void,
+            )
+        };
+        success.then_some(value)
+    }
+}
+
+#[cfg(test)]
+impl CfNumber {
+    pub(crate) fn new(value: f64) -> Option<Self> {
+        unsafe {
+            Self::from_create_rule(CFNumberCreate(
+                ptr::null(),
+                K_CF_NUMBER_DOUBLE_TYPE,
+                &value as *const f64 as *const c_void,
+            ))
+        }
+    }
+}
+
+impl CfArray {
+    /// An immutable array holding `values`, which it retains.
+    #[cfg(test)]
+    pub(crate) fn new(values: &[CfDictionary]) -> Option<Self> {
+        let pointers: Vec<_> = values.iter().map(CfDictionary::as_ptr).collect();
+        unsafe {
+            Self::from_create_rule(CFArrayCreate(
+                ptr::null(),
+                pointers.as_ptr(),
+                pointers.len() as isize,
+                &kCFTypeArrayCallBacks,
+            ))
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        unsafe { CFArrayGetCount(self.0) }.max(0) as usize
+    }
+
+    /// The element at `index`, when it is a dictionary.
+    pub(crate) fn dictionary_at(&self, index: usize) -> Option<CfDictionary> {
+        if index >= self.len() {
+            return None;
+        }
+        unsafe { CfDictionary::from_get_rule(CFArrayGetValueAtIndex(self.0, index as isize)) }
+    }
+
+    /// Every element, whatever its type.
+    #[cfg(feature = "accessibility")]
+    pub(crate) fn values(&self) -> Vec<CfType> {
+        (0..self.len())
+            .filter_map(|index| unsafe {
+                CfType::from_get_rule(CFArrayGetValueAtIndex(self.0, index as isize))
+            })
+            .collect()
+    }
+}
+
+impl CfDictionary {
+    /// An immutable dictionary holding `entries`, which it retains.
+    pub(crate) fn new(entries: &[(&CfString, &CfType)]) -> Option<Self> {
+        let keys: Vec<_> = entries.iter().map(|(key, _)| key.as_ptr()).collect();
+        let values: Vec<_> = entries.iter().map(|(_, value)| value.as_ptr()).collect();
+        unsafe {
+            Self::from_create_rule(CFDictionaryCreate(
+                ptr::null(),
+                keys.as_ptr(),
+                values.as_ptr(),
+                entries.len() as isize,
+                &kCFTypeDictionaryKeyCallBacks,
+                &kCFTypeDictionaryValueCallBacks,
+            ))
+        }
+    }
+
+    pub(crate) fn get(&self, key: &CfString) -> Option<CfType> {
+        unsafe { CfType::from_get_rule(CFDictionaryGetValue(self.0, key.as_ptr())) }
+    }
+
+    pub(crate) fn get_string(&self, key: &CfString) -> Option<String> {
+        self.get(key)?.to_string_value()
+    }
+
+    pub(crate) fn get_number(&self, key: &CfString) -> Option<f64> {
+        self.get(key)?.to_f64()
+    }
+
+    pub(crate) fn get_bool(&self, key: &CfString) -> Option<bool> {
+        self.get(key)?.to_bool()
+    }
+
+    pub(crate) fn get_dictionary(&self, key: &CfString) -> Option<CfDictionary> {
+        self.get(key)?.to_dictionary()
+    }
+
+    /// Every entry in CF's (unspecified) order, with the key as a string
+    /// unless it is some other type.
+    pub(crate) fn entries(&self) -> Vec<(Option<String>, CfType)> {
+        let count = unsafe { CFDictionaryGetCount(self.0) }.max(0) as usize;
+        let mut keys = vec![ptr::null(); count];
+        let mut values = vec![ptr::null(); count];
+        unsafe { CFDictionaryGetKeysAndValues(self.0, keys.as_mut_ptr(), values.as_mut_ptr()) };
+
+        keys.into_iter()
+            .zip(values)
+            .filter_map(|(key, value)| {
+                let key = unsafe { CfType::from_get_rule(key) }?.to_string_value();
+                Some((key, unsafe { CfType::from_get_rule(value) }?))
+            })
+            .collect()
+    }
+}
+
+/// Copies a borrowed CFString, e.g. one handed to a callback, into a `String`.
+/// `None` for null.
+pub(crate) fn cf_string_to_string(string: *const c_void) -> Option<String> {
+    if string.is_null() {
+        return None;
+    }
+
+    unsafe {
+        let length = CFStringGetLength(string);
+        if length == 0 {
+            return Some(String::new());
+        }
+
+        let mut buffer = vec![0u8; (length * 4 + 1) as usize];
+        let success = CFStringGetCString(
+            string,
+            buffer.as_mut_ptr() as *mut c_char,
+            buffer.len() as isize,
+            K_CF_STRING_ENCODING_UTF8,
+        );
+
+        if success {
+            let c_str = CStr::from_ptr(buffer.as_ptr() as *const c_char);
+            Some(c_str.to_string_lossy().into_owned())
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,219 @@
+//! Settings read from a TOML file, by default
+//! `~/.config/relative-panel/config.toml`. Every key is optional:
+//!
+//! ```toml
+//! ignored_apps = ["Dropbox"]      # on top of `system_apps()`
+//! poll_interval_ms = 2000
+//!
+//! [match]
+//! title = "Open"
+//! mode = "starts-with"            # exact, contains, starts-with, regex
+//!
+//! [panel]
+//! placement = "right"             # overlay, right, left, above, below, auto
+//! size = [320, 200]
+//! alpha = 0.8
+//! level = "floating"              # floating, overlay, status, { custom = 12 }
+//! # style = { highlight-border = { thickness = 3.0, color = [1, 0, 0, 1] } }
+//!
+//! [apps."com.apple.TextEdit"]     # app name or bundle id
+//! placement = "below"
+//! ```
+
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::dialog::DialogDetection;
+use crate::manager::PanelManager;
+use crate::panel::{OverlayStyle, PanelConfig, PanelLevel, PanelPlacement};
+use crate::window_search::{system_apps, MatchMode, WindowSearchCriteria};
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "Could not read {}: {}", path.display(), e),
+            ConfigError::Parse(path, e) => write!(f, "Invalid config {}: {}", path.display(), e),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// App names to skip, in addition to `system_apps()`.
+    pub ignored_apps: Vec<String>,
+    pub poll_interval_ms: Option<u64>,
+    #[serde(rename = "match")]
+    pub match_rules: MatchRules,
+    pub panel: PanelStyle,
+    /// Panel styles for particular apps, keyed by app name or bundle id and
+    /// layered over `panel`. When several keys match a window, the first in
+    /// sorted order wins.
+    pub apps: BTreeMap<String, PanelStyle>,
+}
+
+/// Which windows get panels. With no title, app or bundle id this targets
+/// Open dialogs, like `PanelManager::default_search_criteria`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MatchRules {
+    pub title: Option<String>,
+    pub app: Option<String>,
+    pub bundle_id: Option<String>,
+    /// How `title` and `app` compare; exact by default.
+    pub mode: Option<MatchMode>,
+    pub dialog_detection: Option<DialogDetection>,
+}
+
+/// Overrides for `PanelConfig`; unset keys keep the value underneath.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PanelStyle {
+    pub placement: Option<PanelPlacement>,
+    /// `[width, height]`.
+    pub size: Option<[f64; 2]>,
+    pub extra_width: Option<f64>,
+    /// `[dx, dy]`, see `PanelConfig::with_offset`.
+    pub offset: Option<[f64; 2]>,
+    pub alpha: Option<f64>,
+    pub level: Option<PanelLevel>,
+    pub match_target_size: Option<bool>,
+    pub color_by_app: Option<bool>,
+    pub focusable: Option<bool>,
+    pub click_through: Option<bool>,
+    /// See `PanelConfig::attach_to_target`.
+    pub attach_to_target: Option<bool>,
+    pub style: Option<OverlayStyle>,
+    pub show_app_icon: Option<bool>,
+}
+
+impl PanelStyle {
+    pub fn apply(&self, mut config: PanelConfig) -> PanelConfig {
+        if let Some(placement) = self.placement {
+            config = config.with_placement(placement);
+        }
+        if let Some([width, height]) = self.size {
+            config = config.with_panel_size(width, height);
+        }
+        if let Some(extra_width) = self.extra_width {
+            config = config.with_extra_width(extra_width);
+        }
+        if let Some([dx, dy]) = self.offset {
+            config = config.with_offset(dx, dy);
+        }
+        if let Some(alpha) = self.alpha {
+            config = config.with_alpha(alpha);
+        }
+        if let Some(level) = self.level {
+            config = config.with_level(level);
+        }
+        if let Some(enabled) = self.match_target_size {
+            config = config.match_target_size(enabled);
+        }
+        if let Some(enabled) = self.color_by_app {
+            config = config.color_by_app(enabled);
+        }
+        if let Some(enabled) = self.focusable {
+            config = config.focusable(enabled);
+        }
+        if let Some(enabled) = self.click_through {
+            config = config.click_through(enabled);
+        }
+        if let Some(enabled) = self.attach_to_target {
+            config = config.attach_to_target(enabled);
+        }
+        if let Some(style) = self.style {
+            config = config.with_style(style);
+        }
+        if let Some(enabled) = self.show_app_icon {
+            config = config.show_app_icon(enabled);
+        }
+        config
+    }
+}
+
+impl Config {
+    /// `~/.config/relative-panel/config.toml`; `None` without `$HOME`.
+    pub fn default_path() -> Option<PathBuf> {
+        let home = std::env::var_os("HOME")?;
+        Some(PathBuf::from(home).join(".config/relative-panel/config.toml"))
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let text =
+            std::fs::read_to_string(path).map_err(|e| ConfigError::Io(path.to_path_buf(), e))?;
+        toml::from_str(&text).map_err(|e| ConfigError::Parse(path.to_path_buf(), e))
+    }
+
+    /// The file at `default_path`, or the defaults when there is none.
+    pub fn load_default() -> Result<Self, ConfigError> {
+        match Self::default_path() {
+            Some(path) if path.exists() => Self::load(path),
+            _ => Ok(Self::default()),
+        }
+    }
+
+    /// `system_apps()` plus `ignored_apps`, lowercased like the built-in list.
+    pub fn ignored_apps(&self) -> HashSet<String> {
+        let mut apps = system_apps();
+        apps.extend(self.ignored_apps.iter().map(|app| app.to_lowercase()));
+        apps
+    }
+
+    pub fn poll_interval(&self) -> Option<Duration> {
+        self.poll_interval_ms.map(Duration::from_millis)
+    }
+
+    pub fn search_criteria(&self) -> WindowSearchCriteria {
+        let rules = &self.match_rules;
+        let mut criteria =
+            if rules.title.is_none() && rules.app.is_none() && rules.bundle_id.is_none() {
+                PanelManager::default_search_criteria()
+            } else {
+                WindowSearchCriteria::new().require_valid_bounds(true)
+            };
+
+        let mode = rules.mode.unwrap_or(MatchMode::Exact);
+        if let Some(title) = &rules.title {
+            criteria = criteria.with_title_matching(mode, title);
+        }
+        if let Some(app) = &rules.app {
+            criteria = criteria.with_app_name_matching(mode, app);
+        }
+        if let Some(bundle_id) = &rules.bundle_id {
+            criteria = criteria.with_bundle_identifier(bundle_id);
+        }
+        if let Some(detection) = rules.dialog_detection {
+            criteria = criteria.with_dialog_detection(detection);
+        }
+        criteria.with_ignored_apps(self.ignored_apps())
+    }
+
+    pub fn panel_config(&self) -> PanelConfig {
+        self.panel.apply(PanelConfig::new())
+    }
+
+    /// Applies the panel style and per-app overrides to `manager`.
+    pub fn configure(&self, manager: PanelManager) -> PanelManager {
+        let base = self.panel_config();
+        let mut manager = manager.with_panel_config(base.clone());
+        for (app, style) in &self.apps {
+            manager = manager.with_app_panel_config(app, style.apply(base.clone()));
+        }
+        manager
+    }
+}
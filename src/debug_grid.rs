@@ -0,0 +1,300 @@
+use objc2::rc::Retained;
+use objc2::{define_class, msg_send, DefinedClass, MainThreadMarker, MainThreadOnly};
+use objc2_app_kit::{
+    NSBackingStoreType, NSBezierPath, NSColor, NSPanel, NSStringDrawing, NSView,
+    NSWindowCollectionBehavior, NSWindowStyleMask,
+};
+use objc2_foundation::{NSPoint, NSRect, NSSize, NSString};
+
+use crate::screen::ScreenInfo;
+use crate::window_search::{WindowBounds, WindowInfo};
+
+pub const GRID_SPACING: f64 = 100.0;
+const LABEL_OFFSET: f64 = 2.0;
+const LABEL_HEIGHT: f64 = 14.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GridAxis {
+    Vertical,
+    Horizontal,
+}
+
+/// A gridline at `position` in view coordinates (x for vertical lines, y for
+/// horizontal ones), labelled with the global CG coordinate it marks.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GridLine {
+    pub axis: GridAxis,
+    pub position: f64,
+    pub label: String,
+}
+
+/// A matched window's frame in view coordinates, labelled with its CG bounds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WindowAnnotation {
+    pub frame: NSRect,
+    pub label: String,
+}
+
+/// Gridlines at every multiple of `spacing` in global CG coordinates that falls
+/// within `screen_frame` (CG coordinates), so lines line up across displays.
+pub fn grid_lines(screen_frame: &WindowBounds, spacing: f64) -> Vec<GridLine> {
+    if !spacing.is_finite() || spacing <= 0.0 || !screen_frame.is_valid() {
+        return Vec::new();
+    }
+
+    let vertical =
+        multiples_within(screen_frame.x, screen_frame.right(), spacing).map(|x| GridLine {
+            axis: GridAxis::Vertical,
+            position: x - screen_frame.x,
+            label: format!("x:{}", x),
+        });
+    let horizontal =
+        multiples_within(screen_frame.y, screen_frame.bottom(), spacing).map(|y| GridLine {
+            axis: GridAxis::Horizontal,
+            position: screen_frame.bottom() - y,
+            label: format!("y:{}", y),
+        });
+
+    vertical.chain(horizontal).collect()
+}
+
+/// Annotations for the windows intersecting `screen_frame` (CG coordinates).
+pub fn window_annotations(
+    windows: &[WindowInfo],
+    screen_frame: &WindowBounds,
+) -> Vec<WindowAnnotation> {
+    windows
+        .iter()
+        .filter(|window| screen_frame.intersects(&window.bounds))
+        .map(|window| WindowAnnotation {
+            frame: NSRect::new(
+                NSPoint::new(
+                    window.bounds.x - screen_frame.x,
+                    screen_frame.bottom() - window.bounds.bottom(),
+                ),
+                NSSize::new(window.bounds.width, window.bounds.height),
+            ),
+            label: format!(
+                "#{} {} ({}, {}) {}x{}",
+                window.window_number,
+                window.app_name,
+                window.bounds.x,
+                window.bounds.y,
+                window.bounds.width,
+                window.bounds.height
+            ),
+        })
+        .collect()
+}
+
+fn multiples_within(start: f64, end: f64, spacing: f64) -> impl Iterator<Item = f64> {
+    let first = (start / spacing).ceil() as i64;
+    let last = (end / spacing).floor() as i64;
+    (first..=last).map(move |step| step as f64 * spacing)
+}
+
+/// A click-through panel covering `screen` that draws the CG coordinate grid
+/// and outlines `windows`, for checking placement and coordinate conversion.
+pub fn create_debug_grid_panel(
+    screen: &ScreenInfo,
+    primary_height: f64,
+    windows: &[WindowInfo],
+    mtm: MainThreadMarker,
+) -> Retained<NSPanel> {
+    let screen_frame = screen.cg_frame(primary_height);
+    let view_frame = NSRect::new(NSPoint::new(0.0, 0.0), screen.frame.size);
+
+    let grid_view = GridView::new(
+        view_frame,
+        GridViewIvars {
+            lines: grid_lines(&screen_frame, GRID_SPACING),
+            annotations: window_annotations(windows, &screen_frame),
+        },
+        mtm,
+    );
+
+    let panel = NSPanel::initWithContentRect_styleMask_backing_defer(
+        NSPanel::alloc(mtm),
+        screen.frame,
+        NSWindowStyleMask::Borderless | NSWindowStyleMask::NonactivatingPanel,
+        NSBackingStoreType::Buffered,
+        false,
+    );
+
+    panel.setLevel(10);
+    panel.setOpaque(false);
+    panel.setBackgroundColor(Some(&NSColor::clearColor()));
+    panel.setIgnoresMouseEvents(true);
+    panel.setHasShadow(false);
+    panel.setHidesOnDeactivate(false);
+    panel.setCollectionBehavior(
+        NSWindowCollectionBehavior::CanJoinAllSpaces | NSWindowCollectionBehavior::Stationary,
+    );
+    panel.setTitle(&NSString::from_str("Debug Grid"));
+    panel.setContentView(Some(&grid_view));
+    panel.orderFrontRegardless();
+
+    panel
+}
+
+struct GridViewIvars {
+    lines: Vec<GridLine>,
+    annotations: Vec<WindowAnnotation>,
+}
+
+define_class!(
+    // SAFETY: NSView has no subclassing requirements and `GridView` does not
+    // implement `Drop`.
+    #[unsafe(super(NSView))]
+    #[thread_kind = MainThreadOnly]
+    #[name = "PanelDetectorGridView"]
+    #[ivars = GridViewIvars]
+    struct GridView;
+
+    impl GridView {
+        #[unsafe(method(drawRect:))]
+        fn draw_rect(&self, _dirty_rect: NSRect) {
+            self.draw_grid();
+        }
+    }
+);
+
+impl GridView {
+    fn new(frame: NSRect, ivars: GridViewIvars, mtm: MainThreadMarker) -> Retained<Self> {
+        let this = Self::alloc(mtm).set_ivars(ivars);
+        unsafe { msg_send![super(this), initWithFrame: frame] }
+    }
+
+    fn draw_grid(&self) {
+        let size = self.frame().size;
+        let ivars = self.ivars();
+
+        NSColor::colorWithWhite_alpha(0.5, 0.4).setStroke();
+        for line in &ivars.lines {
+            let (from, to, label_at) = match line.axis {
+                GridAxis::Vertical => (
+                    NSPoint::new(line.position, 0.0),
+                    NSPoint::new(line.position, size.height),
+                    NSPoint::new(
+                        line.position + LABEL_OFFSET,
+                        size.height - LABEL_HEIGHT - LABEL_OFFSET,
+                    ),
+                ),
+                GridAxis::Horizontal => (
+                    NSPoint::new(0.0, line.position),
+                    NSPoint::new(size.width, line.position),
+                    NSPoint::new(LABEL_OFFSET, line.position + LABEL_OFFSET),
+                ),
+            };
+            NSBezierPath::strokeLineFromPoint_toPoint(from, to);
+            draw_label(&line.label, label_at);
+        }
+
+        NSColor::redColor().setStroke();
+        for annotation in &ivars.annotations {
+            NSBezierPath::strokeRect(annotation.frame);
+            let origin = annotation.frame.origin;
+            draw_label(
+                &annotation.label,
+                NSPoint::new(
+                    origin.x + LABEL_OFFSET,
+                    origin.y + annotation.frame.size.height - LABEL_HEIGHT - LABEL_OFFSET,
+                ),
+            );
+        }
+    }
+}
+
+fn draw_label(label: &str, at: NSPoint) {
+    unsafe { NSString::from_str(label).drawAtPoint_withAttributes(at, None) };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(lines: &[GridLine], axis: GridAxis) -> Vec<(f64, &str)> {
+        lines
+            .iter()
+            .filter(|line| line.axis == axis)
+            .map(|line| (line.position, line.label.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn grid_lines_on_a_display_left_of_the_primary() {
+        let grid = grid_lines(&WindowBounds::new(-1920.0, 0.0, 1920.0, 1080.0), 500.0);
+
+        assert_eq!(
+            lines(&grid, GridAxis::Vertical),
+            [
+                (420.0, "x:-1500"),
+                (920.0, "x:-1000"),
+                (1420.0, "x:-500"),
+                (1920.0, "x:0"),
+            ]
+        );
+        assert_eq!(
+            lines(&grid, GridAxis::Horizontal),
+            [(1080.0, "y:0"), (580.0, "y:500"), (80.0, "y:1000")]
+        );
+    }
+
+    #[test]
+    fn grid_lines_on_a_display_above_the_primary() {
+        let grid = grid_lines(&WindowBounds::new(1440.0, -1080.0, 1920.0, 1080.0), 500.0);
+
+        assert_eq!(
+            lines(&grid, GridAxis::Vertical),
+            [
+                (60.0, "x:1500"),
+                (560.0, "x:2000"),
+                (1060.0, "x:2500"),
+                (1560.0, "x:3000"),
+            ]
+        );
+        assert_eq!(
+            lines(&grid, GridAxis::Horizontal),
+            [(1000.0, "y:-1000"), (500.0, "y:-500"), (0.0, "y:0")]
+        );
+    }
+
+    #[test]
+    fn grid_lines_need_a_positive_spacing() {
+        let screen = WindowBounds::new(0.0, 0.0, 1440.0, 900.0);
+
+        assert!(grid_lines(&screen, 0.0).is_empty());
+        assert!(grid_lines(&screen, -100.0).is_empty());
+        assert!(grid_lines(&screen, f64::NAN).is_empty());
+        assert!(grid_lines(&screen, f64::INFINITY).is_empty());
+        assert!(grid_lines(&WindowBounds::default(), GRID_SPACING).is_empty());
+    }
+
+    #[test]
+    fn annotations_cover_windows_on_a_negative_origin_display() {
+        let window = |window_number: i64, x: f64| {
+            WindowInfo::builder()
+                .with_window_number(window_number)
+                .with_bounds(WindowBounds::new(x, 100.0, 200.0, 300.0))
+                .build()
+        };
+        let windows = [window(1, -1500.0), window(2, 100.0), window(3, -100.0)];
+
+        let annotations =
+            window_annotations(&windows, &WindowBounds::new(-1920.0, 0.0, 1920.0, 1080.0));
+
+        assert_eq!(
+            annotations,
+            [
+                WindowAnnotation {
+                    frame: NSRect::new(NSPoint::new(420.0, 680.0), NSSize::new(200.0, 300.0)),
+                    label: "#1 Test (-1500, 100) 200x300".to_string(),
+                },
+                WindowAnnotation {
+                    frame: NSRect::new(NSPoint::new(1820.0, 680.0), NSSize::new(200.0, 300.0)),
+                    label: "#3 Test (-100, 100) 200x300".to_string(),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,237 @@
+use serde::Deserialize;
+
+use crate::window_search::{Pid, WindowNumber};
+
+/// How `KnownDialog::matches_with` recognises a dialog. Role detection needs
+/// the `accessibility` feature and matches nothing without it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DialogDetection {
+    /// The window title is one of the dialog's localized titles.
+    Title,
+    /// Accessibility reports the window as this kind of panel, which works in
+    /// any language and whatever the app titles it.
+    Role,
+    /// Either one, except that `Print` only goes by title: its panel has no
+    /// identifier, so its role would accept any sheet or dialog.
+    #[default]
+    TitleOrRole,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KnownDialog {
+    Open,
+    Save,
+    SaveAs,
+    Print,
+}
+
+impl KnownDialog {
+    pub const ALL: [KnownDialog; 4] = [
+        KnownDialog::Open,
+        KnownDialog::Save,
+        KnownDialog::SaveAs,
+        KnownDialog::Print,
+    ];
+
+    pub fn localized_titles(&self) -> &'static [&'static str] {
+        match self {
+            KnownDialog::Open => &[
+                "Open",
+                "Abrir",
+                "Ouvrir",
+                "Öffnen",
+                "Apri",
+                "Openen",
+                "Öppna",
+                "Åbn",
+                "Åpne",
+                "Avaa",
+                "Otwórz",
+                "Открыть",
+                "開く",
+                "打开",
+                "打開",
+                "열기",
+            ],
+            KnownDialog::Save => &[
+                "Save",
+                "Guardar",
+                "Salvar",
+                "Enregistrer",
+                "Sichern",
+                "Speichern",
+                "Salva",
+                "Opslaan",
+                "Spara",
+                "Gem",
+                "Lagre",
+                "Tallenna",
+                "Zapisz",
+                "Сохранить",
+                "保存",
+                "儲存",
+                "저장",
+            ],
+            KnownDialog::SaveAs => &[
+                "Save As",
+                "Guardar como",
+                "Salvar como",
+                "Enregistrer sous",
+                "Sichern unter",
+                "Speichern unter",
+                "Salva con nome",
+                "Opslaan als",
+                "Spara som",
+                "Gem som",
+                "Lagre som",
+                "Tallenna nimellä",
+                "Zapisz jako",
+                "Сохранить как",
+                "別名で保存",
+                "另存为",
+                "另存新檔",
+                "다른 이름으로 저장",
+            ],
+            KnownDialog::Print => &[
+                "Print",
+                "Imprimir",
+                "Imprimer",
+                "Drucken",
+                "Stampa",
+                "Afdrukken",
+                "Skriv ut",
+                "Udskriv",
+                "Tulosta",
+                "Drukuj",
+                "Печать",
+                "プリント",
+                "打印",
+                "列印",
+                "프린트",
+            ],
+        }
+    }
+
+    /// The `AXIdentifier` AppKit gives this dialog's standard panel. Print
+    /// panels have none, so under `DialogDetection::Role` any sheet or dialog
+    /// passes for one.
+    pub fn panel_identifier(&self) -> Option<&'static str> {
+        match self {
+            KnownDialog::Open => Some("open-panel"),
+            KnownDialog::Save | KnownDialog::SaveAs => Some("save-panel"),
+            KnownDialog::Print => None,
+        }
+    }
+
+    pub fn matches_title(&self, title: &str) -> bool {
+        let title = title
+            .trim()
+            .trim_end_matches(['…', '.'])
+            .trim_end()
+            .to_lowercase();
+        self.localized_titles()
+            .iter()
+            .any(|known| known.to_lowercase() == title)
+    }
+
+    pub fn matches(&self, title: &str, pid: Pid, window_number: WindowNumber) -> bool {
+        self.matches_with(DialogDetection::default(), title, pid, window_number)
+    }
+
+    pub fn matches_with(
+        &self,
+        detection: DialogDetection,
+        title: &str,
+        pid: Pid,
+        window_number: WindowNumber,
+    ) -> bool {
+        match detection {
+            DialogDetection::Title => self.matches_title(title),
+            DialogDetection::Role => self.matches_role(pid, window_number),
+            DialogDetection::TitleOrRole => {
+                self.matches_title(title)
+                    || (self.panel_identifier().is_some() && self.matches_role(pid, window_number))
+            }
+        }
+    }
+
+    /// Whether accessibility exposes the window as this dialog's panel,
+    /// whether it is a sheet or a free-standing dialog.
+    pub fn matches_role(&self, pid: Pid, window_number: WindowNumber) -> bool {
+        #[cfg(feature = "accessibility")]
+        {
+            match self.panel_identifier() {
+                Some(identifier) => crate::ax::dialog_identifier(pid.0, window_number.0)
+                    .is_some_and(|found| found == identifier),
+                None => crate::ax::is_dialog_window(pid.0, window_number.0),
+            }
+        }
+
+        #[cfg(not(feature = "accessibility"))]
+        {
+            let _ = (pid, window_number);
+            false
+        }
+    }
+}
+
+/// Whether a window is any kind of dialog: titled like a known dialog, or,
+/// with accessibility enabled, exposed as a sheet or dialog.
+pub fn is_dialog(title: &str, pid: Pid, window_number: WindowNumber) -> bool {
+    if KnownDialog::ALL
+        .iter()
+        .any(|dialog| dialog.matches_title(title))
+    {
+        return true;
+    }
+
+    #[cfg(feature = "accessibility")]
+    {
+        crate::ax::is_dialog_window(pid.0, window_number.0)
+    }
+
+    #[cfg(not(feature = "accessibility"))]
+    {
+        let _ = (pid, window_number);
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn titles_match_in_any_listed_language() {
+        assert!(KnownDialog::Open.matches_title("Abrir"));
+        assert!(KnownDialog::Open.matches_title("Öffnen…"));
+        assert!(KnownDialog::Open.matches_title("  open... "));
+        assert!(KnownDialog::SaveAs.matches_title("別名で保存"));
+    }
+
+    #[test]
+    fn titles_must_match_whole() {
+        assert!(!KnownDialog::Open.matches_title("Open Recent"));
+        assert!(!KnownDialog::Open.matches_title("Öffnen mit"));
+        assert!(!KnownDialog::Save.matches_title("別名で保存"));
+        assert!(!KnownDialog::Print.matches_title(""));
+    }
+
+    #[test]
+    fn print_goes_by_title_unless_role_is_asked_for() {
+        let (pid, window_number) = (Pid(1), WindowNumber(1));
+        assert!(KnownDialog::Print.matches_with(
+            DialogDetection::TitleOrRole,
+            "Drucken",
+            pid,
+            window_number
+        ));
+        assert!(!KnownDialog::Print.matches_with(
+            DialogDetection::TitleOrRole,
+            "Untitled",
+            pid,
+            window_number
+        ));
+    }
+}
@@ -0,0 +1,156 @@
+//! One error type over the modules' own, for callers that use several of them
+//! and just want `?`. The module errors convert into it, sorting the failures
+//! callers usually handle differently (a missing permission, bad bounds) into
+//! variants of their own.
+
+use thiserror::Error;
+
+use crate::activation::ActivationError;
+#[cfg(feature = "accessibility")]
+use crate::ax::AxError;
+#[cfg(feature = "capture")]
+use crate::capture::CaptureError;
+use crate::config::ConfigError;
+use crate::ipc::IpcError;
+use crate::panel::PanelError;
+use crate::permissions::Permission;
+use crate::window_search::{WindowBounds, WindowSearchError};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Failed to get window list")]
+    WindowListUnavailable,
+    #[error("{0} permission not granted")]
+    PermissionDenied(Permission),
+    /// A panel could not be set up for a reason other than its bounds.
+    #[error("Could not create the panel: {0}")]
+    PanelInitFailed(#[source] PanelError),
+    /// The target window reported zero-area or non-finite bounds.
+    #[error("Invalid window bounds ({0})")]
+    InvalidBounds(WindowBounds),
+    #[cfg(feature = "accessibility")]
+    #[error("Accessibility call failed: {0}")]
+    AxError(#[source] AxError),
+    /// Any other window search failure, such as a missing window.
+    #[error(transparent)]
+    WindowSearch(WindowSearchError),
+    #[error(transparent)]
+    Activation(ActivationError),
+    #[cfg(feature = "capture")]
+    #[error(transparent)]
+    Capture(#[from] CaptureError),
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    #[error(transparent)]
+    Ipc(#[from] IpcError),
+}
+
+impl From<WindowSearchError> for Error {
+    fn from(error: WindowSearchError) -> Self {
+        match error {
+            WindowSearchError::WindowListUnavailable => Error::WindowListUnavailable,
+            error => Error::WindowSearch(error),
+        }
+    }
+}
+
+impl From<PanelError> for Error {
+    fn from(error: PanelError) -> Self {
+        match error {
+            PanelError::InvalidBounds(bounds) => Error::InvalidBounds(bounds),
+            error => Error::PanelInitFailed(error),
+        }
+    }
+}
+
+#[cfg(feature = "accessibility")]
+impl From<AxError> for Error {
+    fn from(error: AxError) -> Self {
+        if error.is_permission_denied() {
+            Error::PermissionDenied(Permission::Accessibility)
+        } else {
+            Error::AxError(error)
+        }
+    }
+}
+
+impl From<ActivationError> for Error {
+    fn from(error: ActivationError) -> Self {
+        match error {
+            #[cfg(feature = "accessibility")]
+            ActivationError::RaiseFailed(_, error) if error.is_permission_denied() => {
+                Error::PermissionDenied(Permission::Accessibility)
+            }
+            error => Error::Activation(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::window_search::{Pid, WindowNumber};
+
+    #[test]
+    fn window_list_failures_get_their_own_variant() {
+        assert!(matches!(
+            Error::from(WindowSearchError::WindowListUnavailable),
+            Error::WindowListUnavailable
+        ));
+        let missing = Error::from(WindowSearchError::WindowNotFound(WindowNumber(7)));
+        assert!(matches!(
+            missing,
+            Error::WindowSearch(WindowSearchError::WindowNotFound(WindowNumber(7)))
+        ));
+        assert_eq!(missing.to_string(), "Window 7 not found");
+    }
+
+    #[test]
+    fn panel_bounds_failures_are_split_from_other_panel_errors() {
+        let bounds = WindowBounds::new(0.0, 0.0, 0.0, 10.0);
+        assert!(matches!(
+            Error::from(PanelError::InvalidBounds(bounds)),
+            Error::InvalidBounds(found) if found == bounds
+        ));
+
+        let no_screen = Error::from(PanelError::NoScreen);
+        assert!(matches!(
+            no_screen,
+            Error::PanelInitFailed(PanelError::NoScreen)
+        ));
+        assert_eq!(
+            no_screen.to_string(),
+            "Could not create the panel: No screen available for the panel"
+        );
+        assert!(std::error::Error::source(&no_screen).is_some());
+    }
+
+    #[test]
+    fn activation_errors_pass_through() {
+        let error = Error::from(ActivationError::NoApplication(Pid(42)));
+        assert_eq!(error.to_string(), "No running application with pid 42");
+    }
+
+    #[cfg(feature = "accessibility")]
+    #[test]
+    fn ax_permission_failures_become_permission_denied() {
+        let denied = AxError(-25211);
+        assert!(denied.is_permission_denied());
+        assert!(matches!(
+            Error::from(denied),
+            Error::PermissionDenied(Permission::Accessibility)
+        ));
+        assert!(matches!(
+            Error::from(ActivationError::RaiseFailed(WindowNumber(3), denied)),
+            Error::PermissionDenied(Permission::Accessibility)
+        ));
+        assert!(matches!(
+            Error::from(AxError(-25202)),
+            Error::AxError(AxError(-25202))
+        ));
+        assert_eq!(
+            Error::PermissionDenied(Permission::Accessibility).to_string(),
+            "Accessibility permission not granted"
+        );
+    }
+}
@@ -0,0 +1,340 @@
+//! A control socket for a running overlay. Clients send one JSON command per
+//! line and get one JSON response line back per command:
+//!
+//! ```text
+//! {"cmd":"list"}
+//! {"cmd":"overlay","title":"Open","mode":"starts-with"}
+//! {"cmd":"close","id":3}
+//! {"cmd":"close_all"}
+//! {"cmd":"hide"}
+//! {"cmd":"show"}
+//! ```
+//!
+//! Responses are `{"ok":true,"panels":[...]}` or `{"ok":false,"error":"..."}`.
+//! Connections are served on background threads, but commands only run when
+//! the main thread calls `IpcServer::handle_pending`, since panels are AppKit
+//! objects.
+
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+use serde::{Deserialize, Serialize};
+
+use crate::manager::{PanelId, PanelManager};
+use crate::window_search::{MatchMode, WindowInfo, WindowSearchCriteria};
+
+const SOCKET_NAME: &str = "relative-panel.sock";
+
+#[derive(Debug)]
+pub enum IpcError {
+    /// Another process is serving the socket.
+    AlreadyRunning(PathBuf),
+    /// Something other than a socket is at the path; it is left alone.
+    NotASocket(PathBuf),
+    Io(PathBuf, io::Error),
+}
+
+impl fmt::Display for IpcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IpcError::AlreadyRunning(path) => {
+                write!(f, "Another instance is listening on {}", path.display())
+            }
+            IpcError::NotASocket(path) => {
+                write!(f, "{} exists and is not a socket", path.display())
+            }
+            IpcError::Io(path, e) => write!(f, "Could not listen on {}: {}", path.display(), e),
+        }
+    }
+}
+
+impl std::error::Error for IpcError {}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "cmd", rename_all = "snake_case", deny_unknown_fields)]
+pub enum IpcCommand {
+    /// The tracked panels and their windows.
+    List,
+    /// Switches the overlay to windows whose title matches, exactly unless
+    /// `mode` says otherwise. The rest of the current criteria, such as the
+    /// ignored apps and bundle id, still apply, except that the window no
+    /// longer needs to be a known dialog.
+    Overlay {
+        title: String,
+        #[serde(default)]
+        mode: Option<MatchMode>,
+    },
+    Close {
+        id: u64,
+    },
+    CloseAll,
+    Hide,
+    Show,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct IpcResponse {
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub panels: Option<Vec<ListedPanel>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ListedPanel {
+    pub id: u64,
+    pub window: WindowInfo,
+}
+
+impl IpcResponse {
+    fn ok() -> Self {
+        Self {
+            ok: true,
+            error: None,
+            panels: None,
+        }
+    }
+
+    fn error(message: impl Into<String>) -> Self {
+        Self {
+            ok: false,
+            error: Some(message.into()),
+            panels: None,
+        }
+    }
+
+    fn panels(manager: &PanelManager) -> Self {
+        let panels = manager
+            .tracked_windows()
+            .into_iter()
+            .map(|(id, window)| ListedPanel { id: id.0, window })
+            .collect();
+        Self {
+            panels: Some(panels),
+            ..Self::ok()
+        }
+    }
+}
+
+struct Request {
+    command: IpcCommand,
+    reply: Sender<IpcResponse>,
+}
+
+pub struct IpcServer {
+    path: PathBuf,
+    requests: Receiver<Request>,
+}
+
+impl IpcServer {
+    /// `$TMPDIR/relative-panel.sock`.
+    pub fn default_path() -> PathBuf {
+        std::env::temp_dir().join(SOCKET_NAME)
+    }
+
+    /// Listens on `path`, replacing a socket left behind by an instance that
+    /// didn't shut down cleanly. Any other file at `path` is an error.
+    pub fn bind(path: impl AsRef<Path>) -> Result<Self, IpcError> {
+        let path = path.as_ref().to_path_buf();
+        if let Ok(metadata) = std::fs::symlink_metadata(&path) {
+            if !metadata.file_type().is_socket() {
+                return Err(IpcError::NotASocket(path));
+            }
+            if UnixStream::connect(&path).is_ok() {
+                return Err(IpcError::AlreadyRunning(path));
+            }
+            std::fs::remove_file(&path).map_err(|e| IpcError::Io(path.clone(), e))?;
+        }
+        let listener = UnixListener::bind(&path).map_err(|e| IpcError::Io(path.clone(), e))?;
+
+        let (sender, requests) = mpsc::channel();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        let sender = sender.clone();
+                        thread::spawn(move || serve_connection(stream, sender));
+                    }
+                    Err(e) => log::warn!("[IPC] Failed to accept a connection: {}", e),
+                }
+            }
+        });
+
+        log::info!("[IPC] Listening on {}", path.display());
+        Ok(Self { path, requests })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Runs the commands received since the last call against `manager` and
+    /// answers them. Call from the main thread, e.g. on a short timer.
+    pub fn handle_pending(&self, manager: &PanelManager) {
+        while let Ok(request) = self.requests.try_recv() {
+            log::debug!("[IPC] {:?}", request.command);
+            // The client may have hung up meanwhile.
+            let _ = request.reply.send(execute(manager, request.command));
+        }
+    }
+
+    /// Removes the socket file; also done on drop, which `process::exit`
+    /// skips.
+    pub fn close(&self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+impl Drop for IpcServer {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+fn execute(manager: &PanelManager, command: IpcCommand) -> IpcResponse {
+    match command {
+        IpcCommand::List => IpcResponse::panels(manager),
+        IpcCommand::Overlay { title, mode } => {
+            let criteria = overlay_criteria(&manager.search_criteria(), title, mode);
+            manager.set_search_criteria(criteria);
+            manager.check_for_windows();
+            IpcResponse::panels(manager)
+        }
+        IpcCommand::Close { id } => {
+            if manager.close(PanelId(id)) {
+                IpcResponse::ok()
+            } else {
+                IpcResponse::error(format!("No panel with id {}", id))
+            }
+        }
+        IpcCommand::CloseAll => {
+            manager.close_all();
+            IpcResponse::ok()
+        }
+        IpcCommand::Hide => {
+            manager.hide_all();
+            IpcResponse::ok()
+        }
+        IpcCommand::Show => {
+            manager.show_all();
+            IpcResponse::ok()
+        }
+    }
+}
+
+fn overlay_criteria(
+    current: &WindowSearchCriteria,
+    title: String,
+    mode: Option<MatchMode>,
+) -> WindowSearchCriteria {
+    current
+        .clone()
+        .without_known_dialog()
+        .with_title_matching(mode.unwrap_or(MatchMode::Exact), title)
+}
+
+fn serve_connection(stream: UnixStream, requests: Sender<Request>) {
+    let mut writer = match stream.try_clone() {
+        Ok(writer) => writer,
+        Err(e) => {
+            log::warn!("[IPC] Failed to set up a connection: {}", e);
+            return;
+        }
+    };
+
+    for line in BufReader::new(stream).lines() {
+        let Ok(line) = line else {
+            return;
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response = match serde_json::from_str(&line) {
+            Ok(command) => {
+                let (reply, response) = mpsc::channel();
+                if requests.send(Request { command, reply }).is_err() {
+                    return;
+                }
+                match response.recv() {
+                    Ok(response) => response,
+                    Err(_) => return,
+                }
+            }
+            Err(e) => IpcResponse::error(format!("Invalid command: {}", e)),
+        };
+
+        let Ok(json) = serde_json::to_string(&response) else {
+            return;
+        };
+        if writeln!(writer, "{}", json).is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::dialog::KnownDialog;
+
+    // A socket path of its own for each test, short enough for `sun_path`.
+    fn socket_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("rp-{}-{}.sock", std::process::id(), name));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn binding_leaves_other_files_alone() {
+        let path = socket_path("file");
+        std::fs::write(&path, "notes").unwrap();
+
+        let result = IpcServer::bind(&path);
+
+        assert!(matches!(result, Err(IpcError::NotASocket(_))));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "notes");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn binding_replaces_stale_sockets_but_not_live_ones() {
+        let path = socket_path("stale");
+        drop(UnixListener::bind(&path).unwrap());
+
+        let server = IpcServer::bind(&path).unwrap();
+        assert!(matches!(
+            IpcServer::bind(&path),
+            Err(IpcError::AlreadyRunning(_))
+        ));
+
+        drop(server);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn overlay_keeps_the_current_criteria_but_the_title() {
+        let current = WindowSearchCriteria::new()
+            .with_known_dialog(KnownDialog::Open)
+            .with_title("Open")
+            .with_bundle_identifier("com.apple.TextEdit")
+            .with_ignored_apps(HashSet::from(["dock".to_string()]));
+
+        let criteria =
+            overlay_criteria(&current, "Export".to_string(), Some(MatchMode::StartsWith));
+
+        assert_eq!(
+            criteria.to_string(),
+            "title starting with \"Export\", bundle id \"com.apple.TextEdit\""
+        );
+        assert_eq!(criteria.ignored_apps(), current.ignored_apps());
+    }
+}
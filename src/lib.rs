@@ -0,0 +1,22 @@
+//! Finds on-screen windows through CoreGraphics and pins AppKit overlay panels
+//! to them. The `panel_detector` binary is a CLI over this library.
+
+pub mod activation;
+#[cfg(feature = "accessibility")]
+pub mod ax;
+#[cfg(feature = "capture")]
+pub mod capture;
+mod cf;
+pub mod config;
+pub mod debug_grid;
+pub mod dialog;
+pub mod error;
+pub mod ipc;
+pub mod manager;
+pub mod monitor;
+pub mod panel;
+pub mod permissions;
+pub mod screen;
+#[cfg(feature = "webview")]
+pub mod webview;
+pub mod window_search;
@@ -1,267 +1,644 @@
-mod window_search;
-
-use std::collections::{HashMap, HashSet};
 use std::cell::RefCell;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 use std::ptr::NonNull;
 use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::Arc;
+use std::time::Duration;
 
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use objc2::rc::Retained;
-use objc2::{MainThreadMarker, MainThreadOnly};
-use objc2_app_kit::{
-    NSApplication, NSApplicationActivationPolicy, NSBackingStoreType, NSButton, NSPanel, NSScreen,
-    NSView, NSWindowCollectionBehavior, NSWindowStyleMask,
+use objc2::MainThreadMarker;
+use objc2_app_kit::{NSApplication, NSApplicationActivationPolicy, NSPanel};
+use objc2_foundation::NSTimer;
+
+use panel_detector::config::Config;
+use panel_detector::debug_grid::create_debug_grid_panel;
+use panel_detector::dialog::DialogDetection;
+use panel_detector::ipc::IpcServer;
+use panel_detector::manager::PanelManager;
+use panel_detector::monitor::{format_event_json, format_event_line, spawn_monitor, WindowEvent};
+use panel_detector::permissions::missing_permissions;
+use panel_detector::screen::{watch_display_changes, DisplayWatch};
+use panel_detector::window_search::{
+    find_windows, format_window_entries, inspect_window, MatchMode, WindowNumber,
+    WindowSearchCriteria, WindowSearchResults,
 };
-use objc2_foundation::{NSPoint, NSRect, NSSize, NSString, NSTimer};
 
-use window_search::{find_windows, WindowSearchCriteria};
+const DEFAULT_POLL_INTERVAL_MS: u64 = 5000;
+const INTERRUPT_CHECK_SECONDS: f64 = 0.1;
+const INTERRUPT_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+const FOLLOW_INTERVAL_SECONDS: f64 = 0.1;
+const IPC_CHECK_SECONDS: f64 = 0.05;
+
+/// Finds windows and pins overlay panels to them. Without a subcommand, runs
+/// `overlay` with the options given.
+#[derive(Parser)]
+#[command(name = "panel_detector", args_conflicts_with_subcommands = true)]
+struct Cli {
+    /// Only log warnings and errors to stderr, so stdout carries nothing but
+    /// the command's output, e.g. for piping `--json` into jq.
+    #[arg(long, global = true, visible_alias = "json-only")]
+    quiet: bool,
+
+    /// Read settings from this file instead of
+    /// ~/.config/relative-panel/config.toml. Flags override it.
+    #[arg(long, global = true, value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    overlay: OverlayArgs,
+}
 
-const POLL_INTERVAL_SECONDS: f64 = 5.0;
+#[derive(Subcommand)]
+enum Command {
+    /// Print the matching windows once, grouped by app.
+    List {
+        #[command(flatten)]
+        filter: FilterArgs,
+
+        /// Print one JSON document instead.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print matching windows as they appear, move, resize, retitle and close.
+    Watch {
+        #[command(flatten)]
+        filter: FilterArgs,
+
+        #[command(flatten)]
+        poll: PollArgs,
+
+        /// Print one JSON object per event (NDJSON).
+        #[arg(long)]
+        json: bool,
+    },
+    /// Pin an overlay panel to each matching window until interrupted.
+    Overlay(OverlayArgs),
+    /// Print every CG property of one window.
+    Inspect { window_number: i64 },
+}
 
-struct PanelManager {
-    panels: RefCell<HashMap<i64, Retained<NSPanel>>>,
-    search_criteria: WindowSearchCriteria,
+/// Which windows to target. Without `--title`, `--app` or `--bundle-id`
+/// this uses the config file's `[match]` rules, which default to Open dialogs.
+#[derive(Args)]
+struct FilterArgs {
+    /// Match window titles against this (see --match-mode).
+    #[arg(long)]
+    title: Option<String>,
+
+    /// Match app names against this (see --match-mode).
+    #[arg(long)]
+    app: Option<String>,
+
+    /// Match the owning app's bundle id, e.g. com.apple.finder.
+    #[arg(long = "bundle-id")]
+    bundle_id: Option<String>,
+
+    /// Skip windows narrower or shorter than this, e.g. 50x50.
+    #[arg(long, value_name = "WxH", value_parser = parse_size)]
+    min_size: Option<(f64, f64)>,
+
+    /// Skip windows wider or taller than this.
+    #[arg(long, value_name = "WxH", value_parser = parse_size)]
+    max_size: Option<(f64, f64)>,
+
+    /// Also match minimized windows and those on other spaces.
+    #[arg(long)]
+    include_offscreen: bool,
+
+    /// Skip apps whose name contains this, ignoring case. Repeatable.
+    #[arg(long, value_name = "APP")]
+    ignore: Vec<String>,
+
+    /// How --title and --app compare.
+    #[arg(long, value_enum, default_value_t = MatchModeArg::Exact)]
+    match_mode: MatchModeArg,
+
+    /// How Open dialogs are recognised when no other filter is given. `role`
+    /// needs the accessibility feature. Defaults to title-or-role.
+    #[arg(long, value_enum)]
+    dialog_detection: Option<DialogDetectionArg>,
 }
 
-impl PanelManager {
-    fn new() -> Rc<Self> {
-        Rc::new(Self {
-            panels: RefCell::new(HashMap::new()),
-            search_criteria: WindowSearchCriteria::new()
-                .with_title("Open")
-                .with_ignored_apps(get_ignored_apps()),
-        })
+impl FilterArgs {
+    fn criteria(&self, config: &Config) -> WindowSearchCriteria {
+        let mut criteria = if self.title.is_none() && self.app.is_none() && self.bundle_id.is_none()
+        {
+            let criteria = config.search_criteria();
+            match self.dialog_detection {
+                Some(detection) => criteria.with_dialog_detection(detection.into()),
+                None => criteria,
+            }
+        } else {
+            WindowSearchCriteria::new()
+                .with_ignored_apps(config.ignored_apps())
+                .require_valid_bounds(true)
+        };
+
+        let mode = MatchMode::from(self.match_mode);
+        if let Some(title) = &self.title {
+            criteria = criteria.with_title_matching(mode, title);
+        }
+        if let Some(app) = &self.app {
+            criteria = criteria.with_app_name_matching(mode, app);
+        }
+        if let Some(bundle_id) = &self.bundle_id {
+            criteria = criteria.with_bundle_identifier(bundle_id);
+        }
+        if let Some((width, height)) = self.min_size {
+            criteria = criteria.with_min_size(width, height);
+        }
+        if let Some((width, height)) = self.max_size {
+            criteria = criteria.with_max_size(width, height);
+        }
+        for app in &self.ignore {
+            criteria = criteria.add_ignored_app(app);
+        }
+        criteria.include_offscreen(self.include_offscreen)
     }
+}
 
-    fn check_for_windows(&self) {
-        println!("\n[POLL] Searching for windows with title 'Open'...");
-
-        match find_windows(&self.search_criteria) {
-            Ok(results) => {
-                println!("[POLL] Scanned {} total windows", results.total_windows);
-
-                let current_window_numbers: HashSet<i64> = results
-                    .matched_windows
-                    .iter()
-                    .map(|w| w.window_number)
-                    .collect();
-
-                let mut panels = self.panels.borrow_mut();
-
-                panels.retain(|window_number, panel| {
-                    let keep = current_window_numbers.contains(window_number);
-                    if !keep {
-                        println!("[POLL] Removing panel for closed window {}", window_number);
-                        panel.orderOut(None);
-                    }
-                    keep
-                });
-
-                for window in &results.matched_windows {
-                    if !panels.contains_key(&window.window_number) {
-                        println!("\n[POLL] NEW WINDOW DETECTED:");
-                        println!("  '{}' from {}", window.title, window.app_name);
-                        println!("     App Name: {}", window.app_name);
-                        println!("     Bundle ID: {}", window.bundle_identifier.as_ref().unwrap_or(&"N/A".to_string()));
-                        println!("     Bounds: {}", window.bounds);
-                        println!("     Window Number: {}", window.window_number);
-                        println!("     PID: {}", window.pid);
-                        println!("     Layer: {}", window.layer);
-                        println!("     Alpha: {}", window.alpha);
-                        println!("     Sharing State: {}", window.sharing_state);
-                        println!("     Memory Usage: {} bytes", window.memory_usage);
-                        println!("     Is Onscreen: {}", window.is_onscreen);
-
-                        if let Some(panel) = create_overlay_panel(&window) {
-                            panels.insert(window.window_number, panel);
-                            println!("     ✓ Created overlay panel");
-                        } else {
-                            println!("     ✗ Failed to create overlay panel");
-                        }
-                    }
-                }
+fn parse_size(value: &str) -> Result<(f64, f64), String> {
+    let invalid = || format!("expected WIDTHxHEIGHT in points, got '{}'", value);
+    let (width, height) = value.split_once(['x', 'X']).ok_or_else(invalid)?;
+    let width: f64 = width.trim().parse().map_err(|_| invalid())?;
+    let height: f64 = height.trim().parse().map_err(|_| invalid())?;
+    Ok((width, height))
+}
 
-                println!("[POLL] Currently tracking {} panels", panels.len());
-            }
-            Err(e) => {
-                println!("[POLL] Error: {}", e);
-            }
+#[derive(Clone, Copy, ValueEnum)]
+enum MatchModeArg {
+    Exact,
+    Contains,
+    StartsWith,
+    Regex,
+}
+
+impl From<MatchModeArg> for MatchMode {
+    fn from(mode: MatchModeArg) -> Self {
+        match mode {
+            MatchModeArg::Exact => MatchMode::Exact,
+            MatchModeArg::Contains => MatchMode::Contains,
+            MatchModeArg::StartsWith => MatchMode::StartsWith,
+            MatchModeArg::Regex => MatchMode::Regex,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum DialogDetectionArg {
+    Title,
+    Role,
+    TitleOrRole,
+}
+
+impl From<DialogDetectionArg> for DialogDetection {
+    fn from(detection: DialogDetectionArg) -> Self {
+        match detection {
+            DialogDetectionArg::Title => DialogDetection::Title,
+            DialogDetectionArg::Role => DialogDetection::Role,
+            DialogDetectionArg::TitleOrRole => DialogDetection::TitleOrRole,
         }
     }
 }
 
+#[derive(Args)]
+struct PollArgs {
+    /// Milliseconds between window scans [default: the config file's
+    /// poll_interval_ms, else 5000].
+    #[arg(
+        long = "poll-interval",
+        value_name = "MS",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    poll_interval: Option<u64>,
+}
+
+impl PollArgs {
+    fn interval(&self, config: &Config) -> Duration {
+        self.poll_interval
+            .map(Duration::from_millis)
+            .or_else(|| config.poll_interval())
+            .unwrap_or(Duration::from_millis(DEFAULT_POLL_INTERVAL_MS))
+    }
+}
+
+#[derive(Args)]
+struct OverlayArgs {
+    #[command(flatten)]
+    filter: FilterArgs,
+
+    #[command(flatten)]
+    poll: PollArgs,
+
+    /// Between scans, keep panels on their windows as they're dragged and
+    /// close them as soon as their window closes.
+    #[arg(long)]
+    follow: bool,
+
+    /// Also draw the CG coordinate grid over every display.
+    #[arg(long)]
+    debug_grid: bool,
+
+    /// Accept line-delimited JSON commands on a Unix socket, by default
+    /// `$TMPDIR/relative-panel.sock`; see the `ipc` module.
+    #[arg(long, value_name = "PATH", num_args = 0..=1)]
+    ipc: Option<Option<PathBuf>>,
+}
+
 fn main() {
+    let cli = Cli::parse();
+    init_logging(cli.quiet);
+    check_permissions();
+    let config = load_config(cli.config.as_deref());
+
+    match cli.command.unwrap_or(Command::Overlay(cli.overlay)) {
+        Command::List { filter, json } => run_list(&filter.criteria(&config), json),
+        Command::Watch { filter, poll, json } => {
+            run_watch(filter.criteria(&config), poll.interval(&config), json)
+        }
+        Command::Overlay(options) => run_overlay(options, &config),
+        Command::Inspect { window_number } => run_inspect(WindowNumber(window_number)),
+    }
+}
+
+/// Diagnostics go to stderr through `log` so stdout carries only command
+/// output. `RUST_LOG` overrides the default level.
+fn init_logging(quiet: bool) {
+    logger(quiet, env_logger::Target::Stderr).init();
+}
+
+fn logger(quiet: bool, target: env_logger::Target) -> env_logger::Builder {
+    let level = if quiet {
+        log::LevelFilter::Warn
+    } else {
+        log::LevelFilter::Info
+    };
+    let mut builder = env_logger::Builder::new();
+    builder
+        .filter_level(level)
+        .parse_default_env()
+        .target(target)
+        .format_timestamp(None);
+    builder
+}
+
+/// Explains each missing permission and shows its system prompt, which macOS
+/// only does the first time. Carries on either way with reduced results.
+fn check_permissions() {
+    for permission in missing_permissions() {
+        log::warn!(
+            "{} permission is missing: {}. Grant it to this terminal in System \
+             Settings > Privacy & Security > {}, then restart it.",
+            permission,
+            permission.consequence(),
+            permission
+        );
+        permission.request();
+    }
+}
+
+/// An explicit `path` must exist; the default one is optional.
+fn load_config(path: Option<&Path>) -> Config {
+    let loaded = match path {
+        Some(path) => Config::load(path),
+        None => Config::load_default(),
+    };
+    loaded.unwrap_or_else(|e| exit_with_error(e))
+}
+
+fn install_interrupt_flag() -> Arc<AtomicBool> {
+    let interrupted = Arc::new(AtomicBool::new(false));
+    let interrupted_flag = Arc::clone(&interrupted);
+    if let Err(e) = ctrlc::set_handler(move || interrupted_flag.store(true, Ordering::SeqCst)) {
+        log::error!("Failed to install SIGINT handler: {}", e);
+    }
+    interrupted
+}
+
+fn run_list(criteria: &WindowSearchCriteria, json: bool) {
+    let results = match find_windows(criteria) {
+        Ok(results) => results,
+        Err(e) => exit_with_error(e),
+    };
+
+    if let Err(e) = write_list(&mut io::stdout().lock(), &results, json) {
+        exit_with_error(e);
+    }
+}
+
+fn write_list(out: &mut impl Write, results: &WindowSearchResults, json: bool) -> io::Result<()> {
+    if json {
+        serde_json::to_writer(&mut *out, results)?;
+        writeln!(out)
+    } else {
+        write!(out, "{}", results.format_tree())
+    }
+}
+
+fn write_event(out: &mut impl Write, event: &WindowEvent, json: bool) -> io::Result<()> {
+    if json {
+        writeln!(out, "{}", format_event_json(event))
+    } else {
+        writeln!(out, "{}", format_event_line(event))
+    }
+}
+
+/// Scanning happens on the monitor thread; this thread only prints, so no
+/// AppKit run loop is needed. Stops once stdout is closed, e.g. by `head`.
+fn run_watch(criteria: WindowSearchCriteria, interval: Duration, json: bool) {
+    let interrupted = install_interrupt_flag();
+    let (monitor, events) = spawn_monitor(criteria, interval);
+
+    while !interrupted.load(Ordering::SeqCst) {
+        match events.recv_timeout(INTERRUPT_CHECK_INTERVAL) {
+            Ok(event) => {
+                if write_event(&mut io::stdout().lock(), &event, json).is_err() {
+                    break;
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+    monitor.stop();
+}
+
+fn run_overlay(options: OverlayArgs, config: &Config) {
     let mtm = MainThreadMarker::new().unwrap();
     let app = NSApplication::sharedApplication(mtm);
     app.setActivationPolicy(NSApplicationActivationPolicy::Accessory);
 
-    println!("Starting panel detector with {} second polling interval...", POLL_INTERVAL_SECONDS);
-
-    let manager = PanelManager::new();
+    let interval = options.poll.interval(config);
+    log::info!(
+        "Starting panel detector with {} ms polling interval...",
+        interval.as_millis()
+    );
+
+    let manager = Rc::new(
+        config.configure(
+            PanelManager::new(mtm)
+                .with_search_criteria(options.filter.criteria(config))
+                .smooth_follow(true),
+        ),
+    );
     let manager_clone = Rc::clone(&manager);
 
     manager.check_for_windows();
 
+    let _debug_grids = if options.debug_grid {
+        show_debug_grids(&manager, mtm)
+    } else {
+        Vec::new()
+    };
+
+    let ipc = options.ipc.map(|path| {
+        let path = path.unwrap_or_else(IpcServer::default_path);
+        match IpcServer::bind(&path) {
+            Ok(server) => Rc::new(server),
+            Err(e) => exit_with_error(e),
+        }
+    });
+
+    let interrupted = install_interrupt_flag();
+
+    let display_manager = Rc::downgrade(&manager);
+    let display_watch = RefCell::new(Some(watch_display_changes(move || {
+        if let Some(manager) = display_manager.upgrade() {
+            manager.handle_display_change();
+        }
+    })));
+
+    let follow_manager = Rc::clone(&manager);
+    let ipc_manager = Rc::clone(&manager);
+    let shutdown_manager = Rc::clone(&manager);
+    let shutdown_ipc = ipc.clone();
+
     unsafe {
         NSTimer::scheduledTimerWithTimeInterval_repeats_block(
-            POLL_INTERVAL_SECONDS,
+            interval.as_secs_f64(),
             true,
             &block2::RcBlock::new(move |_timer: NonNull<NSTimer>| {
                 manager_clone.check_for_windows();
             }),
         );
-    }
 
-    println!("Starting NSApplication run loop...");
-    app.run();
-}
-
-fn create_overlay_panel(window: &window_search::WindowInfo) -> Option<Retained<NSPanel>> {
-    println!("Creating NSPanel overlay for {} window...", window.app_name);
+        // Full scans create and close panels on the poll interval; in between,
+        // `--follow` keeps existing panels on their windows as they're dragged
+        // and closes them as soon as their window goes away.
+        if options.follow {
+            NSTimer::scheduledTimerWithTimeInterval_repeats_block(
+                FOLLOW_INTERVAL_SECONDS,
+                true,
+                &block2::RcBlock::new(move |_timer: NonNull<NSTimer>| {
+                    follow_manager.follow_windows();
+                }),
+            );
+        }
 
-    let (cg_x, cg_y, orig_width, orig_height) = parse_bounds_values(&window.bounds)?;
+        if let Some(ipc) = ipc {
+            NSTimer::scheduledTimerWithTimeInterval_repeats_block(
+                IPC_CHECK_SECONDS,
+                true,
+                &block2::RcBlock::new(move |_timer: NonNull<NSTimer>| {
+                    ipc.handle_pending(&ipc_manager);
+                }),
+            );
+        }
 
-    unsafe {
-        let mtm = MainThreadMarker::new().unwrap();
-        let main_screen = NSScreen::mainScreen(mtm).unwrap();
-        let screen_frame = main_screen.frame();
-        let screen_height = screen_frame.size.height;
-        let ns_y = screen_height - cg_y - orig_height;
-
-        println!(" Screen height: {}", screen_height);
-
-        let panel_width = orig_width + 300.0;
-        let panel_height = orig_height;
-        let panel_x = cg_x;
-        let panel_y = ns_y;
-
-        println!(
-            "Original CG coords: x={}, y={}, w={}, h={}",
-            cg_x, cg_y, orig_width, orig_height
-        );
-        println!(
-            "Panel NS coords: x={}, y={}, w={}, h={}",
-            panel_x, panel_y, panel_width, panel_height
+        NSTimer::scheduledTimerWithTimeInterval_repeats_block(
+            INTERRUPT_CHECK_SECONDS,
+            true,
+            &block2::RcBlock::new(move |_timer: NonNull<NSTimer>| {
+                if interrupted.load(Ordering::SeqCst) {
+                    shutdown(
+                        &shutdown_manager,
+                        display_watch.borrow_mut().take(),
+                        shutdown_ipc.as_deref(),
+                    );
+                }
+            }),
         );
+    }
 
-        let panel_frame = NSRect::new(
-            NSPoint::new(panel_x, panel_y),
-            NSSize::new(panel_width, panel_height),
-        );
+    log::info!("Starting NSApplication run loop...");
+    app.run();
+}
 
-        let style_mask = NSWindowStyleMask::Borderless;
+fn shutdown(
+    manager: &PanelManager,
+    display_watch: Option<DisplayWatch>,
+    ipc: Option<&IpcServer>,
+) -> ! {
+    log::info!("Interrupted, closing panels...");
+    drop(display_watch);
+    if let Some(ipc) = ipc {
+        ipc.close();
+    }
+    manager.close_all();
+    std::process::exit(0);
+}
 
-        let panel = NSPanel::initWithContentRect_styleMask_backing_defer(
-            NSPanel::alloc(mtm),
-            panel_frame,
-            style_mask,
-            NSBackingStoreType::Buffered,
-            false,
-        );
+fn show_debug_grids(manager: &PanelManager, mtm: MainThreadMarker) -> Vec<Retained<NSPanel>> {
+    let windows = match find_windows(&manager.search_criteria()) {
+        Ok(results) => results.matched_windows,
+        Err(e) => {
+            log::warn!("[GRID] Error: {}", e);
+            Vec::new()
+        }
+    };
 
-        panel.setLevel(10);
-        panel.setOpaque(false);
-        panel.setAlphaValue(0.9);
-        panel.setHasShadow(true);
-        panel.setMovableByWindowBackground(true);
-        panel.setHidesOnDeactivate(false);
-        panel.setCollectionBehavior(
-            NSWindowCollectionBehavior::CanJoinAllSpaces
-                | NSWindowCollectionBehavior::Stationary,
-        );
+    let screens = manager.screens().screens();
+    let Some(primary_height) = screens.first().map(|screen| screen.frame.size.height) else {
+        return Vec::new();
+    };
 
-        let window_title = NSString::from_str("PANEL DETECTOR OVERLAY");
-        panel.setTitle(&window_title);
+    screens
+        .iter()
+        .map(|screen| create_debug_grid_panel(screen, primary_height, &windows, mtm))
+        .collect()
+}
 
-        let content_view = NSView::initWithFrame(
-            NSView::alloc(mtm),
-            NSRect::new(
-                NSPoint::new(0.0, 0.0),
-                NSSize::new(panel_width, panel_height),
-            ),
-        );
+fn run_inspect(window_number: WindowNumber) {
+    match inspect_window(window_number) {
+        Ok(entries) => println!("{}", format_window_entries(&entries)),
+        Err(e) => exit_with_error(e),
+    }
+}
 
-        panel.setContentView(Some(&content_view));
+fn exit_with_error(e: impl std::fmt::Display) -> ! {
+    eprintln!("Error: {}", e);
+    std::process::exit(1);
+}
 
-        let button_width = panel_width * 0.8;
-        let button_height = panel_height * 0.3;
-        let button_x = (panel_width - button_width) / 2.0;
-        let button_y = (panel_height - button_height) / 2.0;
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
 
-        let button_frame = NSRect::new(
-            NSPoint::new(button_x, button_y),
-            NSSize::new(button_width, button_height),
-        );
+    use log::Log;
+    use panel_detector::window_search::{WindowBounds, WindowInfo};
 
-        let button = NSButton::initWithFrame(NSButton::alloc(mtm), button_frame);
+    use super::*;
 
-        let title_str = format!("PANEL DETECTED: {}", window.app_name);
-        let title = NSString::from_str(&title_str);
-        button.setTitle(&title);
+    fn list_criteria(args: &[&str]) -> WindowSearchCriteria {
+        let cli = Cli::try_parse_from([&["panel_detector", "list"], args].concat()).unwrap();
+        match cli.command {
+            Some(Command::List { filter, .. }) => filter.criteria(&Config::default()),
+            _ => unreachable!("parsed a list command"),
+        }
+    }
 
-        content_view.addSubview(&button);
+    #[test]
+    fn listing_without_filters_finds_open_dialogs_like_the_library() {
+        let criteria = list_criteria(&[]);
+        let library = PanelManager::default_search_criteria();
 
-        let close_button_size = 30.0;
-        let close_button_margin = 10.0;
-        let close_button_frame = NSRect::new(
-            NSPoint::new(
-                panel_width - close_button_size - close_button_margin,
-                panel_height - close_button_size - close_button_margin,
-            ),
-            NSSize::new(close_button_size, close_button_size),
-        );
+        assert_eq!(criteria.to_string(), library.to_string());
+        assert_eq!(criteria.ignored_apps(), library.ignored_apps());
+    }
 
-        let close_button = NSButton::initWithFrame(NSButton::alloc(mtm), close_button_frame);
-        let close_title = NSString::from_str("✕");
-        close_button.setTitle(&close_title);
-        close_button.setTarget(Some(&panel));
-        close_button.setAction(Some(objc2::sel!(orderOut:)));
+    #[test]
+    fn listing_by_title_builds_the_library_criteria() {
+        let criteria = list_criteria(&[
+            "--title",
+            "Open",
+            "--match-mode",
+            "starts-with",
+            "--ignore",
+            "Dock",
+        ]);
+        let library = WindowSearchCriteria::new()
+            .with_ignored_apps(Config::default().ignored_apps())
+            .require_valid_bounds(true)
+            .with_title_matching(MatchMode::StartsWith, "Open")
+            .add_ignored_app("Dock");
+
+        assert_eq!(criteria.to_string(), "title starting with \"Open\"");
+        assert_eq!(criteria.to_string(), library.to_string());
+        assert_eq!(criteria.ignored_apps(), library.ignored_apps());
+    }
 
-        content_view.addSubview(&close_button);
+    #[test]
+    fn sizes_parse_as_width_by_height() {
+        assert_eq!(parse_size("50x40"), Ok((50.0, 40.0)));
+        assert_eq!(parse_size(" 1.5 X 2 "), Ok((1.5, 2.0)));
+        assert!(parse_size("50").is_err());
+        assert!(parse_size("wide x tall").is_err());
+    }
 
-        panel.makeKeyAndOrderFront(None);
-        panel.orderFrontRegardless();
+    // Log output captured in memory, standing in for stderr.
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
 
-        println!("Panel should now be visible!");
-        println!(
-            "   Panel frame: x={}, y={}, w={}, h={}",
-            panel_x, panel_y, panel_width, panel_height
-        );
+    impl Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
 
-        Some(panel)
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
     }
-}
 
-fn parse_bounds_values(bounds_str: &str) -> Option<(f64, f64, f64, f64)> {
-    let mut x = 0.0;
-    let mut y = 0.0;
-    let mut w = 0.0;
-    let mut h = 0.0;
-
-    for part in bounds_str.split(", ") {
-        if let Some(val_str) = part.strip_prefix("x:") {
-            x = val_str.parse().ok()?;
-        } else if let Some(val_str) = part.strip_prefix("y:") {
-            y = val_str.parse().ok()?;
-        } else if let Some(val_str) = part.strip_prefix("w:") {
-            w = val_str.parse().ok()?;
-        } else if let Some(val_str) = part.strip_prefix("h:") {
-            h = val_str.parse().ok()?;
+    #[test]
+    fn quiet_json_output_is_nothing_but_json() {
+        let cli = Cli::try_parse_from(["panel_detector", "--json-only", "list", "--json"]).unwrap();
+        assert!(cli.quiet);
+        let Some(Command::List { json, .. }) = cli.command else {
+            unreachable!("parsed a list command");
+        };
+        let window = WindowInfo::builder()
+            .with_app_name("TextEdit")
+            .with_title("Notes.txt")
+            .with_window_number(42)
+            .with_bounds(WindowBounds::new(0.0, 25.0, 800.0, 600.0))
+            .build();
+        let results = WindowSearchResults {
+            total_windows: 12,
+            matched_windows: vec![window.clone()],
+        };
+
+        let stderr = Captured::default();
+        let logger = logger(
+            cli.quiet,
+            env_logger::Target::Pipe(Box::new(stderr.clone())),
+        )
+        .build();
+        let log = |level, message: &str| {
+            logger.log(
+                &log::Record::builder()
+                    .level(level)
+                    .args(format_args!("{}", message))
+                    .build(),
+            )
+        };
+        let mut stdout = Vec::new();
+        log(log::Level::Info, "[POLL] Found 1 matching windows");
+        write_list(&mut stdout, &results, json).unwrap();
+        log(log::Level::Warn, "Accessibility permission is missing");
+        write_event(&mut stdout, &WindowEvent::Created(window.clone()), json).unwrap();
+        write_event(&mut stdout, &WindowEvent::Moved(window), json).unwrap();
+        logger.flush();
+
+        let stdout = String::from_utf8(stdout).unwrap();
+        assert_eq!(stdout.lines().count(), 3);
+        for line in stdout.lines() {
+            assert!(
+                serde_json::from_str::<serde_json::Value>(line).is_ok(),
+                "{}",
+                line
+            );
         }
+        assert!(!stdout.contains("[POLL]"));
+        assert!(!stdout.contains("permission"));
+        let stderr = String::from_utf8(stderr.0.lock().unwrap().clone()).unwrap();
+        assert!(stderr.contains("Accessibility permission is missing"));
+        assert!(!stderr.contains("[POLL]"));
     }
-
-    Some((x, y, w, h))
-}
-
-fn get_ignored_apps() -> HashSet<String> {
-    let mut ignored = HashSet::new();
-    ignored.insert("notification center".to_lowercase());
-    ignored.insert("notificationcenter".to_lowercase());
-    ignored.insert("sketchybar".to_lowercase());
-    ignored.insert("borders".to_lowercase());
-    ignored.insert("control center".to_lowercase());
-    ignored.insert("controlcenter".to_lowercase());
-    ignored.insert("dock".to_lowercase());
-    ignored.insert("menubar".to_lowercase());
-    ignored.insert("spotlight".to_lowercase());
-    ignored
 }
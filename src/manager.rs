@@ -0,0 +1,1262 @@
+use std::cell::{Cell, Ref, RefCell};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::ptr::NonNull;
+use std::rc::{Rc, Weak};
+use std::time::{Duration, Instant};
+
+use objc2::rc::Retained;
+use objc2::MainThreadMarker;
+use objc2_app_kit::{NSAnimatablePropertyContainer, NSAnimationContext, NSPanel};
+use objc2_foundation::{NSPoint, NSRect, NSTimer};
+
+use crate::dialog::KnownDialog;
+use crate::monitor::DEFAULT_JITTER_THRESHOLD;
+use crate::panel::{
+    cascade_frames, create_overlay_panel_with_frame, order_above_target, release_panel_callbacks,
+    schedule_dismiss, set_fullscreen_target, to_overlay_frame, update_corner_radius, update_panel,
+    PanelConfig,
+};
+use crate::screen::{ScreenInfo, ScreenProvider, SystemScreens};
+use crate::window_search::{
+    system_apps, ScanContext, WindowInfo, WindowNumber, WindowSearchCriteria,
+};
+#[cfg(feature = "accessibility")]
+use crate::{ax::WindowObserver, panel::follow_target};
+
+const DEFAULT_CASCADE_STEP: f64 = 22.0;
+const DEFAULT_FOLLOW_DURATION: Duration = Duration::from_millis(150);
+// Moves of at most MIN_DELTA points are applied directly; moves beyond MAX_DELTA
+// jump instead of sliding across the screen.
+const SMOOTH_FOLLOW_MIN_DELTA: f64 = 2.0;
+const SMOOTH_FOLLOW_MAX_DELTA: f64 = 600.0;
+
+/// What one `check_for_windows` pass did.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SessionResult {
+    pub created: usize,
+    pub updated: usize,
+    pub skipped: usize,
+    pub suppressed: usize,
+    pub pending: usize,
+}
+
+// When a window first matched, and in how many consecutive scans since.
+struct Sighting {
+    first_seen: Instant,
+    scans: u32,
+}
+
+impl Sighting {
+    fn is_mature(&self, now: Instant, min_age: Duration, min_scans: u32) -> bool {
+        self.scans >= min_scans && now.saturating_duration_since(self.first_seen) >= min_age
+    }
+}
+
+// Counts another scan for each window in `windows` and forgets the rest.
+fn record_sightings(
+    sightings: &mut HashMap<WindowNumber, Sighting>,
+    windows: &[WindowInfo],
+    now: Instant,
+) {
+    let current: HashSet<WindowNumber> = windows.iter().map(|w| w.window_number).collect();
+    sightings.retain(|window_number, _| current.contains(window_number));
+    for window_number in current {
+        sightings
+            .entry(window_number)
+            .or_insert(Sighting {
+                first_seen: now,
+                scans: 0,
+            })
+            .scans += 1;
+    }
+}
+
+// `window` cut to the visible frame of each display it touches, keyed by
+// display, for `overlay_per_screen`.
+fn screen_parts(window: &WindowInfo, screens: &[ScreenInfo]) -> Vec<(PanelKey, WindowInfo)> {
+    let Some(primary_height) = screens.first().map(|screen| screen.frame.size.height) else {
+        return Vec::new();
+    };
+
+    window
+        .screens_touched(screens)
+        .into_iter()
+        .filter_map(|index| {
+            let visible_frame = screens[index].cg_visible_frame(primary_height);
+            let mut part = window.clone();
+            part.bounds = visible_frame.intersection(&window.bounds)?;
+            let key = PanelKey {
+                window_number: window.window_number,
+                screen: Some(index),
+            };
+            Some((key, part))
+        })
+        .collect()
+}
+
+// Which matches get a panel, see `with_max_panels`, `with_min_age` and
+// `with_min_scans`.
+#[derive(Debug, Clone, Copy)]
+struct ScanPolicy {
+    max_panels: Option<usize>,
+    min_age: Duration,
+    min_scans: u32,
+}
+
+impl Default for ScanPolicy {
+    fn default() -> Self {
+        Self {
+            max_panels: None,
+            min_age: Duration::ZERO,
+            min_scans: 1,
+        }
+    }
+}
+
+// A slot to place a panel in: `window` as matched, and `target` the window (or
+// its part on one display) the panel is placed against.
+#[derive(Debug)]
+struct Slot {
+    key: PanelKey,
+    window: WindowInfo,
+    target: WindowInfo,
+}
+
+// What one scan means for the panels, decided before touching any of them.
+#[derive(Debug, Default)]
+struct ScanPlan {
+    // In scan order. Tracked slots get their panel refreshed, others a new one.
+    slots: Vec<Slot>,
+    // Slots of every window still matching, dismissed ones included; tracked
+    // panels outside them are closed.
+    current_keys: HashSet<PanelKey>,
+    pending: usize,
+    suppressed: usize,
+}
+
+impl ScanPolicy {
+    // Slots are keyed by window number, so a window keeps its panel across
+    // title changes. `sightings` must already count this scan.
+    fn plan(
+        &self,
+        mut matched_windows: Vec<WindowInfo>,
+        now: Instant,
+        sightings: &HashMap<WindowNumber, Sighting>,
+        tracked: &HashSet<PanelKey>,
+        dismissed: &HashSet<PanelKey>,
+        panel_targets: impl Fn(&WindowInfo) -> Vec<(PanelKey, WindowInfo)>,
+    ) -> ScanPlan {
+        let mut plan = ScanPlan::default();
+
+        if let Some(max_panels) = self.max_panels {
+            if matched_windows.len() > max_panels {
+                plan.suppressed = matched_windows.len() - max_panels;
+                matched_windows.truncate(max_panels);
+            }
+        }
+
+        for window in matched_windows {
+            let has_panel = tracked
+                .iter()
+                .any(|key| key.window_number == window.window_number);
+            let is_mature = sightings
+                .get(&window.window_number)
+                .is_some_and(|sighting| sighting.is_mature(now, self.min_age, self.min_scans));
+            if !has_panel && !is_mature {
+                plan.pending += 1;
+                continue;
+            }
+
+            for (key, target) in panel_targets(&window) {
+                plan.current_keys.insert(key);
+                if !dismissed.contains(&key) {
+                    plan.slots.push(Slot {
+                        key,
+                        window: window.clone(),
+                        target,
+                    });
+                }
+            }
+        }
+
+        plan
+    }
+}
+
+/// Identifies one panel for as long as its manager tracks it. Never reused, so
+/// a stale id simply matches nothing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PanelId(pub u64);
+
+impl fmt::Display for PanelId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+// One overlay slot: the whole window, or with `overlay_per_screen` the part of
+// it on one display.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct PanelKey {
+    window_number: WindowNumber,
+    screen: Option<usize>,
+}
+
+struct TrackedPanel {
+    id: PanelId,
+    panel: Retained<NSPanel>,
+    // The window as of the last update applied to `panel`.
+    window: WindowInfo,
+    // How far `panel` sits from its placement against `window`, i.e. the
+    // cascade step it was given; `follow_windows` keeps it.
+    offset: (f64, f64),
+    _dismiss_timer: Option<CancelOnDrop<Retained<NSTimer>>>,
+    // Moves an attached panel between scans; see `follow_target`.
+    #[cfg(feature = "accessibility")]
+    _observer: Option<WindowObserver>,
+}
+
+impl Drop for TrackedPanel {
+    fn drop(&mut self) {
+        release_panel_callbacks(&self.panel);
+    }
+}
+
+// A pending action, such as an auto-dismiss timer.
+trait Cancel {
+    fn cancel(&self);
+}
+
+impl Cancel for Retained<NSTimer> {
+    fn cancel(&self) {
+        self.invalidate();
+    }
+}
+
+// Cancels `T` when dropped, so a panel closed before its auto-dismiss fires
+// isn't dismissed again later.
+struct CancelOnDrop<T: Cancel>(T);
+
+impl<T: Cancel> Drop for CancelOnDrop<T> {
+    fn drop(&mut self) {
+        self.0.cancel();
+    }
+}
+
+// The auto-dismiss for a new panel, if its config has one; `schedule` starts
+// the timer.
+fn dismiss_timer<T: Cancel>(
+    after: Option<Duration>,
+    schedule: impl FnOnce(Duration) -> T,
+) -> Option<CancelOnDrop<T>> {
+    after.map(|after| CancelOnDrop(schedule(after)))
+}
+
+// What an auto-dismiss does once its timer fires: remembers `key` so its panel
+// isn't recreated, and stops tracking the panel, which is returned for the
+// caller to drop. Either may be gone by then with the manager.
+fn dismiss_slot<T>(
+    panels: &Weak<RefCell<HashMap<PanelKey, T>>>,
+    dismissed: &Weak<RefCell<HashSet<PanelKey>>>,
+    key: PanelKey,
+) -> Option<T> {
+    if let Some(dismissed) = dismissed.upgrade() {
+        dismissed.borrow_mut().insert(key);
+    }
+    panels
+        .upgrade()
+        .and_then(|panels| panels.borrow_mut().remove(&key))
+}
+
+/// Keeps one overlay panel per window matching its criteria, creating, moving
+/// and closing panels as `check_for_windows` rescans. Main thread only.
+pub struct PanelManager {
+    // Keyed by window number, a window's only identity (plus the display with
+    // `overlay_per_screen`). Titles change under a tracked window (e.g.
+    // dropping "Edited" on save), which just refreshes the existing panel
+    // through `update_panel`.
+    panels: Rc<RefCell<HashMap<PanelKey, TrackedPanel>>>,
+    // Slots whose panel auto-dismissed or was closed by id; not recreated
+    // while the window stays.
+    dismissed: Rc<RefCell<HashSet<PanelKey>>>,
+    next_id: Cell<u64>,
+    // Set by `hide_all`; panels created meanwhile start hidden too.
+    hidden: Cell<bool>,
+    // Replaceable while running, see `set_search_criteria`.
+    search_criteria: RefCell<WindowSearchCriteria>,
+    screens: Box<dyn ScreenProvider>,
+    panel_config: PanelConfig,
+    // (app name or bundle id, config), checked in order before `panel_config`.
+    app_panel_configs: Vec<(String, PanelConfig)>,
+    cascade: bool,
+    cascade_step: f64,
+    smooth_follow: bool,
+    follow_duration: Duration,
+    policy: ScanPolicy,
+    sightings: RefCell<HashMap<WindowNumber, Sighting>>,
+    scan_context: RefCell<ScanContext>,
+    jitter_threshold: f64,
+    overlay_per_screen: bool,
+    #[cfg(feature = "accessibility")]
+    mtm: MainThreadMarker,
+}
+
+impl PanelManager {
+    /// Watches for Open dialogs on the attached displays until configured
+    /// otherwise.
+    pub fn new(mtm: MainThreadMarker) -> Self {
+        Self {
+            panels: Rc::new(RefCell::new(HashMap::new())),
+            dismissed: Rc::new(RefCell::new(HashSet::new())),
+            next_id: Cell::new(1),
+            hidden: Cell::new(false),
+            search_criteria: RefCell::new(Self::default_search_criteria()),
+            screens: Box::new(SystemScreens::new(mtm)),
+            panel_config: PanelConfig::new(),
+            app_panel_configs: Vec::new(),
+            cascade: true,
+            cascade_step: DEFAULT_CASCADE_STEP,
+            smooth_follow: false,
+            follow_duration: DEFAULT_FOLLOW_DURATION,
+            policy: ScanPolicy::default(),
+            sightings: RefCell::new(HashMap::new()),
+            scan_context: RefCell::new(ScanContext::new()),
+            jitter_threshold: DEFAULT_JITTER_THRESHOLD,
+            overlay_per_screen: false,
+            #[cfg(feature = "accessibility")]
+            mtm,
+        }
+    }
+
+    /// Open dialogs outside `system_apps()`, with valid bounds.
+    pub fn default_search_criteria() -> WindowSearchCriteria {
+        WindowSearchCriteria::new()
+            .with_known_dialog(KnownDialog::Open)
+            .with_ignored_apps(system_apps())
+            .require_valid_bounds(true)
+    }
+
+    pub fn with_search_criteria(self, criteria: WindowSearchCriteria) -> Self {
+        self.search_criteria.replace(criteria);
+        self
+    }
+
+    /// Switches to other windows from the next scan on, which closes the
+    /// panels of windows that no longer match.
+    pub fn set_search_criteria(&self, criteria: WindowSearchCriteria) {
+        self.search_criteria.replace(criteria);
+    }
+
+    pub fn with_panel_config(mut self, config: PanelConfig) -> Self {
+        self.panel_config = config;
+        self
+    }
+
+    /// Uses `config` instead of the main panel config for windows whose app
+    /// name or bundle id is `app`, ignoring case. The first matching override
+    /// wins.
+    pub fn with_app_panel_config(mut self, app: impl Into<String>, config: PanelConfig) -> Self {
+        self.app_panel_configs.push((app.into(), config));
+        self
+    }
+
+    fn panel_config_for(&self, window: &WindowInfo) -> &PanelConfig {
+        self.app_panel_configs
+            .iter()
+            .find(|(app, _)| {
+                window.app_name.eq_ignore_ascii_case(app)
+                    || window
+                        .bundle_identifier
+                        .as_deref()
+                        .is_some_and(|id| id.eq_ignore_ascii_case(app))
+            })
+            .map_or(&self.panel_config, |(_, config)| config)
+    }
+
+    pub fn with_screens(mut self, screens: impl ScreenProvider + 'static) -> Self {
+        self.screens = Box::new(screens);
+        self
+    }
+
+    pub fn search_criteria(&self) -> Ref<'_, WindowSearchCriteria> {
+        self.search_criteria.borrow()
+    }
+
+    pub fn screens(&self) -> &dyn ScreenProvider {
+        self.screens.as_ref()
+    }
+
+    pub fn cascade(mut self, enabled: bool) -> Self {
+        self.cascade = enabled;
+        self
+    }
+
+    pub fn with_cascade_step(mut self, step: f64) -> Self {
+        self.cascade_step = step;
+        self
+    }
+
+    pub fn smooth_follow(mut self, enabled: bool) -> Self {
+        self.smooth_follow = enabled;
+        self
+    }
+
+    pub fn with_follow_duration(mut self, duration: Duration) -> Self {
+        self.follow_duration = duration;
+        self
+    }
+
+    /// Only the first `max_panels` matches get a panel; the rest are counted as
+    /// suppressed.
+    pub fn with_max_panels(mut self, max_panels: usize) -> Self {
+        self.policy.max_panels = Some(max_panels);
+        self
+    }
+
+    /// A window must have matched for at least this long before it gets a
+    /// panel, so transient windows such as splash screens are left alone.
+    pub fn with_min_age(mut self, min_age: Duration) -> Self {
+        self.policy.min_age = min_age;
+        self
+    }
+
+    /// A window must have matched in this many consecutive scans before it
+    /// gets a panel.
+    pub fn with_min_scans(mut self, min_scans: u32) -> Self {
+        self.policy.min_scans = min_scans;
+        self
+    }
+
+    /// Panels only follow their window once its frame is more than `threshold`
+    /// points away from the panel's, so CG jitter during drags is ignored.
+    pub fn with_jitter_threshold(mut self, threshold: f64) -> Self {
+        self.jitter_threshold = threshold;
+        self
+    }
+
+    /// By default a window gets a single overlay on the display showing most of
+    /// it. With this set, a window spanning displays gets one overlay per
+    /// display it touches, each placed against that display's part of it.
+    pub fn overlay_per_screen(mut self, enabled: bool) -> Self {
+        self.overlay_per_screen = enabled;
+        self
+    }
+
+    // The windows to place overlays against, one per panel slot.
+    fn panel_targets(&self, window: &WindowInfo) -> Vec<(PanelKey, WindowInfo)> {
+        let window_number = window.window_number;
+        if !self.overlay_per_screen {
+            let key = PanelKey {
+                window_number,
+                screen: None,
+            };
+            return vec![(key, window.clone())];
+        }
+
+        screen_parts(window, &self.screens.screens())
+    }
+
+    fn reposition_panel(&self, panel: &NSPanel, frame: NSRect, config: &PanelConfig) -> bool {
+        match follow_step(
+            panel.frame(),
+            frame,
+            self.jitter_threshold,
+            self.smooth_follow,
+        ) {
+            FollowStep::Stay => return false,
+            FollowStep::Animate => {
+                let duration = self.follow_duration.as_secs_f64();
+                NSAnimationContext::runAnimationGroup(&block2::StackBlock::new(
+                    move |context: NonNull<NSAnimationContext>| {
+                        unsafe { context.as_ref() }.setDuration(duration);
+                        panel.animator().setFrame_display(frame, true);
+                    },
+                ));
+            }
+            FollowStep::Snap => panel.setFrame_display(frame, true),
+        }
+        update_corner_radius(panel, config, frame.size);
+        true
+    }
+
+    /// An observer keeping an attached panel on `window` between scans. Not
+    /// for `overlay_per_screen` slots, whose panels only cover part of it.
+    #[cfg(feature = "accessibility")]
+    fn observe_target(
+        &self,
+        panel: &Retained<NSPanel>,
+        key: PanelKey,
+        window: &WindowInfo,
+        config: &PanelConfig,
+    ) -> Option<WindowObserver> {
+        if !config.is_attached_to_target() || key.screen.is_some() {
+            return None;
+        }
+        follow_target(panel, window, config, self.mtm)
+            .inspect_err(|e| {
+                log::warn!(
+                    "[POLL] Panel for window {} will only follow on scans: {}",
+                    window.window_number,
+                    e
+                )
+            })
+            .ok()
+    }
+
+    fn schedule_dismiss(
+        &self,
+        panel: &Retained<NSPanel>,
+        key: PanelKey,
+        after: Duration,
+    ) -> Retained<NSTimer> {
+        let panels = Rc::downgrade(&self.panels);
+        let dismissed = Rc::downgrade(&self.dismissed);
+        schedule_dismiss(panel, after, move || {
+            log::info!(
+                "[POLL] Auto-dismissing panel for window {}",
+                key.window_number
+            );
+            // Dropped here, after the borrow ends.
+            drop(dismiss_slot(&panels, &dismissed, key));
+        })
+    }
+
+    /// Re-reads display geometry and places every panel against it.
+    pub fn handle_display_change(&self) {
+        log::info!("[DISPLAY] Display configuration changed, repositioning panels");
+        self.screens.invalidate();
+        self.check_for_windows();
+    }
+
+    pub fn close_all(&self) {
+        for (_, tracked) in self.panels.borrow_mut().drain() {
+            tracked.panel.orderOut(None);
+        }
+    }
+
+    /// Closes one panel. Like an auto-dismissed panel it isn't recreated while
+    /// its window stays open. Returns whether `id` was tracked.
+    pub fn close(&self, id: PanelId) -> bool {
+        let mut panels = self.panels.borrow_mut();
+        let Some(key) = panels
+            .iter()
+            .find(|(_, tracked)| tracked.id == id)
+            .map(|(key, _)| *key)
+        else {
+            return false;
+        };
+        self.dismissed.borrow_mut().insert(key);
+        if let Some(tracked) = panels.remove(&key) {
+            tracked.panel.orderOut(None);
+        }
+        true
+    }
+
+    /// Orders every panel out without closing it. Panels keep following their
+    /// windows while hidden, and new ones start hidden until `show_all`.
+    pub fn hide_all(&self) {
+        self.hidden.set(true);
+        for tracked in self.panels.borrow().values() {
+            tracked.panel.orderOut(None);
+        }
+    }
+
+    pub fn show_all(&self) {
+        self.hidden.set(false);
+        for tracked in self.panels.borrow().values() {
+            tracked.panel.orderFrontRegardless();
+        }
+    }
+
+    pub fn is_hidden(&self) -> bool {
+        self.hidden.get()
+    }
+
+    /// Every tracked panel's id, in creation order.
+    pub fn panel_ids(&self) -> Vec<PanelId> {
+        let mut ids: Vec<PanelId> = self.panels.borrow().values().map(|t| t.id).collect();
+        ids.sort();
+        ids
+    }
+
+    /// Every tracked panel's id with its window as last updated, in creation
+    /// order.
+    pub fn tracked_windows(&self) -> Vec<(PanelId, WindowInfo)> {
+        let mut tracked: Vec<_> = self
+            .panels
+            .borrow()
+            .values()
+            .map(|tracked| (tracked.id, tracked.window.clone()))
+            .collect();
+        tracked.sort_by_key(|(id, _)| *id);
+        tracked
+    }
+
+    pub fn get(&self, id: PanelId) -> Option<Retained<NSPanel>> {
+        self.panels
+            .borrow()
+            .values()
+            .find(|tracked| tracked.id == id)
+            .map(|tracked| tracked.panel.clone())
+    }
+
+    /// The panels over `window_number`: one, or with `overlay_per_screen` one
+    /// per display it spans, in display order.
+    pub fn get_by_target(&self, window_number: WindowNumber) -> Vec<(PanelId, Retained<NSPanel>)> {
+        let panels = self.panels.borrow();
+        let mut found: Vec<_> = panels
+            .iter()
+            .filter(|(key, _)| key.window_number == window_number)
+            .map(|(key, tracked)| (key.screen, tracked.id, tracked.panel.clone()))
+            .collect();
+        found.sort_by_key(|(screen, _, _)| *screen);
+        found
+            .into_iter()
+            .map(|(_, id, panel)| (id, panel))
+            .collect()
+    }
+
+    fn allocate_id(&self) -> PanelId {
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+        PanelId(id)
+    }
+
+    // Orders out and drops every panel `keep` rejects.
+    fn close_panels(
+        &self,
+        panels: &mut HashMap<PanelKey, TrackedPanel>,
+        keep: impl Fn(&PanelKey) -> bool,
+    ) {
+        let tracked_before = panels.len();
+        panels.retain(|key, tracked| {
+            let keep = keep(key);
+            if !keep {
+                log::info!("[POLL] Removing panel for window {}", key.window_number);
+                tracked.panel.orderOut(None);
+            }
+            keep
+        });
+        if panels.len() < tracked_before {
+            self.scan_context.borrow_mut().evict_exited();
+        }
+    }
+
+    /// Moves tracked panels after their windows, keeping each panel's offset
+    /// from its placement, and closes those whose window is gone, without
+    /// creating or refreshing any. Cheap enough to run on a short timer
+    /// between `check_for_windows` passes. Returns how many panels moved.
+    pub fn follow_windows(&self) -> usize {
+        let scan = self
+            .scan_context
+            .borrow_mut()
+            .find_windows(&self.search_criteria.borrow());
+        let results = match scan {
+            Ok(results) => results,
+            Err(e) => {
+                log::warn!("[FOLLOW] Error: {}", e);
+                return 0;
+            }
+        };
+
+        let mut panels = self.panels.borrow_mut();
+        let current: HashSet<WindowNumber> = results
+            .matched_windows
+            .iter()
+            .map(|window| window.window_number)
+            .collect();
+        self.close_panels(&mut panels, |key| current.contains(&key.window_number));
+
+        let mut moved = 0;
+        for window in &results.matched_windows {
+            for (key, target) in self.panel_targets(window) {
+                let Some(tracked) = panels.get(&key) else {
+                    continue;
+                };
+                let config = self.panel_config_for(window);
+                let Ok(placement) = to_overlay_frame(&target, self.screens.as_ref(), config) else {
+                    continue;
+                };
+                let (dx, dy) = tracked.offset;
+                let frame = NSRect::new(
+                    NSPoint::new(placement.origin.x + dx, placement.origin.y + dy),
+                    placement.size,
+                );
+                if self.reposition_panel(&tracked.panel, frame, config) {
+                    log::debug!("[FOLLOW] Moved panel for window {}", key.window_number);
+                    moved += 1;
+                }
+                if config.is_attached_to_target() {
+                    order_above_target(&tracked.panel, key.window_number);
+                }
+            }
+        }
+        moved
+    }
+
+    pub fn check_for_windows(&self) -> SessionResult {
+        log::info!("[POLL] Searching for {}...", self.search_criteria.borrow());
+
+        let mut session = SessionResult::default();
+
+        let scan = self
+            .scan_context
+            .borrow_mut()
+            .find_windows(&self.search_criteria.borrow());
+        match scan {
+            Ok(results) => {
+                log::info!("[POLL] Scanned {} total windows", results.total_windows);
+
+                let now = Instant::now();
+                record_sightings(
+                    &mut self.sightings.borrow_mut(),
+                    &results.matched_windows,
+                    now,
+                );
+
+                let mut panels = self.panels.borrow_mut();
+                let mut dismissed = self.dismissed.borrow_mut();
+                let tracked: HashSet<PanelKey> = panels.keys().copied().collect();
+                let plan = self.policy.plan(
+                    results.matched_windows,
+                    now,
+                    &self.sightings.borrow(),
+                    &tracked,
+                    &dismissed,
+                    |window| self.panel_targets(window),
+                );
+                session.pending = plan.pending;
+                session.suppressed = plan.suppressed;
+                if let Some(max_panels) = self.policy.max_panels.filter(|_| plan.suppressed > 0) {
+                    log::info!(
+                        "[POLL] Suppressing {} windows over the {} panel limit",
+                        plan.suppressed,
+                        max_panels
+                    );
+                }
+
+                dismissed.retain(|key| plan.current_keys.contains(key));
+                drop(dismissed);
+
+                self.close_panels(&mut panels, |key| plan.current_keys.contains(key));
+
+                let mut slots = Vec::new();
+                let mut frames = Vec::new();
+                for slot in plan.slots {
+                    let config = self.panel_config_for(&slot.window);
+                    match to_overlay_frame(&slot.target, self.screens.as_ref(), config) {
+                        Ok(frame) => {
+                            slots.push(slot);
+                            frames.push(frame);
+                        }
+                        Err(e) => log::warn!(
+                            "[POLL] Could not place a panel for window {}: {}",
+                            slot.key.window_number,
+                            e
+                        ),
+                    }
+                }
+
+                let placements = frames.clone();
+                if self.cascade {
+                    // Highlights outline their window exactly; only panels move.
+                    let cascading: Vec<usize> = (0..slots.len())
+                        .filter(|&i| !self.panel_config_for(&slots[i].window).is_highlight())
+                        .collect();
+                    let mut cascaded: Vec<NSRect> = cascading.iter().map(|&i| frames[i]).collect();
+                    cascade_frames(&mut cascaded, self.cascade_step);
+                    for (i, frame) in cascading.into_iter().zip(cascaded) {
+                        frames[i] = frame;
+                    }
+                }
+
+                let screens = self.screens.screens();
+                for ((slot, frame), placement) in slots.iter().zip(frames).zip(placements) {
+                    let (key, window) = (slot.key, &slot.window);
+                    let offset = (
+                        frame.origin.x - placement.origin.x,
+                        frame.origin.y - placement.origin.y,
+                    );
+                    let fullscreen = window.is_fullscreen(&screens);
+                    let config = self.panel_config_for(window);
+                    match panels.entry(key) {
+                        Entry::Occupied(mut entry) => {
+                            let tracked = entry.get_mut();
+                            set_fullscreen_target(&tracked.panel, fullscreen);
+                            let moved = self.reposition_panel(&tracked.panel, frame, config);
+                            if config.is_attached_to_target() {
+                                order_above_target(&tracked.panel, key.window_number);
+                            }
+                            let refreshed =
+                                update_panel(&tracked.panel, &tracked.window, window, config);
+                            tracked.window = window.clone();
+                            tracked.offset = offset;
+                            if moved || refreshed {
+                                session.updated += 1;
+                            } else {
+                                session.skipped += 1;
+                            }
+                        }
+                        Entry::Vacant(entry) => {
+                            log::info!("[POLL] NEW WINDOW DETECTED:");
+                            log::info!("  '{}' from {}", window.display_title(), window.app_name);
+                            log::info!("     App Name: {}", window.app_name);
+                            log::info!(
+                                "     Bundle ID: {}",
+                                window
+                                    .bundle_identifier
+                                    .as_ref()
+                                    .unwrap_or(&"N/A".to_string())
+                            );
+                            log::info!("     Bounds: {}", window.bounds);
+                            log::info!("     Window Number: {}", window.window_number);
+                            log::info!("     PID: {}", window.pid);
+                            log::info!("     Layer: {}", window.layer);
+                            log::info!("     Alpha: {}", window.alpha);
+                            log::info!("     Sharing State: {}", window.sharing_state);
+                            log::info!("     Memory Usage: {} bytes", window.memory_usage);
+                            log::info!("     Is Onscreen: {}", window.is_onscreen);
+
+                            match create_overlay_panel_with_frame(window, frame, config) {
+                                Ok(panel) => {
+                                    if self.hidden.get() {
+                                        panel.orderOut(None);
+                                    }
+                                    set_fullscreen_target(&panel, fullscreen);
+                                    let dismiss_timer =
+                                        dismiss_timer(config.auto_dismiss_after(), |after| {
+                                            self.schedule_dismiss(&panel, key, after)
+                                        });
+                                    #[cfg(feature = "accessibility")]
+                                    let observer = self.observe_target(&panel, key, window, config);
+                                    entry.insert(TrackedPanel {
+                                        id: self.allocate_id(),
+                                        panel,
+                                        window: window.clone(),
+                                        offset,
+                                        _dismiss_timer: dismiss_timer,
+                                        #[cfg(feature = "accessibility")]
+                                        _observer: observer,
+                                    });
+                                    session.created += 1;
+                                    log::info!("     ✓ Created overlay panel");
+                                }
+                                Err(e) => {
+                                    log::warn!("     ✗ Failed to create overlay panel: {}", e)
+                                }
+                            }
+                        }
+                    }
+                }
+
+                log::info!(
+                    "[POLL] Created {}, updated {}, unchanged {}, pending {}",
+                    session.created,
+                    session.updated,
+                    session.skipped,
+                    session.pending
+                );
+                log::info!("[POLL] Currently tracking {} panels", panels.len());
+            }
+            Err(e) => {
+                log::warn!("[POLL] Error: {}", e);
+            }
+        }
+
+        session
+    }
+}
+
+// How a panel at `current` follows its window to `target`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FollowStep {
+    /// Within the jitter threshold; the panel stays put.
+    Stay,
+    Animate,
+    /// Moves at once: smooth follow is off, or the change is too small to
+    /// see animate or so large the slide would crawl across screens.
+    Snap,
+}
+
+fn follow_step(
+    current: NSRect,
+    target: NSRect,
+    jitter_threshold: f64,
+    smooth_follow: bool,
+) -> FollowStep {
+    let delta = frame_delta(current, target);
+    if delta <= jitter_threshold {
+        FollowStep::Stay
+    } else if smooth_follow && delta > SMOOTH_FOLLOW_MIN_DELTA && delta <= SMOOTH_FOLLOW_MAX_DELTA {
+        FollowStep::Animate
+    } else {
+        FollowStep::Snap
+    }
+}
+
+// The largest change in any of origin x/y, width or height.
+fn frame_delta(from: NSRect, to: NSRect) -> f64 {
+    (from.origin.x - to.origin.x)
+        .abs()
+        .max((from.origin.y - to.origin.y).abs())
+        .max((from.size.width - to.size.width).abs())
+        .max((from.size.height - to.size.height).abs())
+}
+
+#[cfg(test)]
+mod tests {
+    use objc2_foundation::NSSize;
+
+    use super::*;
+    use crate::screen::tests::three_screens;
+    use crate::window_search::WindowBounds;
+
+    fn key(window_number: i64) -> PanelKey {
+        PanelKey {
+            window_number: WindowNumber(window_number),
+            screen: None,
+        }
+    }
+
+    fn window(window_number: i64, title: &str) -> WindowInfo {
+        WindowInfo::builder()
+            .with_window_number(window_number)
+            .with_title(title)
+            .build()
+    }
+
+    // The slots of a manager without `overlay_per_screen`.
+    fn whole_window(window: &WindowInfo) -> Vec<(PanelKey, WindowInfo)> {
+        vec![(key(window.window_number.0), window.clone())]
+    }
+
+    // The bookkeeping `check_for_windows` does around `ScanPolicy::plan`,
+    // with the panels reduced to their keys.
+    #[derive(Default)]
+    struct Session {
+        policy: ScanPolicy,
+        sightings: HashMap<WindowNumber, Sighting>,
+        tracked: HashSet<PanelKey>,
+        dismissed: HashSet<PanelKey>,
+    }
+
+    impl Session {
+        // Returns the plan and how many of its slots got a new panel.
+        fn scan(&mut self, windows: Vec<WindowInfo>, now: Instant) -> (ScanPlan, usize) {
+            record_sightings(&mut self.sightings, &windows, now);
+            let plan = self.policy.plan(
+                windows,
+                now,
+                &self.sightings,
+                &self.tracked,
+                &self.dismissed,
+                whole_window,
+            );
+            self.tracked.retain(|key| plan.current_keys.contains(key));
+            let created = plan
+                .slots
+                .iter()
+                .filter(|slot| self.tracked.insert(slot.key))
+                .count();
+            (plan, created)
+        }
+    }
+
+    fn rect(x: f64, y: f64, width: f64, height: f64) -> NSRect {
+        NSRect::new(NSPoint::new(x, y), NSSize::new(width, height))
+    }
+
+    #[test]
+    fn follow_animates_moderate_moves_and_snaps_the_rest() {
+        let current = rect(100.0, 100.0, 400.0, 300.0);
+        let step = |target| follow_step(current, target, 0.0, true);
+
+        assert_eq!(step(current), FollowStep::Stay);
+        assert_eq!(step(rect(101.5, 100.0, 400.0, 300.0)), FollowStep::Snap);
+        assert_eq!(step(rect(102.0, 100.0, 400.0, 300.0)), FollowStep::Snap);
+        assert_eq!(step(rect(102.5, 100.0, 400.0, 300.0)), FollowStep::Animate);
+        assert_eq!(step(rect(100.0, 100.0, 400.0, 900.0)), FollowStep::Animate);
+        assert_eq!(step(rect(700.0, 100.0, 400.0, 300.0)), FollowStep::Animate);
+        assert_eq!(step(rect(700.5, 100.0, 400.0, 300.0)), FollowStep::Snap);
+        assert_eq!(step(rect(-2500.0, 100.0, 400.0, 300.0)), FollowStep::Snap);
+    }
+
+    #[test]
+    fn follow_snaps_without_smoothing_and_ignores_jitter() {
+        let current = rect(100.0, 100.0, 400.0, 300.0);
+
+        assert_eq!(
+            follow_step(current, rect(150.0, 100.0, 400.0, 300.0), 1.0, false),
+            FollowStep::Snap
+        );
+        assert_eq!(
+            follow_step(current, rect(101.0, 99.0, 400.5, 300.0), 1.0, true),
+            FollowStep::Stay
+        );
+    }
+
+    #[test]
+    fn following_a_drag_converges_on_the_window() {
+        let targets = [
+            rect(110.0, 100.0, 400.0, 300.0),
+            rect(130.0, 105.0, 400.0, 300.0),
+            rect(130.4, 105.0, 400.0, 300.0),
+            rect(1600.0, 105.0, 400.0, 300.0),
+        ];
+        let mut panel = rect(100.0, 100.0, 400.0, 300.0);
+        let mut steps = Vec::new();
+
+        for target in targets {
+            let step = follow_step(panel, target, 1.0, true);
+            if step != FollowStep::Stay {
+                panel = target;
+            }
+            steps.push(step);
+        }
+
+        assert_eq!(
+            steps,
+            [
+                FollowStep::Animate,
+                FollowStep::Animate,
+                FollowStep::Stay,
+                FollowStep::Snap,
+            ]
+        );
+        assert_eq!(panel, targets[3]);
+        assert_eq!(follow_step(panel, targets[3], 1.0, true), FollowStep::Stay);
+    }
+
+    #[test]
+    fn rescanning_identical_windows_creates_no_panels() {
+        let mut session = Session::default();
+        let now = Instant::now();
+        let windows = vec![window(1, "Open"), window(2, "Save")];
+
+        let (first, created) = session.scan(windows.clone(), now);
+        assert_eq!(created, 2);
+
+        let (second, created) = session.scan(windows, now);
+        assert_eq!(created, 0);
+        let keys = |plan: &ScanPlan| plan.slots.iter().map(|slot| slot.key).collect::<Vec<_>>();
+        assert_eq!(keys(&second), keys(&first));
+        assert_eq!(session.tracked, HashSet::from([key(1), key(2)]));
+
+        let (_, created) = session.scan(vec![window(2, "Save")], now);
+        assert_eq!(created, 0);
+        assert_eq!(session.tracked, HashSet::from([key(2)]));
+    }
+
+    #[test]
+    fn max_panels_keeps_the_first_matches_and_counts_the_rest() {
+        let mut session = Session {
+            policy: ScanPolicy {
+                max_panels: Some(2),
+                ..ScanPolicy::default()
+            },
+            ..Session::default()
+        };
+        let windows: Vec<WindowInfo> = (1..=5).map(|n| window(n, "Open")).collect();
+
+        let (plan, created) = session.scan(windows, Instant::now());
+
+        assert_eq!(plan.suppressed, 3);
+        assert_eq!(created, 2);
+        let keys: Vec<PanelKey> = plan.slots.iter().map(|slot| slot.key).collect();
+        assert_eq!(keys, [key(1), key(2)]);
+        assert_eq!(plan.current_keys, HashSet::from([key(1), key(2)]));
+    }
+
+    #[test]
+    fn max_panels_suppresses_nothing_at_the_cap() {
+        let mut session = Session {
+            policy: ScanPolicy {
+                max_panels: Some(2),
+                ..ScanPolicy::default()
+            },
+            ..Session::default()
+        };
+
+        let (plan, created) =
+            session.scan(vec![window(1, "Open"), window(2, "Open")], Instant::now());
+
+        assert_eq!(plan.suppressed, 0);
+        assert_eq!(created, 2);
+    }
+
+    #[test]
+    fn title_change_keeps_the_tracked_panel() {
+        let mut session = Session::default();
+        let now = Instant::now();
+
+        let (_, created) = session.scan(vec![window(7, "report.txt — Edited")], now);
+        assert_eq!(created, 1);
+
+        let (plan, created) = session.scan(vec![window(7, "report.txt")], now);
+        assert_eq!(created, 0);
+        assert_eq!(plan.slots.len(), 1);
+        assert_eq!(plan.slots[0].key, key(7));
+        assert_eq!(plan.slots[0].window.title.as_deref(), Some("report.txt"));
+        assert_eq!(session.tracked, HashSet::from([key(7)]));
+        assert_eq!(session.sightings[&WindowNumber(7)].scans, 2);
+    }
+
+    #[test]
+    fn min_scans_delays_panels_until_enough_consecutive_sightings() {
+        let mut session = Session {
+            policy: ScanPolicy {
+                min_scans: 3,
+                ..ScanPolicy::default()
+            },
+            ..Session::default()
+        };
+        let now = Instant::now();
+        let mut scan = |windows| {
+            let (plan, created) = session.scan(windows, now);
+            (plan.pending, created)
+        };
+
+        assert_eq!(scan(vec![window(1, "Open"), window(2, "Saving")]), (2, 0));
+        assert_eq!(scan(vec![window(1, "Open")]), (1, 0));
+        assert_eq!(scan(vec![window(1, "Open"), window(2, "Saving")]), (1, 1));
+        assert_eq!(scan(vec![window(1, "Open"), window(2, "Saving")]), (1, 0));
+        assert_eq!(scan(vec![window(1, "Open"), window(2, "Saving")]), (0, 1));
+    }
+
+    #[test]
+    fn min_age_delays_panels_until_the_window_is_old_enough() {
+        let mut session = Session {
+            policy: ScanPolicy {
+                min_age: Duration::from_millis(500),
+                ..ScanPolicy::default()
+            },
+            ..Session::default()
+        };
+        let start = Instant::now();
+        let mut scan_at = |millis| {
+            let (plan, created) = session.scan(
+                vec![window(1, "Open")],
+                start + Duration::from_millis(millis),
+            );
+            (plan.pending, created)
+        };
+
+        assert_eq!(scan_at(0), (1, 0));
+        assert_eq!(scan_at(499), (1, 0));
+        assert_eq!(scan_at(500), (0, 1));
+        assert_eq!(scan_at(900), (0, 0));
+    }
+
+    #[test]
+    fn windows_spanning_displays_get_a_part_per_display() {
+        let screens = three_screens().screens;
+        let spanning = WindowInfo::builder()
+            .with_window_number(3)
+            .with_bounds(WindowBounds::new(-200.0, 100.0, 600.0, 300.0))
+            .build();
+
+        assert_eq!(spanning.screens_touched(&screens), [0, 2]);
+        assert_eq!(spanning.screen_index(&screens), Some(0));
+
+        let parts: Vec<(PanelKey, WindowBounds)> = screen_parts(&spanning, &screens)
+            .into_iter()
+            .map(|(key, part)| (key, part.bounds))
+            .collect();
+        let on_screen = |screen| PanelKey {
+            window_number: WindowNumber(3),
+            screen: Some(screen),
+        };
+        assert_eq!(
+            parts,
+            [
+                (on_screen(0), WindowBounds::new(0.0, 100.0, 400.0, 300.0)),
+                (on_screen(2), WindowBounds::new(-200.0, 100.0, 200.0, 300.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn windows_on_one_display_get_a_single_part() {
+        let screens = three_screens().screens;
+        let window = WindowInfo::builder()
+            .with_window_number(4)
+            .with_bounds(WindowBounds::new(1500.0, -1000.0, 400.0, 300.0))
+            .build();
+
+        let parts = screen_parts(&window, &screens);
+
+        assert_eq!(parts.len(), 1);
+        assert_eq!(parts[0].0.screen, Some(1));
+        assert_eq!(parts[0].1.bounds, window.bounds);
+        assert!(screen_parts(&window, &[]).is_empty());
+    }
+
+    // Counts its cancellations.
+    struct FakeTimer(Rc<Cell<u32>>);
+
+    impl Cancel for FakeTimer {
+        fn cancel(&self) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+
+    #[test]
+    fn auto_dismiss_is_only_scheduled_when_configured() {
+        let scheduled = Cell::new(None);
+        let cancelled = Rc::new(Cell::new(0));
+        let schedule = |after| {
+            scheduled.set(Some(after));
+            FakeTimer(Rc::clone(&cancelled))
+        };
+
+        assert!(dismiss_timer(None, schedule).is_none());
+        assert_eq!(scheduled.get(), None);
+
+        let timer = dismiss_timer(Some(Duration::from_secs(3)), schedule);
+        assert!(timer.is_some());
+        assert_eq!(scheduled.get(), Some(Duration::from_secs(3)));
+        assert_eq!(cancelled.get(), 0);
+    }
+
+    #[test]
+    fn closing_a_panel_early_cancels_its_dismiss_timer() {
+        let cancelled = Rc::new(Cell::new(0));
+        let panels = Rc::new(RefCell::new(HashMap::new()));
+        let timer = dismiss_timer(Some(Duration::from_secs(3)), |_| {
+            FakeTimer(Rc::clone(&cancelled))
+        });
+        panels.borrow_mut().insert(key(7), timer);
+
+        // As `close` and `close_all` do.
+        panels.borrow_mut().remove(&key(7));
+
+        assert_eq!(cancelled.get(), 1);
+    }
+
+    #[test]
+    fn a_fired_dismiss_untracks_the_panel_and_keeps_it_closed() {
+        let panels = Rc::new(RefCell::new(HashMap::from([
+            (key(7), "seven"),
+            (key(8), "eight"),
+        ])));
+        let dismissed = Rc::new(RefCell::new(HashSet::new()));
+
+        let removed = dismiss_slot(&Rc::downgrade(&panels), &Rc::downgrade(&dismissed), key(7));
+
+        assert_eq!(removed, Some("seven"));
+        assert_eq!(panels.borrow().keys().collect::<Vec<_>>(), [&key(8)]);
+        assert_eq!(*dismissed.borrow(), HashSet::from([key(7)]));
+    }
+
+    #[test]
+    fn a_dismiss_firing_after_the_manager_is_gone_does_nothing() {
+        let panels: Rc<RefCell<HashMap<PanelKey, &str>>> = Rc::default();
+        let dismissed: Rc<RefCell<HashSet<PanelKey>>> = Rc::default();
+        let (weak_panels, weak_dismissed) = (Rc::downgrade(&panels), Rc::downgrade(&dismissed));
+        drop((panels, dismissed));
+
+        assert_eq!(dismiss_slot(&weak_panels, &weak_dismissed, key(7)), None);
+    }
+}
@@ -0,0 +1,519 @@
+use std::collections::HashMap;
+use std::ops::ControlFlow;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::window_search::{
+    ScanContext, WindowBounds, WindowInfo, WindowNumber, WindowSearchCriteria, WindowSearchError,
+};
+
+/// Moves and resizes of at most this many points are treated as jitter; CG
+/// bounds can wobble by a pixel during live drags.
+pub const DEFAULT_JITTER_THRESHOLD: f64 = 1.0;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", content = "window", rename_all = "snake_case")]
+pub enum WindowEvent {
+    Created(WindowInfo),
+    Destroyed(WindowInfo),
+    Moved(WindowInfo),
+    Resized(WindowInfo),
+    /// The window kept its number but its title changed, e.g. a new document
+    /// tab; carries the window with the new title.
+    TitleChanged(WindowInfo),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct WindowDiff {
+    pub events: Vec<WindowEvent>,
+}
+
+impl WindowDiff {
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+}
+
+/// Pull-based counterpart to `monitor_windows`: each `poll` scans, diffs against
+/// the previous poll and remembers the new state, so a UI can ask "what changed
+/// since last frame" without callbacks or threads.
+#[derive(Debug)]
+pub struct WindowWatcher {
+    known: HashMap<WindowNumber, WindowInfo>,
+    jitter_threshold: f64,
+    context: ScanContext,
+}
+
+impl WindowWatcher {
+    pub fn new() -> Self {
+        Self {
+            known: HashMap::new(),
+            jitter_threshold: DEFAULT_JITTER_THRESHOLD,
+            context: ScanContext::new(),
+        }
+    }
+
+    /// Changes of at most `threshold` points in origin or size produce no
+    /// event. The last reported bounds are kept until a change exceeds it, so
+    /// slow drifts still show up once they add up.
+    pub fn with_jitter_threshold(mut self, threshold: f64) -> Self {
+        self.jitter_threshold = threshold;
+        self
+    }
+
+    pub fn poll(
+        &mut self,
+        criteria: &WindowSearchCriteria,
+    ) -> Result<WindowDiff, WindowSearchError> {
+        let results = self.context.find_windows(criteria)?;
+        let diff = self.apply(results.matched_windows);
+        if diff
+            .events
+            .iter()
+            .any(|event| matches!(event, WindowEvent::Destroyed(_)))
+        {
+            self.context.evict_exited();
+        }
+        Ok(diff)
+    }
+
+    pub fn apply(&mut self, windows: Vec<WindowInfo>) -> WindowDiff {
+        let events = diff_windows(&self.known, &windows, self.jitter_threshold);
+        self.known = windows
+            .into_iter()
+            .map(|mut window| {
+                if let Some(old) = self.known.get(&window.window_number) {
+                    window.bounds =
+                        settled_bounds(&old.bounds, &window.bounds, self.jitter_threshold);
+                }
+                (window.window_number, window)
+            })
+            .collect();
+        WindowDiff { events }
+    }
+}
+
+impl Default for WindowWatcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct MonitorHandle {
+    stop_sender: Sender<()>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MonitorHandle {
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        let _ = self.stop_sender.send(());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for MonitorHandle {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+/// Scans for windows matching `criteria` every `interval` on a dedicated thread
+/// and calls `on_event` with the differences between consecutive scans.
+///
+/// Scanning runs on the monitor thread itself rather than being bounced to the
+/// main thread: `CGWindowListCopyWindowInfo` and `NSRunningApplication` lookups
+/// are safe to call from any thread, and this keeps the monitor usable from
+/// programs that never run an AppKit event loop. Anything that touches AppKit in
+/// response to an event (creating or moving panels) must still be done on the
+/// main thread.
+///
+/// With the `accessibility` feature, criteria using dialog detection,
+/// `require_dialog` or `resolve_parents`, or matching off-screen windows, also
+/// make AX calls from this thread. `AXUIElement` is thread-safe, and each call is a synchronous
+/// round trip to the target app that can block for the messaging timeout
+/// when that app hangs, so they are better off here than on the main thread.
+///
+/// The thread stops when `MonitorHandle::stop` is called, when the handle is
+/// dropped, or once `on_event` returns `ControlFlow::Break`.
+pub fn monitor_windows<F>(
+    criteria: WindowSearchCriteria,
+    interval: Duration,
+    on_event: F,
+) -> MonitorHandle
+where
+    F: FnMut(WindowEvent) -> ControlFlow<()> + Send + 'static,
+{
+    run_monitor(
+        interval,
+        move || {
+            let mut watcher = WindowWatcher::new();
+            move || watcher.poll(&criteria)
+        },
+        on_event,
+    )
+}
+
+// Polls what `start` returns, on the monitor thread, every `interval`. The
+// poller is built there too, so it need not be `Send`.
+fn run_monitor<S, P, F>(interval: Duration, start: S, mut on_event: F) -> MonitorHandle
+where
+    S: FnOnce() -> P + Send + 'static,
+    P: FnMut() -> Result<WindowDiff, WindowSearchError>,
+    F: FnMut(WindowEvent) -> ControlFlow<()> + Send + 'static,
+{
+    let (stop_sender, stop_receiver) = mpsc::channel();
+
+    let thread = thread::spawn(move || {
+        let mut poll = start();
+
+        loop {
+            match poll() {
+                Ok(diff) => {
+                    for event in diff.events {
+                        if on_event(event).is_break() {
+                            return;
+                        }
+                    }
+                }
+                Err(e) => {
+                    log::warn!("[MONITOR] Error: {}", e);
+                }
+            }
+
+            match stop_receiver.recv_timeout(interval) {
+                Err(RecvTimeoutError::Timeout) => continue,
+                _ => return,
+            }
+        }
+    });
+
+    MonitorHandle {
+        stop_sender,
+        thread: Some(thread),
+    }
+}
+
+/// Channel flavour of `monitor_windows`; the thread also stops once the
+/// receiver has been dropped.
+pub fn spawn_monitor(
+    criteria: WindowSearchCriteria,
+    interval: Duration,
+) -> (MonitorHandle, Receiver<WindowEvent>) {
+    let (event_sender, event_receiver) = mpsc::channel();
+    let handle = monitor_windows(criteria, interval, forward_to(event_sender));
+    (handle, event_receiver)
+}
+
+fn forward_to(
+    event_sender: Sender<WindowEvent>,
+) -> impl FnMut(WindowEvent) -> ControlFlow<()> + Send + 'static {
+    move |event| match event_sender.send(event) {
+        Ok(()) => ControlFlow::Continue(()),
+        Err(_) => ControlFlow::Break(()),
+    }
+}
+
+pub fn format_event_line(event: &WindowEvent) -> String {
+    let (kind, window) = match event {
+        WindowEvent::Created(window) => ("APPEARED", window),
+        WindowEvent::Destroyed(window) => ("DISAPPEARED", window),
+        WindowEvent::Moved(window) => ("MOVED", window),
+        WindowEvent::Resized(window) => ("RESIZED", window),
+        WindowEvent::TitleChanged(window) => ("RETITLED", window),
+    };
+
+    format!(
+        "{} {} {:?} {:?} {}",
+        kind,
+        window.window_number,
+        window.app_name,
+        window.display_title(),
+        window.bounds
+    )
+}
+
+/// One NDJSON line: `{"event":"created","window":{...}}`.
+pub fn format_event_json(event: &WindowEvent) -> String {
+    serde_json::to_string(event).expect("window events always serialize")
+}
+
+/// Events between two scans; moves and resizes of at most `jitter_threshold`
+/// points are ignored.
+pub fn diff_windows(
+    previous: &HashMap<WindowNumber, WindowInfo>,
+    current: &[WindowInfo],
+    jitter_threshold: f64,
+) -> Vec<WindowEvent> {
+    let mut events = Vec::new();
+
+    for window in current {
+        let Some(old) = previous.get(&window.window_number) else {
+            events.push(WindowEvent::Created(window.clone()));
+            continue;
+        };
+
+        let (old_bounds, bounds) = (old.bounds, window.bounds);
+
+        if exceeds(old_bounds.x, bounds.x, jitter_threshold)
+            || exceeds(old_bounds.y, bounds.y, jitter_threshold)
+        {
+            events.push(WindowEvent::Moved(window.clone()));
+        }
+        if exceeds(old_bounds.width, bounds.width, jitter_threshold)
+            || exceeds(old_bounds.height, bounds.height, jitter_threshold)
+        {
+            events.push(WindowEvent::Resized(window.clone()));
+        }
+        if old.title != window.title {
+            events.push(WindowEvent::TitleChanged(window.clone()));
+        }
+    }
+
+    for (window_number, old) in previous {
+        if !current.iter().any(|w| w.window_number == *window_number) {
+            events.push(WindowEvent::Destroyed(old.clone()));
+        }
+    }
+
+    events
+}
+
+// Keeps `old`'s origin and size unless the new one moved past the threshold,
+// mirroring which events `diff_windows` reports.
+fn settled_bounds(old: &WindowBounds, new: &WindowBounds, threshold: f64) -> WindowBounds {
+    let moved = exceeds(old.x, new.x, threshold) || exceeds(old.y, new.y, threshold);
+    let resized =
+        exceeds(old.width, new.width, threshold) || exceeds(old.height, new.height, threshold);
+
+    let (x, y) = if moved {
+        (new.x, new.y)
+    } else {
+        (old.x, old.y)
+    };
+    let (width, height) = if resized {
+        (new.width, new.height)
+    } else {
+        (old.width, old.height)
+    };
+    WindowBounds::new(x, y, width, height)
+}
+
+fn exceeds(old: f64, new: f64, threshold: f64) -> bool {
+    (old - new).abs() > threshold
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+
+    fn window(window_number: i64) -> WindowInfo {
+        WindowInfo::builder()
+            .with_window_number(window_number)
+            .with_title("Open")
+            .build()
+    }
+
+    #[test]
+    fn events_format_as_one_line_each() {
+        let window = WindowInfo::builder()
+            .with_window_number(3)
+            .with_app_name("TextEdit")
+            .with_title("Notes \"draft\"")
+            .with_bounds(WindowBounds::new(10.0, -20.0, 300.5, 200.0))
+            .build();
+        let untitled = WindowInfo::builder()
+            .with_window_number(4)
+            .without_title()
+            .build();
+
+        assert_eq!(
+            format_event_line(&WindowEvent::Created(window.clone())),
+            "APPEARED 3 \"TextEdit\" \"Notes \\\"draft\\\"\" x:10, y:-20, w:300.5, h:200"
+        );
+        assert_eq!(
+            format_event_line(&WindowEvent::Destroyed(untitled)),
+            "DISAPPEARED 4 \"Test\" \"<no title>\" x:0, y:0, w:100, h:100"
+        );
+        for (event, kind) in [
+            (WindowEvent::Moved(window.clone()), "MOVED "),
+            (WindowEvent::Resized(window.clone()), "RESIZED "),
+            (WindowEvent::TitleChanged(window), "RETITLED "),
+        ] {
+            assert!(format_event_line(&event).starts_with(kind));
+        }
+    }
+
+    // A monitor over `scans`, repeating the last one, sending to a channel.
+    fn spawn_scripted(
+        interval: Duration,
+        scans: Vec<Vec<WindowInfo>>,
+    ) -> (MonitorHandle, Receiver<WindowEvent>) {
+        let (event_sender, event_receiver) = mpsc::channel();
+        let handle = run_monitor(
+            interval,
+            move || {
+                let mut watcher = WindowWatcher::new();
+                let mut scans = scans.into_iter().peekable();
+                let mut last = Vec::new();
+                move || {
+                    if scans.peek().is_some() {
+                        last = scans.next().unwrap_or_default();
+                    }
+                    Ok(watcher.apply(last.clone()))
+                }
+            },
+            forward_to(event_sender),
+        );
+        (handle, event_receiver)
+    }
+
+    fn summary(event: &WindowEvent) -> (&'static str, i64) {
+        match event {
+            WindowEvent::Created(window) => ("created", window.window_number.0),
+            WindowEvent::Destroyed(window) => ("destroyed", window.window_number.0),
+            WindowEvent::Moved(window) => ("moved", window.window_number.0),
+            WindowEvent::Resized(window) => ("resized", window.window_number.0),
+            WindowEvent::TitleChanged(window) => ("title_changed", window.window_number.0),
+        }
+    }
+
+    fn window_at(x: f64, y: f64, width: f64, height: f64) -> WindowInfo {
+        WindowInfo::builder()
+            .with_window_number(1)
+            .with_bounds(WindowBounds::new(x, y, width, height))
+            .build()
+    }
+
+    #[test]
+    fn watcher_diffs_each_scan_against_the_last() {
+        let mut watcher = WindowWatcher::new();
+        let mut apply = |windows| {
+            let diff = watcher.apply(windows);
+            diff.events.iter().map(summary).collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            apply(vec![window(1), window(2)]),
+            [("created", 1), ("created", 2)]
+        );
+
+        let mut renamed = window(1);
+        renamed.title = Some("Save".to_string());
+        renamed.bounds.x += 50.0;
+        assert_eq!(
+            apply(vec![renamed.clone(), window(3)]),
+            [
+                ("moved", 1),
+                ("title_changed", 1),
+                ("created", 3),
+                ("destroyed", 2)
+            ]
+        );
+
+        assert!(apply(vec![renamed, window(3)]).is_empty());
+    }
+
+    #[test]
+    fn sub_threshold_jitter_produces_no_events() {
+        let previous = HashMap::from([(WindowNumber(1), window_at(100.0, 100.0, 400.0, 300.0))]);
+
+        let jitter = [window_at(100.5, 99.0, 401.0, 300.0)];
+        assert!(diff_windows(&previous, &jitter, 1.0).is_empty());
+
+        let moved = [window_at(101.5, 100.0, 400.5, 300.0)];
+        let events: Vec<_> = diff_windows(&previous, &moved, 1.0)
+            .iter()
+            .map(summary)
+            .collect();
+        assert_eq!(events, [("moved", 1)]);
+
+        let resized = [window_at(100.0, 100.0, 400.0, 298.5)];
+        let events: Vec<_> = diff_windows(&previous, &resized, 1.0)
+            .iter()
+            .map(summary)
+            .collect();
+        assert_eq!(events, [("resized", 1)]);
+    }
+
+    #[test]
+    fn settled_bounds_keep_the_unchanged_half() {
+        let old = WindowBounds::new(100.0, 100.0, 400.0, 300.0);
+
+        assert_eq!(
+            settled_bounds(&old, &WindowBounds::new(100.8, 99.5, 400.6, 300.0), 1.0),
+            old
+        );
+        assert_eq!(
+            settled_bounds(&old, &WindowBounds::new(150.0, 99.5, 400.6, 300.0), 1.0),
+            WindowBounds::new(150.0, 99.5, 400.0, 300.0)
+        );
+        assert_eq!(
+            settled_bounds(&old, &WindowBounds::new(100.8, 99.5, 420.0, 300.0), 1.0),
+            WindowBounds::new(100.0, 100.0, 420.0, 300.0)
+        );
+    }
+
+    #[test]
+    fn slow_drifts_report_once_they_add_up() {
+        let mut watcher = WindowWatcher::new().with_jitter_threshold(1.0);
+        assert_eq!(
+            watcher
+                .apply(vec![window_at(100.0, 100.0, 400.0, 300.0)])
+                .events
+                .len(),
+            1
+        );
+
+        let drift = |watcher: &mut WindowWatcher, x| {
+            watcher
+                .apply(vec![window_at(x, 100.0, 400.0, 300.0)])
+                .events
+                .iter()
+                .map(summary)
+                .collect::<Vec<_>>()
+        };
+        assert!(drift(&mut watcher, 100.6).is_empty());
+        assert!(drift(&mut watcher, 101.0).is_empty());
+        assert_eq!(drift(&mut watcher, 101.2), [("moved", 1)]);
+        assert!(drift(&mut watcher, 101.8).is_empty());
+    }
+
+    #[test]
+    fn events_arrive_over_the_channel_in_scan_order() {
+        let (handle, events) = spawn_scripted(
+            Duration::from_millis(1),
+            vec![vec![window(1)], vec![window(1), window(2)], vec![window(2)]],
+        );
+
+        let received: Vec<(&str, i64)> = (0..3)
+            .map(|_| summary(&events.recv_timeout(Duration::from_secs(5)).unwrap()))
+            .collect();
+        handle.stop();
+
+        assert_eq!(received, [("created", 1), ("created", 2), ("destroyed", 1)]);
+        assert_eq!(events.iter().count(), 0);
+    }
+
+    #[test]
+    fn stopping_joins_the_thread_promptly() {
+        let (handle, events) = spawn_scripted(Duration::from_secs(60), vec![vec![window(1)]]);
+        assert!(events.recv_timeout(Duration::from_secs(5)).is_ok());
+
+        let stopping = Instant::now();
+        handle.stop();
+
+        assert!(stopping.elapsed() < Duration::from_secs(5));
+        assert!(events.recv().is_err());
+    }
+}
@@ -0,0 +1,2460 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::hash::Hasher;
+use std::path::PathBuf;
+use std::ptr::NonNull;
+use std::rc::Rc;
+use std::time::Duration;
+
+use objc2::rc::Retained;
+use objc2::runtime::{AnyObject, NSObject};
+use objc2::{
+    define_class, msg_send, sel, AllocAnyThread, DefinedClass, MainThreadMarker, MainThreadOnly,
+    Message,
+};
+use objc2_app_kit::{
+    NSAutoresizingMaskOptions, NSBackingStoreType, NSBezierPath, NSBox, NSBoxType, NSButton,
+    NSCellImagePosition, NSColor, NSControl, NSFocusRingType, NSFont, NSFontAttributeName,
+    NSFontWeightBold, NSFontWeightLight, NSFontWeightMedium, NSFontWeightRegular,
+    NSFontWeightSemibold, NSForegroundColorAttributeName, NSImage, NSImageScaling, NSImageView,
+    NSLayoutAttribute, NSPanel, NSProgressIndicator, NSProgressIndicatorStyle, NSStackView,
+    NSTextField, NSTitlePosition, NSUserInterfaceItemIdentification,
+    NSUserInterfaceLayoutOrientation, NSView, NSWindowCollectionBehavior, NSWindowOrderingMode,
+    NSWindowStyleMask,
+};
+use objc2_foundation::{
+    NSCopying, NSMutableAttributedString, NSPoint, NSRange, NSRect, NSSize, NSString, NSTimer,
+};
+#[cfg(feature = "webview")]
+use objc2_web_kit::WKWebView;
+use serde::Deserialize;
+
+use crate::screen::{cg_rect_to_ns, screen_for_bounds, ScreenInfo, ScreenProvider};
+#[cfg(feature = "webview")]
+use crate::webview::{
+    attach_message_handler, build_web_view, detach_message_handler, update_message_window,
+    MessageHandler, WebSource,
+};
+use crate::window_search::{
+    StableHasher, WindowBounds, WindowInfo, WindowNumber, WindowSearchResults,
+};
+
+const PROGRESS_BAR_HEIGHT: f64 = 20.0;
+const DEFAULT_EXTRA_WIDTH: f64 = 300.0;
+const DEFAULT_PANEL_SIZE: NSSize = NSSize::new(300.0, 200.0);
+const DEFAULT_ALPHA: f64 = 0.9;
+const DEFAULT_WINDOW_TITLE_TEMPLATE: &str = "Overlay: {app} — {title}";
+const FALLBACK_WINDOW_TITLE: &str = "PANEL DETECTOR OVERLAY";
+// Frames sharing at least this fraction of the smaller one's area get cascaded.
+const CASCADE_OVERLAP_FRACTION: f64 = 0.5;
+const MAX_CASCADE_STEPS: usize = 64;
+const CONTENT_BUTTON_TAG: isize = 1;
+const DEFAULT_FONT_SIZE: f64 = 13.0;
+const APP_ICON_SIZE: f64 = 24.0;
+const APP_COLOR_SATURATION: f64 = 0.55;
+const APP_COLOR_VALUE: f64 = 0.85;
+// `NSView` identifiers for the subviews panel updates need to find again.
+const CONTENT_IDENTIFIER: &str = "PanelDetectorContent";
+const BACKGROUND_IDENTIFIER: &str = "PanelDetectorBackground";
+// Associated-object key that ties a `ClickTarget` to its button's lifetime.
+static CLICK_TARGET_KEY: u8 = 0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PanelError {
+    /// The target window reported zero-area or non-finite bounds.
+    InvalidBounds(WindowBounds),
+    NoScreen,
+    NoProgressIndicator,
+    InvalidElementPath(String),
+    ElementNotFound(String),
+}
+
+impl fmt::Display for PanelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PanelError::InvalidBounds(bounds) => write!(f, "Invalid window bounds ({})", bounds),
+            PanelError::NoScreen => write!(f, "No screen available for the panel"),
+            PanelError::NoProgressIndicator => write!(f, "Panel has no progress indicator"),
+            PanelError::InvalidElementPath(reason) => write!(f, "{}", reason),
+            PanelError::ElementNotFound(path) => {
+                write!(f, "No accessibility element with a frame at '{}'", path)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PanelError {}
+
+/// Where a panel sits relative to its target window. Side placements use the
+/// configured panel size; `Overlay` covers the window and extends past its right
+/// edge by `extra_width`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PanelPlacement {
+    #[default]
+    Overlay,
+    Right,
+    Left,
+    Above,
+    Below,
+    /// The first side with room for the panel on the window's screen, tried in
+    /// the order right, left, above, below; `Overlay` when none fits.
+    Auto,
+}
+
+/// The window level panels float at. Each level sits above the ones before it.
+/// In config files a name such as `"floating"`, or `{ custom = 12 }`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PanelLevel {
+    /// `NSFloatingWindowLevel`, like tool palettes.
+    Floating,
+    /// Just above modal panels, so overlays stay in front of the Open dialogs
+    /// they annotate.
+    #[default]
+    Overlay,
+    /// `NSStatusWindowLevel`, above the menu bar.
+    Status,
+    /// Any raw `NSWindowLevel`.
+    Custom(isize),
+}
+
+impl PanelLevel {
+    pub fn window_level(self) -> isize {
+        match self {
+            PanelLevel::Floating => 3,
+            PanelLevel::Overlay => 10,
+            PanelLevel::Status => 25,
+            PanelLevel::Custom(level) => level,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub enum PanelContent {
+    #[default]
+    Button,
+    Progress {
+        indeterminate: bool,
+    },
+    /// Read-only formatted text built from consecutive runs; include `\n` in a
+    /// run's text to start a new line.
+    AttributedText(Vec<TextRun>),
+    /// Wrapping text in the run's style. `{app}`, `{title}` and `{pid}`
+    /// expand to the window's and are refreshed with it.
+    Label(TextRun),
+    /// An image scaled to `size` points.
+    Image {
+        source: ImageSource,
+        size: (f64, f64),
+    },
+    /// `children` in a row or column, `spacing` points apart and centered
+    /// across the axis, e.g. an icon beside a column of labels.
+    Stack {
+        axis: StackAxis,
+        spacing: f64,
+        children: Vec<PanelContent>,
+    },
+    /// A web page filling the panel; see the `webview` module for messaging
+    /// back to Rust.
+    #[cfg(feature = "webview")]
+    WebView(WebSource),
+}
+
+/// Where `PanelContent::Image` gets its picture. An image that can't be
+/// loaded leaves an empty space of the requested size.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImageSource {
+    /// The window's app icon, see `WindowInfo::app_icon`.
+    AppIcon,
+    File(PathBuf),
+    /// A system or app bundle image by name, e.g. `"NSCaution"`.
+    Named(String),
+    /// An SF Symbol, e.g. `"info.circle"`.
+    Symbol(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StackAxis {
+    Horizontal,
+    #[default]
+    Vertical,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TextWeight {
+    Light,
+    #[default]
+    Regular,
+    Medium,
+    Semibold,
+    Bold,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextRun {
+    pub text: String,
+    pub font_size: f64,
+    pub weight: TextWeight,
+    /// sRGB components; `None` uses the system label color.
+    pub color: Option<(f64, f64, f64, f64)>,
+}
+
+impl TextRun {
+    pub fn new(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            font_size: DEFAULT_FONT_SIZE,
+            weight: TextWeight::default(),
+            color: None,
+        }
+    }
+
+    pub fn with_font_size(mut self, font_size: f64) -> Self {
+        self.font_size = font_size;
+        self
+    }
+
+    pub fn with_weight(mut self, weight: TextWeight) -> Self {
+        self.weight = weight;
+        self
+    }
+
+    pub fn with_color(mut self, red: f64, green: f64, blue: f64, alpha: f64) -> Self {
+        self.color = Some((red, green, blue, alpha));
+        self
+    }
+}
+
+/// How round a panel's corners are. Either kind is clamped to half the panel's
+/// smaller side, where the corners meet.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CornerRadius {
+    Fixed(f64),
+    /// A fraction of the panel's smaller side, so the rounding looks the same
+    /// as panels are resized to follow their windows.
+    Proportional(f64),
+}
+
+impl CornerRadius {
+    pub fn radius_for(&self, size: NSSize) -> f64 {
+        let smaller_side = size.width.min(size.height).max(0.0);
+        let radius = match *self {
+            CornerRadius::Fixed(radius) => radius,
+            CornerRadius::Proportional(fraction) => fraction * smaller_side,
+        };
+        if radius.is_nan() {
+            return 0.0;
+        }
+        radius.clamp(0.0, smaller_side / 2.0)
+    }
+
+    fn is_square(&self) -> bool {
+        match *self {
+            CornerRadius::Fixed(radius) | CornerRadius::Proportional(radius) => {
+                radius.is_nan() || radius <= 0.0
+            }
+        }
+    }
+}
+
+/// What a panel draws for its target.
+/// In config files `"panel"`, or `{ highlight-border = { thickness = 3.0 } }`
+/// with an optional `color = [r, g, b, a]`.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OverlayStyle {
+    /// A panel with content and a close button, placed per `PanelPlacement`.
+    #[default]
+    Panel,
+    /// Only a border `thickness` points wide running just outside the target's
+    /// bounds, with a transparent interior, like a window highlighter. Always
+    /// click-through; placement, size, offset and content are ignored, and
+    /// the corner radius rounds the border instead.
+    #[serde(rename_all = "kebab-case")]
+    HighlightBorder {
+        thickness: f64,
+        /// sRGB components; `None` uses the app color (see `app_color`).
+        #[serde(default)]
+        color: Option<(f64, f64, f64, f64)>,
+    },
+}
+
+impl Default for CornerRadius {
+    fn default() -> Self {
+        CornerRadius::Fixed(0.0)
+    }
+}
+
+/// Builds and refreshes the view a panel shows for its window, for content the
+/// built-in `PanelContent` modes don't cover. The returned view is placed in the
+/// panel's content view as its first subview, below the close button; controls
+/// get the panel's focus settings applied.
+pub trait PanelContentProvider {
+    /// `frame` is the panel's content area; the returned view sets its own frame
+    /// within it.
+    fn build_content(
+        &self,
+        window: &WindowInfo,
+        frame: NSRect,
+        mtm: MainThreadMarker,
+    ) -> Retained<NSView>;
+
+    /// Called with the view from `build_content` when the window's title or app
+    /// changed; `changed` says which.
+    fn update_content(&self, view: &NSView, window: &WindowInfo, changed: ChangedFields);
+}
+
+/// The `WindowInfo` fields panel content is drawn from that differ between
+/// two scans of a window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ChangedFields {
+    pub title: bool,
+    pub app: bool,
+}
+
+impl ChangedFields {
+    pub fn between(previous: &WindowInfo, window: &WindowInfo) -> Self {
+        Self {
+            title: previous.title != window.title,
+            app: previous.app_name != window.app_name,
+        }
+    }
+
+    pub fn any(&self) -> bool {
+        self.title || self.app
+    }
+}
+
+#[derive(Clone)]
+struct ContentProvider(Rc<dyn PanelContentProvider>);
+
+impl fmt::Debug for ContentProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ContentProvider")
+    }
+}
+
+#[derive(Clone)]
+struct ClickHandler(Rc<dyn Fn(&WindowInfo)>);
+
+impl fmt::Debug for ClickHandler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ClickHandler")
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PanelConfig {
+    content: PanelContent,
+    content_provider: Option<ContentProvider>,
+    on_click: Option<ClickHandler>,
+    #[cfg(feature = "webview")]
+    on_web_message: Option<MessageHandler>,
+    window_title: String,
+    placement: PanelPlacement,
+    panel_size: NSSize,
+    extra_width: f64,
+    match_target_size: bool,
+    offset: (f64, f64),
+    alpha: f64,
+    level: PanelLevel,
+    size_in_points: bool,
+    color_by_app: bool,
+    focusable: bool,
+    passthrough_except_controls: bool,
+    click_through: bool,
+    auto_dismiss: Option<Duration>,
+    corner_radius: CornerRadius,
+    attach_to_target: bool,
+    style: OverlayStyle,
+    show_app_icon: bool,
+}
+
+impl PanelConfig {
+    pub fn new() -> Self {
+        Self {
+            content: PanelContent::default(),
+            content_provider: None,
+            on_click: None,
+            #[cfg(feature = "webview")]
+            on_web_message: None,
+            window_title: DEFAULT_WINDOW_TITLE_TEMPLATE.to_string(),
+            placement: PanelPlacement::default(),
+            panel_size: DEFAULT_PANEL_SIZE,
+            extra_width: DEFAULT_EXTRA_WIDTH,
+            match_target_size: false,
+            offset: (0.0, 0.0),
+            alpha: DEFAULT_ALPHA,
+            level: PanelLevel::default(),
+            size_in_points: true,
+            color_by_app: false,
+            focusable: false,
+            passthrough_except_controls: false,
+            click_through: false,
+            auto_dismiss: None,
+            corner_radius: CornerRadius::default(),
+            attach_to_target: false,
+            style: OverlayStyle::default(),
+            show_app_icon: false,
+        }
+    }
+
+    pub fn with_content(mut self, content: PanelContent) -> Self {
+        self.content = content;
+        self
+    }
+
+    /// Replaces the `with_content` mode with views built by `provider`.
+    pub fn with_content_provider(mut self, provider: impl PanelContentProvider + 'static) -> Self {
+        self.content_provider = Some(ContentProvider(Rc::new(provider)));
+        self
+    }
+
+    /// Runs `handler` with the panel's target window, as of its last update,
+    /// when the panel's content button is clicked: the built-in
+    /// `PanelContent::Button`, or a provider's content if it is an `NSButton`.
+    pub fn on_click(mut self, handler: impl Fn(&WindowInfo) + 'static) -> Self {
+        self.on_click = Some(ClickHandler(Rc::new(handler)));
+        self
+    }
+
+    fn content_provider(&self) -> &dyn PanelContentProvider {
+        match &self.content_provider {
+            Some(ContentProvider(provider)) => provider.as_ref(),
+            None => &self.content,
+        }
+    }
+
+    /// Called with the panel's window and each message a `WebView` page
+    /// posts to `webview::MESSAGE_HANDLER_NAME`.
+    #[cfg(feature = "webview")]
+    pub fn on_web_message(mut self, handler: impl Fn(&WindowInfo, &str) + 'static) -> Self {
+        self.on_web_message = Some(MessageHandler(Rc::new(handler)));
+        self
+    }
+
+    /// Template for the panel's window title, as shown in Mission Control and to
+    /// accessibility clients. `{app}`, `{title}` and `{pid}` expand to the
+    /// target's app name, window title and process id.
+    pub fn with_window_title(mut self, template: impl Into<String>) -> Self {
+        self.window_title = template.into();
+        self
+    }
+
+    pub fn window_title_for(&self, window: &WindowInfo) -> String {
+        if window.app_name.is_empty() && window.title.is_none() {
+            return FALLBACK_WINDOW_TITLE.to_string();
+        }
+
+        expand_placeholders(&self.window_title, window)
+    }
+
+    pub fn with_placement(mut self, placement: PanelPlacement) -> Self {
+        self.placement = placement;
+        self
+    }
+
+    pub fn with_panel_size(mut self, width: f64, height: f64) -> Self {
+        self.panel_size = NSSize::new(width, height);
+        self
+    }
+
+    pub fn with_extra_width(mut self, extra_width: f64) -> Self {
+        self.extra_width = extra_width;
+        self
+    }
+
+    /// Gives the panel its target window's size, ignoring `with_panel_size`
+    /// and `with_extra_width`; an `Overlay` panel then covers the window
+    /// exactly.
+    pub fn match_target_size(mut self, enabled: bool) -> Self {
+        self.match_target_size = enabled;
+        self
+    }
+
+    /// Shifts the panel from its placement, in the same units as the other
+    /// sizes. Positive `dy` moves it down, as in CG window coordinates.
+    pub fn with_offset(mut self, dx: f64, dy: f64) -> Self {
+        self.offset = (dx, dy);
+        self
+    }
+
+    /// The panel's opacity, from 0.0 (invisible) to 1.0.
+    pub fn with_alpha(mut self, alpha: f64) -> Self {
+        self.alpha = alpha;
+        self
+    }
+
+    pub fn with_level(mut self, level: PanelLevel) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// Whether size inputs such as `extra_width` are points (the default) or
+    /// device pixels. CG window bounds are already in points, as are AppKit
+    /// frames, so the target window's frame is never scaled; only sizes given
+    /// here in pixels are divided by the screen's backing scale factor. Pixels
+    /// otherwise only matter when capturing window images.
+    pub fn size_in_points(mut self, size_in_points: bool) -> Self {
+        self.size_in_points = size_in_points;
+        self
+    }
+
+    /// Tints each panel's background with a color derived from its target's app,
+    /// so overlays for the same app always share a color, across runs too.
+    pub fn color_by_app(mut self, enabled: bool) -> Self {
+        self.color_by_app = enabled;
+        self
+    }
+
+    /// Whether the panel may take key status and its controls keyboard focus.
+    /// Off by default so overlays never steal focus from the app they annotate:
+    /// the panel is non-activating and its controls refuse first responder and
+    /// draw no focus ring.
+    pub fn focusable(mut self, enabled: bool) -> Self {
+        self.focusable = enabled;
+        self
+    }
+
+    /// Lets clicks outside the panel's controls fall through: the content view's
+    /// hit test only reports the controls, such as the close button, including
+    /// those nested in stack views, and nothing elsewhere. Unlike
+    /// `ignoresMouseEvents` this keeps the controls usable.
+    pub fn passthrough_except_controls(mut self, enabled: bool) -> Self {
+        self.passthrough_except_controls = enabled;
+        self
+    }
+
+    /// Makes the whole panel transparent to the mouse (`ignoresMouseEvents`),
+    /// for purely visual annotations; its controls can't be clicked either.
+    /// Overrides `focusable`, so the panel stays non-activating and focus
+    /// never leaves the target app.
+    pub fn click_through(mut self, enabled: bool) -> Self {
+        self.click_through = enabled;
+        self
+    }
+
+    /// Closes each panel this long after it appears, e.g. for transient
+    /// notifications; it stays closed while its window is still around. `None`
+    /// (the default) keeps panels up until their window goes away.
+    pub fn auto_dismiss(mut self, after: Option<Duration>) -> Self {
+        self.auto_dismiss = after;
+        self
+    }
+
+    /// Square corners by default. Rounded panels draw their background in a
+    /// subview, whose radius `update_corner_radius` refreshes after a resize.
+    pub fn with_corner_radius(mut self, corner_radius: CornerRadius) -> Self {
+        self.corner_radius = corner_radius;
+        self
+    }
+
+    /// Glues the panel to its target instead of floating above everything:
+    /// it takes the target's window layer in place of `with_level` and is
+    /// ordered directly above it, so windows in front of the target cover the
+    /// panel too. With the `accessibility` feature, `follow_target` then moves
+    /// it along with the target as it is dragged, without polling.
+    pub fn attach_to_target(mut self, enabled: bool) -> Self {
+        self.attach_to_target = enabled;
+        self
+    }
+
+    pub fn is_attached_to_target(&self) -> bool {
+        self.attach_to_target
+    }
+
+    /// Puts the app's icon beside the `PanelContent::Button` label. Other
+    /// content can use `ImageSource::AppIcon`.
+    pub fn show_app_icon(mut self, enabled: bool) -> Self {
+        self.show_app_icon = enabled;
+        self
+    }
+
+    pub fn with_style(mut self, style: OverlayStyle) -> Self {
+        self.style = style;
+        self
+    }
+
+    /// Whether panels outline their target rather than sit beside it, and so
+    /// must not be cascaded.
+    pub fn is_highlight(&self) -> bool {
+        matches!(self.style, OverlayStyle::HighlightBorder { .. })
+    }
+
+    pub fn auto_dismiss_after(&self) -> Option<Duration> {
+        self.auto_dismiss
+    }
+
+    fn is_focusable(&self) -> bool {
+        self.focusable && !self.click_through && !self.is_highlight()
+    }
+
+    fn points(&self, size: f64, screen: &ScreenInfo) -> f64 {
+        if self.size_in_points || screen.scale_factor <= 0.0 {
+            size
+        } else {
+            size / screen.scale_factor
+        }
+    }
+}
+
+impl Default for PanelConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn create_overlay_panel(
+    window: &WindowInfo,
+    screens: &dyn ScreenProvider,
+    config: &PanelConfig,
+) -> Result<Retained<NSPanel>, PanelError> {
+    let panel_frame = to_overlay_frame(window, screens, config)?;
+    let panel = create_overlay_panel_with_frame(window, panel_frame, config)?;
+    set_fullscreen_target(&panel, window.is_fullscreen(&screens.screens()));
+    Ok(panel)
+}
+
+/// A fullscreen window lives in its own Space, which only shows windows from
+/// the fullscreen app plus those marked `FullScreenAuxiliary`; `CanJoinAllSpaces`
+/// alone keeps a panel on every regular desktop but not there.
+pub fn overlay_collection_behavior(fullscreen_target: bool) -> NSWindowCollectionBehavior {
+    let behavior =
+        NSWindowCollectionBehavior::CanJoinAllSpaces | NSWindowCollectionBehavior::Stationary;
+    if fullscreen_target {
+        behavior | NSWindowCollectionBehavior::FullScreenAuxiliary
+    } else {
+        behavior
+    }
+}
+
+/// Lets `panel` appear in its target's fullscreen Space, or stops it doing so
+/// once the target leaves fullscreen.
+pub fn set_fullscreen_target(panel: &NSPanel, fullscreen: bool) {
+    let behavior = overlay_collection_behavior(fullscreen);
+    if panel.collectionBehavior() != behavior {
+        panel.setCollectionBehavior(behavior);
+    }
+}
+
+/// One panel per matched window, in order, for one-shot "overlay everything"
+/// callers; a window that can't get a panel doesn't stop the rest.
+pub fn create_overlay_panels_for(
+    results: &WindowSearchResults,
+    screens: &dyn ScreenProvider,
+    config: &PanelConfig,
+) -> Vec<Result<Retained<NSPanel>, PanelError>> {
+    create_each(results, |window| {
+        create_overlay_panel(window, screens, config)
+    })
+}
+
+// `create_overlay_panels_for` with the panel creation passed in.
+fn create_each<T>(
+    results: &WindowSearchResults,
+    create: impl FnMut(&WindowInfo) -> Result<T, PanelError>,
+) -> Vec<Result<T, PanelError>> {
+    results.matched_windows.iter().map(create).collect()
+}
+
+/// Places a panel exactly over the accessibility element at `element_path` in
+/// the app `pid`, e.g. `AXWindow/AXToolbar` (see `ax::parse_element_path` for
+/// the syntax).
+#[cfg(feature = "accessibility")]
+pub fn create_overlay_for_ax_element(
+    pid: crate::window_search::Pid,
+    element_path: &str,
+    screens: &dyn ScreenProvider,
+    config: &PanelConfig,
+) -> Result<Retained<NSPanel>, PanelError> {
+    let path =
+        crate::ax::parse_element_path(element_path).map_err(PanelError::InvalidElementPath)?;
+    let element = crate::ax::find_element_by_path(pid.0, &path)
+        .ok_or_else(|| PanelError::ElementNotFound(element_path.to_string()))?;
+    let bounds = element
+        .frame()
+        .ok_or_else(|| PanelError::ElementNotFound(element_path.to_string()))?;
+    let frame = ax_element_frame(&bounds, screens)?;
+
+    let mut target = WindowInfo::builder()
+        .with_app_name(crate::window_search::get_localized_app_name(pid.0).unwrap_or_default())
+        .with_bounds(bounds)
+        .with_window_number(element.window_number().unwrap_or(0))
+        .with_pid(pid);
+    target = match element.string_attribute("AXTitle") {
+        Some(title) => target.with_title(title),
+        None => target.without_title(),
+    };
+    let target = target.build();
+
+    create_overlay_panel_with_frame(&target, frame, config)
+}
+
+// AX positions share CG's top-left origin on the primary display, so element
+// frames flip into AppKit's space like window bounds do.
+#[cfg(feature = "accessibility")]
+fn ax_element_frame(
+    bounds: &WindowBounds,
+    screens: &dyn ScreenProvider,
+) -> Result<NSRect, PanelError> {
+    if !bounds.is_valid() {
+        return Err(PanelError::InvalidBounds(*bounds));
+    }
+    let primary_height = screens
+        .screens()
+        .first()
+        .ok_or(PanelError::NoScreen)?
+        .frame
+        .size
+        .height;
+    Ok(cg_rect_to_ns(bounds, primary_height))
+}
+
+/// Fails with `InvalidBounds` when `panel_frame` is empty or not finite,
+/// which AppKit would otherwise accept and show as an invisible panel.
+pub fn create_overlay_panel_with_frame(
+    window: &WindowInfo,
+    panel_frame: NSRect,
+    config: &PanelConfig,
+) -> Result<Retained<NSPanel>, PanelError> {
+    let frame_bounds = WindowBounds::new(
+        panel_frame.origin.x,
+        panel_frame.origin.y,
+        panel_frame.size.width,
+        panel_frame.size.height,
+    );
+    if !frame_bounds.is_valid() {
+        return Err(PanelError::InvalidBounds(frame_bounds));
+    }
+
+    log::debug!("Creating NSPanel overlay for {} window...", window.app_name);
+
+    let panel_x = panel_frame.origin.x;
+    let panel_y = panel_frame.origin.y;
+    let panel_width = panel_frame.size.width;
+    let panel_height = panel_frame.size.height;
+
+    log::debug!(
+        "Panel NS coords: x={}, y={}, w={}, h={}",
+        panel_x,
+        panel_y,
+        panel_width,
+        panel_height
+    );
+
+    let mtm = MainThreadMarker::new().unwrap();
+
+    let panel = NSPanel::initWithContentRect_styleMask_backing_defer(
+        NSPanel::alloc(mtm),
+        panel_frame,
+        panel_style_mask(config),
+        NSBackingStoreType::Buffered,
+        false,
+    );
+
+    panel.setLevel(if config.attach_to_target {
+        window.layer as isize
+    } else {
+        config.level.window_level()
+    });
+    panel.setOpaque(false);
+    panel.setAlphaValue(config.alpha);
+    panel.setHasShadow(!config.is_highlight());
+    panel.setMovableByWindowBackground(!config.is_highlight());
+    panel.setHidesOnDeactivate(false);
+    panel.setBecomesKeyOnlyIfNeeded(!config.is_focusable());
+    panel.setIgnoresMouseEvents(config.click_through || config.is_highlight());
+    panel.setCollectionBehavior(overlay_collection_behavior(false));
+
+    let window_title = NSString::from_str(&config.window_title_for(window));
+    panel.setTitle(&window_title);
+
+    let content_frame = NSRect::new(
+        NSPoint::new(0.0, 0.0),
+        NSSize::new(panel_width, panel_height),
+    );
+    match config.style {
+        OverlayStyle::Panel => add_panel_content(&panel, content_frame, config, window, mtm),
+        OverlayStyle::HighlightBorder { thickness, color } => {
+            let color = match color {
+                Some((red, green, blue, alpha)) => {
+                    NSColor::colorWithSRGBRed_green_blue_alpha(red, green, blue, alpha)
+                }
+                None => app_color(window.app_key()),
+            };
+            panel.setBackgroundColor(Some(&NSColor::clearColor()));
+            let border = BorderView::new(
+                content_frame,
+                BorderViewIvars {
+                    thickness,
+                    color,
+                    corner_radius: config.corner_radius,
+                },
+                mtm,
+            );
+            panel.setContentView(Some(&border));
+        }
+    }
+
+    if config.is_focusable() {
+        panel.makeKeyAndOrderFront(None);
+    }
+    panel.orderFrontRegardless();
+    if config.attach_to_target {
+        order_above_target(&panel, window.window_number);
+    }
+
+    log::debug!("Panel should now be visible!");
+    log::debug!(
+        "   Panel frame: x={}, y={}, w={}, h={}",
+        panel_x,
+        panel_y,
+        panel_width,
+        panel_height
+    );
+
+    Ok(panel)
+}
+
+/// The background, content view and close button of an `OverlayStyle::Panel`.
+fn add_panel_content(
+    panel: &Retained<NSPanel>,
+    content_frame: NSRect,
+    config: &PanelConfig,
+    window: &WindowInfo,
+    mtm: MainThreadMarker,
+) {
+    let background = config.color_by_app.then(|| app_color(window.app_key()));
+    if config.corner_radius.is_square() {
+        if let Some(background) = &background {
+            panel.setBackgroundColor(Some(background));
+        }
+    } else {
+        panel.setBackgroundColor(Some(&NSColor::clearColor()));
+    }
+
+    let content_view = if config.passthrough_except_controls {
+        Retained::into_super(PassthroughView::new(content_frame, mtm))
+    } else {
+        NSView::initWithFrame(NSView::alloc(mtm), content_frame)
+    };
+
+    panel.setContentView(Some(&content_view));
+
+    if !config.corner_radius.is_square() {
+        let fill = background.unwrap_or_else(NSColor::windowBackgroundColor);
+        add_rounded_background(&content_view, &fill, &config.corner_radius, mtm);
+    }
+    add_content_view(&content_view, config, window, mtm);
+
+    let close_button_size = 30.0;
+    let close_button_margin = 10.0;
+    let close_button_frame = NSRect::new(
+        NSPoint::new(
+            content_frame.size.width - close_button_size - close_button_margin,
+            content_frame.size.height - close_button_size - close_button_margin,
+        ),
+        NSSize::new(close_button_size, close_button_size),
+    );
+
+    let close_button = NSButton::initWithFrame(NSButton::alloc(mtm), close_button_frame);
+    let close_title = NSString::from_str("✕");
+    close_button.setTitle(&close_title);
+    unsafe { close_button.setTarget(Some(panel)) };
+    unsafe { close_button.setAction(Some(objc2::sel!(orderOut:))) };
+    configure_focus(&close_button, config.is_focusable());
+
+    content_view.addSubview(&close_button);
+}
+
+struct BorderViewIvars {
+    thickness: f64,
+    color: Retained<NSColor>,
+    corner_radius: CornerRadius,
+}
+
+define_class!(
+    // SAFETY: NSView has no subclassing requirements and `BorderView` does not
+    // implement `Drop`.
+    #[unsafe(super(NSView))]
+    #[thread_kind = MainThreadOnly]
+    #[name = "PanelDetectorBorderView"]
+    #[ivars = BorderViewIvars]
+    struct BorderView;
+
+    impl BorderView {
+        #[unsafe(method(drawRect:))]
+        fn draw_rect(&self, _dirty_rect: NSRect) {
+            self.draw_border();
+        }
+    }
+);
+
+impl BorderView {
+    fn new(frame: NSRect, ivars: BorderViewIvars, mtm: MainThreadMarker) -> Retained<Self> {
+        let this = Self::alloc(mtm).set_ivars(ivars);
+        unsafe { msg_send![super(this), initWithFrame: frame] }
+    }
+
+    /// Strokes along the middle of a `thickness`-wide band at the view's
+    /// edge, which `to_overlay_frame` sizes to sit just outside the target.
+    fn draw_border(&self) {
+        let ivars = self.ivars();
+        let bounds = self.bounds();
+        let inset = ivars.thickness / 2.0;
+        let rect = NSRect::new(
+            NSPoint::new(bounds.origin.x + inset, bounds.origin.y + inset),
+            NSSize::new(
+                (bounds.size.width - ivars.thickness).max(0.0),
+                (bounds.size.height - ivars.thickness).max(0.0),
+            ),
+        );
+        let radius = ivars.corner_radius.radius_for(rect.size);
+        let path = NSBezierPath::bezierPathWithRoundedRect_xRadius_yRadius(rect, radius, radius);
+        path.setLineWidth(ivars.thickness);
+        ivars.color.setStroke();
+        path.stroke();
+    }
+}
+
+/// Puts `panel` directly above `window_number` in the window order, which
+/// only holds while both share a window level. Hidden panels stay hidden.
+pub fn order_above_target(panel: &NSPanel, window_number: WindowNumber) {
+    if panel.isVisible() {
+        panel.orderWindow_relativeTo(NSWindowOrderingMode::Above, window_number.0 as isize);
+    }
+}
+
+/// Keeps an attached panel (see `PanelConfig::attach_to_target`) on its
+/// target from AX notifications: it is repositioned and reordered above the
+/// target as soon as the target moves or resizes, keeping whatever offset it
+/// had from its placement, retitled with it, and ordered out when the target
+/// closes. Drop the returned observer to stop, but never from inside its own
+/// callback, so closing here only hides the panel and leaves removing it to
+/// the caller.
+#[cfg(feature = "accessibility")]
+pub fn follow_target(
+    panel: &Retained<NSPanel>,
+    window: &WindowInfo,
+    config: &PanelConfig,
+    mtm: MainThreadMarker,
+) -> Result<crate::ax::WindowObserver, crate::ax::AxError> {
+    use crate::ax::{find_window_element, observe_window, AxWindowEvent};
+    use crate::screen::SystemScreens;
+
+    let panel = Retained::clone(panel);
+    let config = config.clone();
+    let target = RefCell::new(window.clone());
+    let (pid, window_number) = (window.pid, window.window_number);
+
+    observe_window(pid.0, window_number.0, move |event| match event {
+        AxWindowEvent::Moved | AxWindowEvent::Resized => {
+            let Some(bounds) =
+                find_window_element(pid.0, window_number.0).and_then(|element| element.frame())
+            else {
+                return;
+            };
+            let mut target = target.borrow_mut();
+            let screens = SystemScreens::new(mtm);
+            let Ok(previous) = to_overlay_frame(&target, &screens, &config) else {
+                return;
+            };
+            target.bounds = bounds;
+            if let Ok(placement) = to_overlay_frame(&target, &screens, &config) {
+                let current = panel.frame();
+                let frame = NSRect::new(
+                    NSPoint::new(
+                        placement.origin.x + current.origin.x - previous.origin.x,
+                        placement.origin.y + current.origin.y - previous.origin.y,
+                    ),
+                    placement.size,
+                );
+                panel.setFrame_display(frame, true);
+                update_corner_radius(&panel, &config, frame.size);
+                order_above_target(&panel, window_number);
+            }
+        }
+        AxWindowEvent::TitleChanged => {
+            let title = find_window_element(pid.0, window_number.0)
+                .and_then(|element| element.string_attribute("AXTitle"));
+            let mut target = target.borrow_mut();
+            let mut retitled = target.clone();
+            retitled.title = title;
+            update_panel(&panel, &target, &retitled, &config);
+            *target = retitled;
+        }
+        AxWindowEvent::Destroyed => panel.orderOut(None),
+    })
+}
+
+/// Orders `panel` out after `after` on the main run loop, then calls
+/// `on_dismiss`. Invalidate the returned timer to cancel, e.g. when the panel is
+/// closed earlier for another reason.
+pub fn schedule_dismiss(
+    panel: &Retained<NSPanel>,
+    after: Duration,
+    on_dismiss: impl Fn() + 'static,
+) -> Retained<NSTimer> {
+    let panel = Retained::clone(panel);
+    unsafe {
+        NSTimer::scheduledTimerWithTimeInterval_repeats_block(
+            after.as_secs_f64(),
+            false,
+            &block2::RcBlock::new(move |_timer: NonNull<NSTimer>| {
+                panel.orderOut(None);
+                on_dismiss();
+            }),
+        )
+    }
+}
+
+pub fn app_color(app_key: &str) -> Retained<NSColor> {
+    let (red, green, blue, alpha) = app_color_components(app_key);
+    NSColor::colorWithSRGBRed_green_blue_alpha(red, green, blue, alpha)
+}
+
+/// Deterministic RGBA for an app: the key's stable hash picks the hue, while
+/// saturation and value stay fixed so every app color is equally legible.
+pub fn app_color_components(app_key: &str) -> (f64, f64, f64, f64) {
+    let hue = app_hue_degrees(app_key) as f64 / 360.0;
+    let (red, green, blue) = hsv_to_rgb(hue, APP_COLOR_SATURATION, APP_COLOR_VALUE);
+    (red, green, blue, 1.0)
+}
+
+fn app_hue_degrees(app_key: &str) -> u64 {
+    let mut hasher = StableHasher::new();
+    hasher.write_str(app_key);
+    hasher.finish() % 360
+}
+
+fn hsv_to_rgb(hue: f64, saturation: f64, value: f64) -> (f64, f64, f64) {
+    let sector = (hue.rem_euclid(1.0) * 6.0).min(5.999_999);
+    let fraction = sector.fract();
+    let p = value * (1.0 - saturation);
+    let q = value * (1.0 - saturation * fraction);
+    let t = value * (1.0 - saturation * (1.0 - fraction));
+
+    match sector as u8 {
+        0 => (value, t, p),
+        1 => (q, value, p),
+        2 => (p, value, t),
+        3 => (p, q, value),
+        4 => (t, p, value),
+        _ => (value, p, q),
+    }
+}
+
+/// Applies the fields that changed between `previous` and `window` to an
+/// existing panel, leaving untouched subviews alone to avoid flicker and
+/// relayout. Returns whether anything was updated. The frame is not handled
+/// here since it also depends on placement and cascading.
+pub fn update_panel(
+    panel: &NSPanel,
+    previous: &WindowInfo,
+    window: &WindowInfo,
+    config: &PanelConfig,
+) -> bool {
+    let changed = ChangedFields::between(previous, window);
+    if !changed.any() {
+        return false;
+    }
+
+    panel.setTitle(&NSString::from_str(&config.window_title_for(window)));
+
+    if let Some(content) = subview_with_identifier(panel, CONTENT_IDENTIFIER) {
+        config
+            .content_provider()
+            .update_content(&content, window, changed);
+        let click_target = content
+            .downcast_ref::<NSButton>()
+            .and_then(|button| button.target())
+            .and_then(|target| target.downcast::<ClickTarget>().ok());
+        if let Some(click_target) = click_target {
+            *click_target.ivars().window.borrow_mut() = window.clone();
+        }
+        #[cfg(feature = "webview")]
+        if let Some(web_view) = content.downcast_ref::<WKWebView>() {
+            update_message_window(web_view, window);
+        }
+    }
+
+    true
+}
+
+fn expand_placeholders(template: &str, window: &WindowInfo) -> String {
+    template
+        .replace("{app}", &window.app_name)
+        .replace("{title}", window.display_title())
+        .replace("{pid}", &window.pid.to_string())
+}
+
+fn content_button_title(window: &WindowInfo) -> String {
+    format!("PANEL DETECTED: {}", window.app_name)
+}
+
+/// Sets the panel's progress indicator, which may sit inside stack views as in
+/// `PanelContent::Stack`.
+pub fn set_panel_progress(panel: &NSPanel, fraction: f64) -> Result<(), PanelError> {
+    let indicator = panel
+        .contentView()
+        .and_then(|content_view| {
+            find_depth_first(
+                content_view,
+                &|view: &Retained<NSView>| view.subviews().to_vec(),
+                &|view: &Retained<NSView>| view.clone().downcast::<NSProgressIndicator>().ok(),
+            )
+        })
+        .ok_or(PanelError::NoProgressIndicator)?;
+
+    show_progress(&*indicator, fraction);
+    Ok(())
+}
+
+// The indicator calls `PanelContent::Progress` and `set_panel_progress` make.
+trait ProgressControl {
+    fn set_range(&self, min: f64, max: f64);
+    fn is_indeterminate(&self) -> bool;
+    fn set_indeterminate(&self, indeterminate: bool);
+    fn set_animating(&self, animating: bool);
+    fn set_value(&self, value: f64);
+}
+
+impl ProgressControl for NSProgressIndicator {
+    fn set_range(&self, min: f64, max: f64) {
+        self.setMinValue(min);
+        self.setMaxValue(max);
+    }
+
+    fn is_indeterminate(&self) -> bool {
+        self.isIndeterminate()
+    }
+
+    fn set_indeterminate(&self, indeterminate: bool) {
+        self.setIndeterminate(indeterminate);
+    }
+
+    fn set_animating(&self, animating: bool) {
+        if animating {
+            unsafe { self.startAnimation(None) };
+        } else {
+            unsafe { self.stopAnimation(None) };
+        }
+    }
+
+    fn set_value(&self, value: f64) {
+        self.setDoubleValue(value);
+    }
+}
+
+// A new indicator for `PanelContent::Progress`: a fraction bar, or a running
+// spinner until `set_panel_progress` gives it a fraction.
+fn start_progress(indicator: &impl ProgressControl, indeterminate: bool) {
+    indicator.set_range(0.0, 1.0);
+    indicator.set_indeterminate(indeterminate);
+    if indeterminate {
+        indicator.set_animating(true);
+    }
+}
+
+fn show_progress(indicator: &impl ProgressControl, fraction: f64) {
+    if indicator.is_indeterminate() {
+        indicator.set_animating(false);
+        indicator.set_indeterminate(false);
+    }
+    indicator.set_value(clamp_progress(fraction));
+}
+
+/// What `pick` returns for the first node, depth first from `root`, it returns
+/// anything for.
+fn find_depth_first<N, T>(
+    root: N,
+    children: &impl Fn(&N) -> Vec<N>,
+    pick: &impl Fn(&N) -> Option<T>,
+) -> Option<T> {
+    pick(&root).or_else(|| {
+        children(&root)
+            .into_iter()
+            .find_map(|child| find_depth_first(child, children, pick))
+    })
+}
+
+fn clamp_progress(fraction: f64) -> f64 {
+    if fraction.is_nan() {
+        0.0
+    } else {
+        fraction.clamp(0.0, 1.0)
+    }
+}
+
+/// Whether `hit` is a control or lies inside one, such as a button's label,
+/// looking no further up than `is_root`, which is excluded.
+fn within_control<N>(
+    hit: N,
+    parent: impl Fn(&N) -> Option<N>,
+    is_root: impl Fn(&N) -> bool,
+    is_control: impl Fn(&N) -> bool,
+) -> bool {
+    std::iter::successors(Some(hit), parent)
+        .take_while(|view| !is_root(view))
+        .any(|view| is_control(&view))
+}
+
+define_class!(
+    // SAFETY: NSView has no subclassing requirements and `PassthroughView` does
+    // not implement `Drop`.
+    #[unsafe(super(NSView))]
+    #[thread_kind = MainThreadOnly]
+    #[name = "PanelDetectorPassthroughView"]
+    struct PassthroughView;
+
+    impl PassthroughView {
+        #[unsafe(method_id(hitTest:))]
+        fn hit_test(&self, point: NSPoint) -> Option<Retained<NSView>> {
+            self.control_hit_test(point)
+        }
+    }
+);
+
+impl PassthroughView {
+    fn new(frame: NSRect, mtm: MainThreadMarker) -> Retained<Self> {
+        let this = Self::alloc(mtm).set_ivars(());
+        unsafe { msg_send![super(this), initWithFrame: frame] }
+    }
+
+    /// AppKit's own hit test, kept only when it landed in a control.
+    fn control_hit_test(&self, point: NSPoint) -> Option<Retained<NSView>> {
+        let hit: Option<Retained<NSView>> = unsafe { msg_send![super(self), hitTest: point] };
+        let hit = hit?;
+        let root: &NSView = self;
+        within_control(
+            hit.clone(),
+            |view| unsafe { view.superview() },
+            |view| std::ptr::eq(&**view, root),
+            |view| view.downcast_ref::<NSControl>().is_some(),
+        )
+        .then_some(hit)
+    }
+}
+
+fn panel_style_mask(config: &PanelConfig) -> NSWindowStyleMask {
+    if config.is_focusable() {
+        NSWindowStyleMask::Borderless
+    } else {
+        NSWindowStyleMask::Borderless | NSWindowStyleMask::NonactivatingPanel
+    }
+}
+
+fn configure_focus(control: &NSControl, focusable: bool) {
+    control.setRefusesFirstResponder(!focusable);
+    control.setFocusRingType(focus_ring_type(focusable));
+}
+
+fn focus_ring_type(focusable: bool) -> NSFocusRingType {
+    if focusable {
+        NSFocusRingType::Default
+    } else {
+        NSFocusRingType::None
+    }
+}
+
+// A (location, length) in UTF-16 code units, as `NSRange` expects.
+type Utf16Range = (usize, usize);
+
+/// The runs' concatenated text, and each run with its range in it.
+fn assemble_runs(runs: &[TextRun]) -> (String, Vec<(&TextRun, Utf16Range)>) {
+    let mut text = String::new();
+    let mut location = 0;
+    let ranges = runs
+        .iter()
+        .map(|run| {
+            text.push_str(&run.text);
+            let length = run.text.encode_utf16().count();
+            let range = (location, length);
+            location += length;
+            (run, range)
+        })
+        .collect();
+    (text, ranges)
+}
+
+fn attributed_text(runs: &[TextRun]) -> Retained<NSMutableAttributedString> {
+    let (text, ranges) = assemble_runs(runs);
+    let attributed = NSMutableAttributedString::initWithString(
+        NSMutableAttributedString::alloc(),
+        &NSString::from_str(&text),
+    );
+
+    for (run, (location, length)) in ranges {
+        let range = NSRange::new(location, length);
+        let font = NSFont::systemFontOfSize_weight(run.font_size, font_weight(run.weight));
+        let color = match run.color {
+            Some((red, green, blue, alpha)) => {
+                NSColor::colorWithSRGBRed_green_blue_alpha(red, green, blue, alpha)
+            }
+            None => NSColor::labelColor(),
+        };
+
+        unsafe {
+            attributed.addAttribute_value_range(NSFontAttributeName, &font, range);
+            attributed.addAttribute_value_range(NSForegroundColorAttributeName, &color, range);
+        }
+    }
+
+    attributed
+}
+
+fn font_weight(weight: TextWeight) -> f64 {
+    unsafe {
+        match weight {
+            TextWeight::Light => NSFontWeightLight,
+            TextWeight::Regular => NSFontWeightRegular,
+            TextWeight::Medium => NSFontWeightMedium,
+            TextWeight::Semibold => NSFontWeightSemibold,
+            TextWeight::Bold => NSFontWeightBold,
+        }
+    }
+}
+
+fn add_content_view(
+    content_view: &NSView,
+    config: &PanelConfig,
+    window: &WindowInfo,
+    mtm: MainThreadMarker,
+) {
+    let content = config
+        .content_provider()
+        .build_content(window, content_view.frame(), mtm);
+    if let Some(control) = content.downcast_ref::<NSControl>() {
+        configure_focus(control, config.is_focusable());
+    }
+    if let (Some(button), Some(handler)) = (content.downcast_ref::<NSButton>(), &config.on_click) {
+        attach_click_handler(button, handler.clone(), window, mtm);
+    }
+    if config.show_app_icon {
+        let button = content
+            .downcast_ref::<NSButton>()
+            .filter(|button| button.tag() == CONTENT_BUTTON_TAG);
+        if let (Some(button), Some(icon)) = (button, window.app_icon()) {
+            let icon = icon.copy();
+            icon.setSize(NSSize::new(APP_ICON_SIZE, APP_ICON_SIZE));
+            button.setImage(Some(&icon));
+            button.setImagePosition(NSCellImagePosition::ImageLeading);
+        }
+    }
+    #[cfg(feature = "webview")]
+    if let (Some(web_view), Some(handler)) =
+        (content.downcast_ref::<WKWebView>(), &config.on_web_message)
+    {
+        attach_message_handler(web_view, handler.clone(), window, mtm);
+    }
+    content.setIdentifier(Some(&NSString::from_str(CONTENT_IDENTIFIER)));
+    content_view.addSubview(&content);
+}
+
+// A control's target is unretained, so the button keeps its `ClickTarget`
+// alive as an associated object.
+fn attach_click_handler(
+    button: &NSButton,
+    handler: ClickHandler,
+    window: &WindowInfo,
+    mtm: MainThreadMarker,
+) {
+    let target = ClickTarget::new(handler, window.clone(), mtm);
+    unsafe {
+        button.setTarget(Some(&target));
+        button.setAction(Some(sel!(panelButtonClicked:)));
+    }
+    associate_click_target(button, Some(&target));
+}
+
+// Makes `owner` retain `target`, releasing the one it held before; `None`
+// only releases.
+fn associate_click_target(owner: &AnyObject, target: Option<&ClickTarget>) {
+    let target = target.map_or(std::ptr::null(), |target| target as *const ClickTarget);
+    unsafe {
+        objc2::ffi::objc_setAssociatedObject(
+            (owner as *const AnyObject).cast_mut(),
+            (&CLICK_TARGET_KEY as *const u8).cast(),
+            target.cast_mut().cast(),
+            objc2::ffi::OBJC_ASSOCIATION_RETAIN_NONATOMIC,
+        );
+    }
+}
+
+/// Drops the closures from `PanelConfig::on_click` and `on_web_message` held
+/// by `panel`'s content right away, rather than whenever AppKit frees the
+/// panel; its button and page stop reaching Rust. `PanelManager` does this for
+/// every panel it closes.
+pub fn release_panel_callbacks(panel: &NSPanel) {
+    let Some(content) = subview_with_identifier(panel, CONTENT_IDENTIFIER) else {
+        return;
+    };
+    if let Some(button) = content.downcast_ref::<NSButton>() {
+        let has_click_target = button
+            .target()
+            .is_some_and(|target| target.downcast::<ClickTarget>().is_ok());
+        if has_click_target {
+            unsafe {
+                button.setTarget(None);
+                button.setAction(None);
+            }
+        }
+        associate_click_target(button, None);
+    }
+    #[cfg(feature = "webview")]
+    if let Some(web_view) = content.downcast_ref::<WKWebView>() {
+        detach_message_handler(web_view);
+    }
+}
+
+struct ClickTargetIvars {
+    handler: ClickHandler,
+    window: RefCell<WindowInfo>,
+}
+
+define_class!(
+    // SAFETY: NSObject has no subclassing requirements and `ClickTarget` does
+    // not implement `Drop`.
+    #[unsafe(super(NSObject))]
+    #[thread_kind = MainThreadOnly]
+    #[name = "PanelDetectorClickTarget"]
+    #[ivars = ClickTargetIvars]
+    struct ClickTarget;
+
+    impl ClickTarget {
+        #[unsafe(method(panelButtonClicked:))]
+        fn button_clicked(&self, _sender: Option<&AnyObject>) {
+            // Retained in case the handler closes the panel, which releases
+            // this target, and cloned so it can trigger a panel update.
+            let this = self.retain();
+            let window = this.ivars().window.borrow().clone();
+            (this.ivars().handler.0)(&window);
+        }
+    }
+);
+
+impl ClickTarget {
+    fn new(handler: ClickHandler, window: WindowInfo, mtm: MainThreadMarker) -> Retained<Self> {
+        let this = Self::alloc(mtm).set_ivars(ClickTargetIvars {
+            handler,
+            window: RefCell::new(window),
+        });
+        unsafe { msg_send![super(this), init] }
+    }
+}
+
+fn add_rounded_background(
+    content_view: &NSView,
+    fill: &NSColor,
+    corner_radius: &CornerRadius,
+    mtm: MainThreadMarker,
+) {
+    let frame = content_view.bounds();
+    let background = NSBox::initWithFrame(NSBox::alloc(mtm), frame);
+    background.setBoxType(NSBoxType::Custom);
+    background.setTitlePosition(NSTitlePosition::NoTitle);
+    background.setBorderWidth(0.0);
+    background.setFillColor(fill);
+    background.setCornerRadius(corner_radius.radius_for(frame.size));
+    background.setAutoresizingMask(
+        NSAutoresizingMaskOptions::ViewWidthSizable | NSAutoresizingMaskOptions::ViewHeightSizable,
+    );
+    background.setIdentifier(Some(&NSString::from_str(BACKGROUND_IDENTIFIER)));
+    content_view.addSubview_positioned_relativeTo(&background, NSWindowOrderingMode::Below, None);
+}
+
+/// Recomputes a rounded panel's corner radius for its new `size`; call after
+/// resizing it. Does nothing for square panels.
+pub fn update_corner_radius(panel: &NSPanel, config: &PanelConfig, size: NSSize) {
+    let background = subview_with_identifier(panel, BACKGROUND_IDENTIFIER)
+        .and_then(|view| view.downcast::<NSBox>().ok());
+    if let Some(background) = background {
+        background.setCornerRadius(config.corner_radius.radius_for(size));
+        panel.invalidateShadow();
+    }
+}
+
+fn subview_with_identifier(panel: &NSPanel, identifier: &str) -> Option<Retained<NSView>> {
+    let identifier = NSString::from_str(identifier);
+    panel.contentView()?.subviews().iter().find(|view| {
+        view.identifier()
+            .is_some_and(|view_identifier| view_identifier.isEqualToString(&identifier))
+    })
+}
+
+impl PanelContent {
+    // Whether `update_content` would redraw anything: the button shows the app
+    // and labels whichever placeholders they use.
+    fn is_affected_by(&self, changed: ChangedFields) -> bool {
+        match self {
+            PanelContent::Button => changed.app,
+            PanelContent::Label(run) => {
+                (changed.title && run.text.contains("{title}"))
+                    || (changed.app && run.text.contains("{app}"))
+            }
+            PanelContent::Stack { children, .. } => {
+                children.iter().any(|child| child.is_affected_by(changed))
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The built-in modes, used unless `PanelConfig::with_content_provider` is set.
+impl PanelContentProvider for PanelContent {
+    fn build_content(
+        &self,
+        window: &WindowInfo,
+        frame: NSRect,
+        mtm: MainThreadMarker,
+    ) -> Retained<NSView> {
+        let bounds = frame.size;
+        let element_width = bounds.width * 0.8;
+        let element_x = (bounds.width - element_width) / 2.0;
+
+        match self {
+            PanelContent::Button => {
+                let button_height = bounds.height * 0.3;
+                let button_frame = NSRect::new(
+                    NSPoint::new(element_x, (bounds.height - button_height) / 2.0),
+                    NSSize::new(element_width, button_height),
+                );
+
+                let button = NSButton::initWithFrame(NSButton::alloc(mtm), button_frame);
+
+                let title = NSString::from_str(&content_button_title(window));
+                button.setTitle(&title);
+                button.setTag(CONTENT_BUTTON_TAG);
+
+                Retained::into_super(Retained::into_super(button))
+            }
+            PanelContent::Progress { indeterminate } => {
+                let progress_frame = NSRect::new(
+                    NSPoint::new(element_x, (bounds.height - PROGRESS_BAR_HEIGHT) / 2.0),
+                    NSSize::new(element_width, PROGRESS_BAR_HEIGHT),
+                );
+
+                let indicator = NSProgressIndicator::initWithFrame(
+                    NSProgressIndicator::alloc(mtm),
+                    progress_frame,
+                );
+                indicator.setStyle(NSProgressIndicatorStyle::Bar);
+                start_progress(&*indicator, *indeterminate);
+
+                Retained::into_super(indicator)
+            }
+            PanelContent::AttributedText(runs) => {
+                let text_height = bounds.height * 0.6;
+                let text_frame = NSRect::new(
+                    NSPoint::new(element_x, (bounds.height - text_height) / 2.0),
+                    NSSize::new(element_width, text_height),
+                );
+
+                let label = NSTextField::labelWithAttributedString(&attributed_text(runs), mtm);
+                label.setFrame(text_frame);
+                label.setEditable(false);
+                label.setSelectable(false);
+                label.setMaximumNumberOfLines(0);
+
+                Retained::into_super(Retained::into_super(label))
+            }
+            PanelContent::Label(run) => {
+                let label = text_label(run, window, element_width, mtm);
+                let size = label.fittingSize();
+                label.setFrame(NSRect::new(
+                    NSPoint::new(element_x, (bounds.height - size.height) / 2.0),
+                    NSSize::new(element_width, size.height),
+                ));
+                Retained::into_super(Retained::into_super(label))
+            }
+            PanelContent::Image { source, size } => {
+                let image_view = image_view(source, *size, window, mtm);
+                image_view.setFrame(NSRect::new(
+                    NSPoint::new(
+                        (bounds.width - size.0) / 2.0,
+                        (bounds.height - size.1) / 2.0,
+                    ),
+                    NSSize::new(size.0, size.1),
+                ));
+                Retained::into_super(Retained::into_super(image_view))
+            }
+            PanelContent::Stack {
+                axis,
+                spacing,
+                children,
+            } => {
+                let stack = NSStackView::new(mtm);
+                let (orientation, alignment) = match axis {
+                    StackAxis::Horizontal => (
+                        NSUserInterfaceLayoutOrientation::Horizontal,
+                        NSLayoutAttribute::CenterY,
+                    ),
+                    StackAxis::Vertical => (
+                        NSUserInterfaceLayoutOrientation::Vertical,
+                        NSLayoutAttribute::CenterX,
+                    ),
+                };
+                stack.setOrientation(orientation);
+                stack.setAlignment(alignment);
+                stack.setSpacing(*spacing);
+                stack.setFrame(NSRect::new(
+                    NSPoint::new(element_x, bounds.height * 0.1),
+                    NSSize::new(element_width, bounds.height * 0.8),
+                ));
+                // Arranged views are sized by their content, not by `frame`.
+                for child in children {
+                    stack.addArrangedSubview(&child.build_content(window, frame, mtm));
+                }
+                Retained::into_super(stack)
+            }
+            #[cfg(feature = "webview")]
+            PanelContent::WebView(source) => {
+                let web_frame = NSRect::new(NSPoint::new(0.0, 0.0), bounds);
+                Retained::into_super(build_web_view(source, web_frame, mtm))
+            }
+        }
+    }
+
+    fn update_content(&self, view: &NSView, window: &WindowInfo, changed: ChangedFields) {
+        if !self.is_affected_by(changed) {
+            return;
+        }
+        match self {
+            PanelContent::Button => {
+                if let Some(button) = view.downcast_ref::<NSButton>() {
+                    if button.tag() == CONTENT_BUTTON_TAG {
+                        button.setTitle(&NSString::from_str(&content_button_title(window)));
+                    }
+                }
+            }
+            PanelContent::Label(run) => {
+                if let Some(label) = view.downcast_ref::<NSTextField>() {
+                    label.setStringValue(&NSString::from_str(&expand_placeholders(
+                        &run.text, window,
+                    )));
+                }
+            }
+            PanelContent::Stack { children, .. } => {
+                if let Some(stack) = view.downcast_ref::<NSStackView>() {
+                    for (child, view) in children.iter().zip(stack.arrangedSubviews().iter()) {
+                        child.update_content(&view, window, changed);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn text_label(
+    run: &TextRun,
+    window: &WindowInfo,
+    max_width: f64,
+    mtm: MainThreadMarker,
+) -> Retained<NSTextField> {
+    let text = NSString::from_str(&expand_placeholders(&run.text, window));
+    let label = NSTextField::wrappingLabelWithString(&text, mtm);
+    label.setFont(Some(&NSFont::systemFontOfSize_weight(
+        run.font_size,
+        font_weight(run.weight),
+    )));
+    let color = match run.color {
+        Some((red, green, blue, alpha)) => {
+            NSColor::colorWithSRGBRed_green_blue_alpha(red, green, blue, alpha)
+        }
+        None => NSColor::labelColor(),
+    };
+    label.setTextColor(Some(&color));
+    label.setPreferredMaxLayoutWidth(max_width);
+    label
+}
+
+fn image_view(
+    source: &ImageSource,
+    (width, height): (f64, f64),
+    window: &WindowInfo,
+    mtm: MainThreadMarker,
+) -> Retained<NSImageView> {
+    let image = match source {
+        ImageSource::AppIcon => window.app_icon(),
+        ImageSource::File(path) => path.to_str().and_then(|path| {
+            NSImage::initWithContentsOfFile(NSImage::alloc(), &NSString::from_str(path))
+        }),
+        ImageSource::Named(name) => NSImage::imageNamed(&NSString::from_str(name)),
+        ImageSource::Symbol(name) => NSImage::imageWithSystemSymbolName_accessibilityDescription(
+            &NSString::from_str(name),
+            None,
+        ),
+    }
+    .unwrap_or_else(|| {
+        log::warn!("Could not load panel image {:?}", source);
+        NSImage::initWithSize(NSImage::alloc(), NSSize::new(width, height))
+    });
+    // A copy, so sizing a shared named image doesn't resize it everywhere;
+    // the view's intrinsic size then follows, which stacks lay out by.
+    let image = image.copy();
+    image.setSize(NSSize::new(width, height));
+
+    let image_view = NSImageView::imageViewWithImage(&image, mtm);
+    image_view.setImageScaling(NSImageScaling::ScaleProportionallyUpOrDown);
+    image_view
+}
+
+pub fn to_overlay_frame(
+    window: &WindowInfo,
+    screens: &dyn ScreenProvider,
+    config: &PanelConfig,
+) -> Result<NSRect, PanelError> {
+    if !window.bounds.is_valid() {
+        return Err(PanelError::InvalidBounds(window.bounds));
+    }
+
+    let all_screens = screens.screens();
+    let primary_height = all_screens
+        .first()
+        .ok_or(PanelError::NoScreen)?
+        .frame
+        .size
+        .height;
+    let screen = screen_for_bounds(&all_screens, &window.bounds)
+        .or_else(|| screens.main_screen())
+        .ok_or(PanelError::NoScreen)?;
+
+    if let OverlayStyle::HighlightBorder { thickness, .. } = config.style {
+        let thickness = thickness.max(0.0);
+        let outline = WindowBounds::new(
+            window.bounds.x - thickness,
+            window.bounds.y - thickness,
+            window.bounds.width + 2.0 * thickness,
+            window.bounds.height + 2.0 * thickness,
+        );
+        return Ok(cg_rect_to_ns(&outline, primary_height));
+    }
+
+    let (panel_size, extra_width) = if config.match_target_size {
+        (NSSize::new(window.bounds.width, window.bounds.height), 0.0)
+    } else {
+        (
+            NSSize::new(
+                config.points(config.panel_size.width, &screen),
+                config.points(config.panel_size.height, &screen),
+            ),
+            config.points(config.extra_width, &screen),
+        )
+    };
+
+    let mut frame = placement_bounds(
+        config.placement,
+        &window.bounds,
+        panel_size,
+        extra_width,
+        &screen.cg_visible_frame(primary_height),
+    );
+    frame.x += config.points(config.offset.0, &screen);
+    frame.y += config.points(config.offset.1, &screen);
+
+    Ok(cg_rect_to_ns(&frame, primary_height))
+}
+
+/// Shifts each frame that substantially overlaps an earlier one right and down
+/// by `step` points, like AppKit's new-window cascading, until it sits clear of
+/// every frame before it.
+pub fn cascade_frames(frames: &mut [NSRect], step: f64) {
+    for i in 1..frames.len() {
+        let (placed, rest) = frames.split_at_mut(i);
+        let frame = &mut rest[0];
+
+        for _ in 0..MAX_CASCADE_STEPS {
+            if !placed
+                .iter()
+                .any(|other| overlaps_significantly(frame, other))
+            {
+                break;
+            }
+            frame.origin.x += step;
+            frame.origin.y -= step;
+        }
+    }
+}
+
+fn overlaps_significantly(a: &NSRect, b: &NSRect) -> bool {
+    let overlap_width =
+        (a.origin.x + a.size.width).min(b.origin.x + b.size.width) - a.origin.x.max(b.origin.x);
+    let overlap_height =
+        (a.origin.y + a.size.height).min(b.origin.y + b.size.height) - a.origin.y.max(b.origin.y);
+    if overlap_width <= 0.0 || overlap_height <= 0.0 {
+        return false;
+    }
+
+    let smaller_area = (a.size.width * a.size.height).min(b.size.width * b.size.height);
+    smaller_area > 0.0 && overlap_width * overlap_height >= smaller_area * CASCADE_OVERLAP_FRACTION
+}
+
+fn placement_bounds(
+    placement: PanelPlacement,
+    window: &WindowBounds,
+    panel_size: NSSize,
+    extra_width: f64,
+    visible: &WindowBounds,
+) -> WindowBounds {
+    let (width, height) = (panel_size.width, panel_size.height);
+
+    match placement {
+        PanelPlacement::Overlay => WindowBounds::new(
+            window.x,
+            window.y,
+            window.width + extra_width,
+            window.height,
+        ),
+        PanelPlacement::Right => WindowBounds::new(window.right(), window.y, width, height),
+        PanelPlacement::Left => WindowBounds::new(window.x - width, window.y, width, height),
+        PanelPlacement::Above => WindowBounds::new(window.x, window.y - height, width, height),
+        PanelPlacement::Below => WindowBounds::new(window.x, window.bottom(), width, height),
+        PanelPlacement::Auto => placement_bounds(
+            choose_auto_placement(window, panel_size, visible),
+            window,
+            panel_size,
+            extra_width,
+            visible,
+        ),
+    }
+}
+
+fn choose_auto_placement(
+    window: &WindowBounds,
+    panel_size: NSSize,
+    visible: &WindowBounds,
+) -> PanelPlacement {
+    let fits_horizontally = panel_size.height <= visible.height;
+    let fits_vertically = panel_size.width <= visible.width;
+
+    if fits_horizontally && window.right() + panel_size.width <= visible.right() {
+        PanelPlacement::Right
+    } else if fits_horizontally && window.x - panel_size.width >= visible.x {
+        PanelPlacement::Left
+    } else if fits_vertically && window.y - panel_size.height >= visible.y {
+        PanelPlacement::Above
+    } else if fits_vertically && window.bottom() + panel_size.height <= visible.bottom() {
+        PanelPlacement::Below
+    } else {
+        PanelPlacement::Overlay
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::screen::tests::{rect, three_screens, FakeScreens};
+
+    // A view tree stand-in: a name and children.
+    struct Node(&'static str, Vec<Node>);
+
+    fn named<'a>(wanted: &'a str) -> impl Fn(&&Node) -> Option<&'static str> + 'a {
+        move |node| node.0.starts_with(wanted).then_some(node.0)
+    }
+
+    fn children<'a>(node: &&'a Node) -> Vec<&'a Node> {
+        node.1.iter().collect()
+    }
+
+    #[test]
+    fn depth_first_search_finds_nested_views_first() {
+        let tree = Node(
+            "content",
+            vec![
+                Node(
+                    "stack",
+                    vec![Node("label", vec![]), Node("progress 1", vec![])],
+                ),
+                Node("progress 2", vec![]),
+            ],
+        );
+
+        assert_eq!(
+            find_depth_first(&tree, &children, &named("progress")),
+            Some("progress 1")
+        );
+        assert_eq!(
+            find_depth_first(&tree, &children, &named("content")),
+            Some("content")
+        );
+        assert_eq!(find_depth_first(&tree, &children, &named("button")), None);
+    }
+
+    #[test]
+    fn hits_count_inside_controls_below_the_root_only() {
+        let parents = HashMap::from([
+            ("button label", "button"),
+            ("button", "stack"),
+            ("label", "stack"),
+            ("stack", "content"),
+            ("content", "frame"),
+        ]);
+        let hits_control = |hit: &'static str| {
+            within_control(
+                hit,
+                |view| parents.get(view).copied(),
+                |view| *view == "content",
+                |view| matches!(*view, "button" | "frame"),
+            )
+        };
+
+        assert!(hits_control("button"));
+        assert!(hits_control("button label"));
+        assert!(!hits_control("label"));
+        assert!(!hits_control("stack"));
+        assert!(!hits_control("content"));
+    }
+
+    fn window_at(bounds: WindowBounds) -> WindowInfo {
+        WindowInfo::builder().with_bounds(bounds).build()
+    }
+
+    #[test]
+    fn window_titles_interpolate_the_target() {
+        let window = WindowInfo::builder()
+            .with_app_name("TextEdit")
+            .with_title("Notes.txt")
+            .with_pid(314)
+            .build();
+        let untitled = WindowInfo::builder()
+            .with_app_name("Finder")
+            .without_title()
+            .build();
+
+        let config = PanelConfig::new();
+        assert_eq!(
+            config.window_title_for(&window),
+            "Overlay: TextEdit — Notes.txt"
+        );
+        assert_eq!(
+            config.window_title_for(&untitled),
+            "Overlay: Finder — <no title>"
+        );
+
+        let custom = PanelConfig::new().with_window_title("{app} ({pid}): {title} / {app}");
+        assert_eq!(
+            custom.window_title_for(&window),
+            "TextEdit (314): Notes.txt / TextEdit"
+        );
+    }
+
+    #[test]
+    fn window_titles_fall_back_without_target_info() {
+        let unknown = WindowInfo::builder()
+            .with_app_name("")
+            .without_title()
+            .build();
+
+        assert_eq!(
+            PanelConfig::new().window_title_for(&unknown),
+            "PANEL DETECTOR OVERLAY"
+        );
+    }
+
+    // A 200x100 pixel panel to the right of its window.
+    fn pixel_panel() -> PanelConfig {
+        PanelConfig::new()
+            .with_placement(PanelPlacement::Right)
+            .with_panel_size(200.0, 100.0)
+            .size_in_points(false)
+    }
+
+    #[test]
+    fn overlay_frames_on_a_single_retina_screen() {
+        let screens = FakeScreens {
+            screens: three_screens().screens[..1].to_vec(),
+            main: Some(0),
+        };
+        let window = window_at(WindowBounds::new(100.0, 100.0, 400.0, 300.0));
+
+        let frame = to_overlay_frame(&window, &screens, &pixel_panel()).unwrap();
+
+        assert_eq!(frame, rect(500.0, 750.0, 100.0, 50.0));
+    }
+
+    #[test]
+    fn sizes_in_points_are_never_scaled() {
+        let screens = three_screens().screens;
+        let pixels = PanelConfig::new().size_in_points(false);
+        let points = PanelConfig::new();
+
+        assert_eq!(pixels.points(300.0, &screens[0]), 150.0);
+        assert_eq!(pixels.points(300.0, &screens[1]), 300.0);
+        assert_eq!(points.points(300.0, &screens[0]), 300.0);
+        assert_eq!(points.points(300.0, &screens[1]), 300.0);
+
+        let unknown_scale = ScreenInfo::new(
+            rect(0.0, 0.0, 800.0, 600.0),
+            rect(0.0, 0.0, 800.0, 600.0),
+            0.0,
+        );
+        assert_eq!(pixels.points(300.0, &unknown_scale), 300.0);
+    }
+
+    #[test]
+    fn point_sized_panels_match_on_retina_and_standard_screens() {
+        let config = pixel_panel().size_in_points(true);
+        let retina = window_at(WindowBounds::new(100.0, 100.0, 400.0, 300.0));
+        let standard = window_at(WindowBounds::new(1500.0, -1000.0, 400.0, 300.0));
+
+        let on_retina = to_overlay_frame(&retina, &three_screens(), &config).unwrap();
+        let on_standard = to_overlay_frame(&standard, &three_screens(), &config).unwrap();
+
+        assert_eq!(on_retina.size, NSSize::new(200.0, 100.0));
+        assert_eq!(on_standard.size, NSSize::new(200.0, 100.0));
+    }
+
+    #[test]
+    fn overlay_frames_use_the_secondary_screen_they_sit_on() {
+        let window = window_at(WindowBounds::new(1500.0, -1000.0, 400.0, 300.0));
+
+        let frame = to_overlay_frame(&window, &three_screens(), &pixel_panel()).unwrap();
+
+        assert_eq!(frame, rect(1900.0, 1800.0, 200.0, 100.0));
+    }
+
+    #[test]
+    fn batches_keep_going_past_windows_that_fail() {
+        let invalid = WindowBounds::new(0.0, 0.0, 0.0, 300.0);
+        let results = WindowSearchResults {
+            total_windows: 3,
+            matched_windows: vec![
+                window_at(WindowBounds::new(100.0, 100.0, 400.0, 300.0)),
+                window_at(invalid),
+                window_at(WindowBounds::new(1500.0, -1000.0, 400.0, 300.0)),
+            ],
+        };
+        let screens = three_screens();
+
+        let frames = create_each(&results, |window| {
+            to_overlay_frame(window, &screens, &pixel_panel())
+        });
+
+        assert_eq!(
+            frames,
+            [
+                Ok(rect(500.0, 750.0, 100.0, 50.0)),
+                Err(PanelError::InvalidBounds(invalid)),
+                Ok(rect(1900.0, 1800.0, 200.0, 100.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn only_fullscreen_targets_get_auxiliary_panels() {
+        let regular = overlay_collection_behavior(false);
+        let fullscreen = overlay_collection_behavior(true);
+
+        assert!(regular.contains(NSWindowCollectionBehavior::CanJoinAllSpaces));
+        assert!(!regular.contains(NSWindowCollectionBehavior::FullScreenAuxiliary));
+        assert_eq!(
+            fullscreen,
+            regular | NSWindowCollectionBehavior::FullScreenAuxiliary
+        );
+    }
+
+    #[test]
+    fn overlay_frames_off_every_screen_fall_back_to_the_main_one() {
+        let screens = FakeScreens {
+            main: Some(1),
+            ..three_screens()
+        };
+        let window = window_at(WindowBounds::new(5000.0, 5000.0, 400.0, 300.0));
+
+        let frame = to_overlay_frame(&window, &screens, &pixel_panel()).unwrap();
+        assert_eq!(frame, rect(5400.0, -4200.0, 200.0, 100.0));
+
+        let no_screens = FakeScreens {
+            screens: Vec::new(),
+            main: None,
+        };
+        assert!(matches!(
+            to_overlay_frame(&window, &no_screens, &pixel_panel()),
+            Err(PanelError::NoScreen)
+        ));
+    }
+
+    #[test]
+    fn overlay_frames_on_the_left_display_keep_negative_origins() {
+        let window = window_at(WindowBounds::new(-1500.0, 100.0, 400.0, 300.0));
+
+        let right = to_overlay_frame(&window, &three_screens(), &pixel_panel()).unwrap();
+        let overlay = to_overlay_frame(
+            &window,
+            &three_screens(),
+            &PanelConfig::new().match_target_size(true),
+        )
+        .unwrap();
+
+        assert_eq!(right, rect(-1100.0, 700.0, 200.0, 100.0));
+        assert_eq!(overlay, rect(-1500.0, 500.0, 400.0, 300.0));
+    }
+
+    #[test]
+    fn identically_placed_windows_get_cascaded_panels() {
+        let config = PanelConfig::new()
+            .with_placement(PanelPlacement::Right)
+            .with_panel_size(200.0, 100.0);
+        let window = window_at(WindowBounds::new(100.0, 100.0, 400.0, 300.0));
+        let frame = to_overlay_frame(&window, &three_screens(), &config).unwrap();
+        let mut frames = vec![frame; 3];
+
+        cascade_frames(&mut frames, 60.0);
+
+        assert_eq!(
+            frames,
+            [
+                rect(500.0, 700.0, 200.0, 100.0),
+                rect(560.0, 640.0, 200.0, 100.0),
+                rect(620.0, 580.0, 200.0, 100.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn frames_that_barely_overlap_are_not_cascaded() {
+        let mut frames = vec![
+            rect(0.0, 0.0, 200.0, 100.0),
+            rect(150.0, 0.0, 200.0, 100.0),
+            rect(400.0, 0.0, 200.0, 100.0),
+        ];
+        let before = frames.clone();
+
+        cascade_frames(&mut frames, 20.0);
+
+        assert_eq!(frames, before);
+    }
+
+    #[test]
+    fn auto_placement_falls_back_right_left_above_below_overlay() {
+        let visible = WindowBounds::new(0.0, 25.0, 1440.0, 875.0);
+        let panel = NSSize::new(200.0, 100.0);
+        let place = |x, y, width, height| {
+            choose_auto_placement(&WindowBounds::new(x, y, width, height), panel, &visible)
+        };
+
+        assert_eq!(place(400.0, 300.0, 400.0, 300.0), PanelPlacement::Right);
+        assert_eq!(place(1040.0, 300.0, 200.0, 300.0), PanelPlacement::Right);
+        assert_eq!(place(1040.0, 300.0, 400.0, 300.0), PanelPlacement::Left);
+        assert_eq!(place(0.0, 300.0, 1440.0, 300.0), PanelPlacement::Above);
+        assert_eq!(place(0.0, 25.0, 1440.0, 300.0), PanelPlacement::Below);
+        assert_eq!(place(0.0, 25.0, 1440.0, 875.0), PanelPlacement::Overlay);
+    }
+
+    #[test]
+    fn auto_placement_overlays_when_the_panel_outgrows_the_screen() {
+        let visible = WindowBounds::new(0.0, 0.0, 150.0, 80.0);
+        let window = WindowBounds::new(20.0, 10.0, 50.0, 40.0);
+
+        assert_eq!(
+            choose_auto_placement(&window, NSSize::new(200.0, 100.0), &visible),
+            PanelPlacement::Overlay
+        );
+    }
+
+    #[test]
+    fn corner_radius_scales_with_the_smaller_side() {
+        let size = NSSize::new(300.0, 100.0);
+
+        assert_eq!(CornerRadius::Proportional(0.1).radius_for(size), 10.0);
+        assert_eq!(
+            CornerRadius::Proportional(0.1).radius_for(NSSize::new(300.0, 40.0)),
+            4.0
+        );
+        assert_eq!(CornerRadius::Fixed(12.0).radius_for(size), 12.0);
+    }
+
+    #[test]
+    fn corner_radius_is_clamped_to_half_the_smaller_side() {
+        let size = NSSize::new(300.0, 100.0);
+
+        assert_eq!(CornerRadius::Fixed(80.0).radius_for(size), 50.0);
+        assert_eq!(CornerRadius::Proportional(0.9).radius_for(size), 50.0);
+        assert_eq!(CornerRadius::Fixed(-4.0).radius_for(size), 0.0);
+        assert_eq!(CornerRadius::Fixed(f64::NAN).radius_for(size), 0.0);
+        assert_eq!(
+            CornerRadius::Fixed(8.0).radius_for(NSSize::new(-10.0, 20.0)),
+            0.0
+        );
+    }
+
+    #[test]
+    fn app_colors_are_pinned_per_app() {
+        assert_eq!(app_hue_degrees("com.apple.TextEdit"), 39);
+        assert_eq!(app_hue_degrees("com.apple.Safari"), 310);
+        assert_eq!(
+            app_color_components("com.apple.TextEdit"),
+            app_color_components("com.apple.TextEdit")
+        );
+    }
+
+    #[test]
+    fn different_apps_get_visibly_different_hues() {
+        let hue_distance = |a: u64, b: u64| {
+            let difference = a.abs_diff(b);
+            difference.min(360 - difference)
+        };
+        let text_edit = app_hue_degrees("com.apple.TextEdit");
+        let safari = app_hue_degrees("com.apple.Safari");
+
+        assert!(hue_distance(text_edit, safari) >= 60);
+        assert_ne!(
+            app_color_components("com.apple.TextEdit"),
+            app_color_components("com.apple.Safari")
+        );
+    }
+
+    #[test]
+    fn overlays_refuse_focus_unless_asked() {
+        let overlay = PanelConfig::new();
+        assert!(!overlay.is_focusable());
+        assert!(panel_style_mask(&overlay).contains(NSWindowStyleMask::NonactivatingPanel));
+        assert_eq!(
+            focus_ring_type(overlay.is_focusable()),
+            NSFocusRingType::None
+        );
+
+        let focusable = PanelConfig::new().focusable(true);
+        assert!(focusable.is_focusable());
+        assert_eq!(panel_style_mask(&focusable), NSWindowStyleMask::Borderless);
+        assert_eq!(
+            focus_ring_type(focusable.is_focusable()),
+            NSFocusRingType::Default
+        );
+    }
+
+    #[test]
+    fn click_through_and_highlights_are_never_focusable() {
+        let click_through = PanelConfig::new().focusable(true).click_through(true);
+        let highlight =
+            PanelConfig::new()
+                .focusable(true)
+                .with_style(OverlayStyle::HighlightBorder {
+                    thickness: 2.0,
+                    color: None,
+                });
+
+        for config in [click_through, highlight] {
+            assert!(!config.is_focusable());
+            assert!(panel_style_mask(&config).contains(NSWindowStyleMask::NonactivatingPanel));
+        }
+    }
+
+    #[test]
+    fn title_changes_only_refresh_title_labels() {
+        let window = WindowInfo::builder()
+            .with_app_name("TextEdit")
+            .with_title("Untitled")
+            .build();
+        let retitled = WindowInfo::builder()
+            .with_app_name("TextEdit")
+            .with_title("Notes.txt")
+            .build();
+        let title_label = PanelContent::Label(TextRun::new("Editing {title}"));
+        let app_label = PanelContent::Label(TextRun::new("{app}"));
+
+        let changed = ChangedFields::between(&window, &retitled);
+        assert_eq!(
+            changed,
+            ChangedFields {
+                title: true,
+                app: false
+            }
+        );
+        assert!(title_label.is_affected_by(changed));
+        assert!(!app_label.is_affected_by(changed));
+        assert!(!PanelContent::Button.is_affected_by(changed));
+
+        let stack = PanelContent::Stack {
+            axis: StackAxis::Vertical,
+            spacing: 4.0,
+            children: vec![PanelContent::Button, title_label],
+        };
+        assert!(stack.is_affected_by(changed));
+
+        assert!(!ChangedFields::between(&retitled, &retitled).any());
+    }
+
+    #[test]
+    fn app_changes_refresh_the_button_and_app_labels() {
+        let window = WindowInfo::builder().with_app_name("TextEdit").build();
+        let relaunched = WindowInfo::builder().with_app_name("Pages").build();
+
+        let changed = ChangedFields::between(&window, &relaunched);
+
+        assert!(PanelContent::Button.is_affected_by(changed));
+        assert!(PanelContent::Label(TextRun::new("{app}")).is_affected_by(changed));
+        assert!(!PanelContent::Label(TextRun::new("{title}")).is_affected_by(changed));
+        assert!(!PanelContent::Progress {
+            indeterminate: true
+        }
+        .is_affected_by(changed));
+    }
+
+    #[test]
+    fn progress_fractions_are_clamped_to_the_bar() {
+        assert_eq!(clamp_progress(-0.5), 0.0);
+        assert_eq!(clamp_progress(0.0), 0.0);
+        assert_eq!(clamp_progress(0.4), 0.4);
+        assert_eq!(clamp_progress(1.0), 1.0);
+        assert_eq!(clamp_progress(1.5), 1.0);
+        assert_eq!(clamp_progress(f64::NAN), 0.0);
+    }
+
+    // An indicator's state, as `NSProgressIndicator` would hold it.
+    #[derive(Debug, Default, Clone, Copy, PartialEq)]
+    struct Indicator {
+        min: f64,
+        max: f64,
+        indeterminate: bool,
+        animating: bool,
+        value: f64,
+    }
+
+    #[derive(Default)]
+    struct FakeIndicator(Cell<Indicator>);
+
+    impl FakeIndicator {
+        fn update(&self, change: impl FnOnce(&mut Indicator)) {
+            let mut state = self.0.get();
+            change(&mut state);
+            self.0.set(state);
+        }
+    }
+
+    impl ProgressControl for FakeIndicator {
+        fn set_range(&self, min: f64, max: f64) {
+            self.update(|state| (state.min, state.max) = (min, max));
+        }
+
+        fn is_indeterminate(&self) -> bool {
+            self.0.get().indeterminate
+        }
+
+        fn set_indeterminate(&self, indeterminate: bool) {
+            self.update(|state| state.indeterminate = indeterminate);
+        }
+
+        fn set_animating(&self, animating: bool) {
+            self.update(|state| state.animating = animating);
+        }
+
+        fn set_value(&self, value: f64) {
+            self.update(|state| state.value = value);
+        }
+    }
+
+    #[test]
+    fn progress_content_starts_a_bar_or_a_spinner() {
+        let bar = FakeIndicator::default();
+        start_progress(&bar, false);
+        assert_eq!(
+            bar.0.get(),
+            Indicator {
+                min: 0.0,
+                max: 1.0,
+                indeterminate: false,
+                animating: false,
+                value: 0.0,
+            }
+        );
+
+        let spinner = FakeIndicator::default();
+        start_progress(&spinner, true);
+        assert_eq!(
+            spinner.0.get(),
+            Indicator {
+                min: 0.0,
+                max: 1.0,
+                indeterminate: true,
+                animating: true,
+                value: 0.0,
+            }
+        );
+    }
+
+    #[test]
+    fn setting_progress_stops_the_spinner() {
+        let spinner = FakeIndicator::default();
+        start_progress(&spinner, true);
+
+        show_progress(&spinner, 1.5);
+
+        let state = spinner.0.get();
+        assert!(!state.indeterminate);
+        assert!(!state.animating);
+        assert_eq!(state.value, 1.0);
+    }
+
+    #[cfg(feature = "accessibility")]
+    #[test]
+    fn ax_element_frames_flip_on_every_display() {
+        let frame = |x, y, width, height| {
+            ax_element_frame(&WindowBounds::new(x, y, width, height), &three_screens()).unwrap()
+        };
+
+        assert_eq!(
+            frame(100.0, 200.0, 300.0, 40.0),
+            rect(100.0, 660.0, 300.0, 40.0)
+        );
+        assert_eq!(
+            frame(1500.0, -1000.0, 200.0, 50.0),
+            rect(1500.0, 1850.0, 200.0, 50.0)
+        );
+        assert_eq!(
+            frame(-1800.0, 100.0, 200.0, 50.0),
+            rect(-1800.0, 750.0, 200.0, 50.0)
+        );
+    }
+
+    #[cfg(feature = "accessibility")]
+    #[test]
+    fn ax_element_frames_need_valid_bounds_and_a_screen() {
+        let empty = WindowBounds::new(100.0, 200.0, 0.0, 40.0);
+        assert!(matches!(
+            ax_element_frame(&empty, &three_screens()),
+            Err(PanelError::InvalidBounds(_))
+        ));
+
+        let no_screens = FakeScreens {
+            screens: Vec::new(),
+            main: None,
+        };
+        assert!(matches!(
+            ax_element_frame(&WindowBounds::new(0.0, 0.0, 10.0, 10.0), &no_screens),
+            Err(PanelError::NoScreen)
+        ));
+    }
+
+    #[test]
+    fn attributed_text_keeps_each_run_and_its_utf16_range() {
+        let runs = [
+            TextRun::new("Report ✅\n").with_weight(TextWeight::Bold),
+            TextRun::new(""),
+            TextRun::new("🎉 saved")
+                .with_font_size(11.0)
+                .with_color(0.5, 0.5, 0.5, 1.0),
+        ];
+
+        let (text, ranges) = assemble_runs(&runs);
+
+        assert_eq!(text, "Report ✅\n🎉 saved");
+        let recorded: Vec<(TextRun, Utf16Range)> = ranges
+            .into_iter()
+            .map(|(run, range)| (run.clone(), range))
+            .collect();
+        assert_eq!(
+            recorded,
+            [
+                (runs[0].clone(), (0, 9)),
+                (runs[1].clone(), (9, 0)),
+                (runs[2].clone(), (9, 8)),
+            ]
+        );
+    }
+}
@@ -0,0 +1,105 @@
+//! The two privacy permissions this crate depends on. Both are granted per
+//! app in System Settings > Privacy & Security, and macOS only applies a new
+//! grant once the process restarts.
+//!
+//! Without Screen Recording, CG still lists windows but leaves their titles
+//! out, so title matching silently finds nothing. Without Accessibility, every
+//! AX call fails with `kAXErrorAPIDisabled`.
+
+use std::fmt;
+use std::os::raw::c_void;
+
+use crate::cf::{CfDictionary, CfString, CfType};
+
+#[link(name = "CoreGraphics", kind = "framework")]
+extern "C" {
+    fn CGPreflightScreenCaptureAccess() -> bool;
+    fn CGRequestScreenCaptureAccess() -> bool;
+}
+
+#[link(name = "ApplicationServices", kind = "framework")]
+extern "C" {
+    static kAXTrustedCheckOptionPrompt: *const c_void;
+    fn AXIsProcessTrusted() -> bool;
+    fn AXIsProcessTrustedWithOptions(options: *const c_void) -> bool;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    ScreenRecording,
+    Accessibility,
+}
+
+impl Permission {
+    pub fn is_granted(&self) -> bool {
+        match self {
+            Permission::ScreenRecording => screen_recording_granted(),
+            Permission::Accessibility => accessibility_granted(),
+        }
+    }
+
+    /// Shows the system prompt for this permission, if macOS still will (it
+    /// only asks once per app). Returns whether it is granted right now.
+    pub fn request(&self) -> bool {
+        match self {
+            Permission::ScreenRecording => request_screen_recording(),
+            Permission::Accessibility => request_accessibility(),
+        }
+    }
+
+    /// What stops working without it.
+    pub fn consequence(&self) -> &'static str {
+        match self {
+            Permission::ScreenRecording => {
+                "window titles will be empty, so title matching finds nothing"
+            }
+            Permission::Accessibility => {
+                "dialog detection, window raising and element paths will fail"
+            }
+        }
+    }
+}
+
+impl fmt::Display for Permission {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Permission::ScreenRecording => write!(f, "Screen Recording"),
+            Permission::Accessibility => write!(f, "Accessibility"),
+        }
+    }
+}
+
+/// The permissions this build uses that haven't been granted. Accessibility
+/// only counts with the `accessibility` feature.
+pub fn missing_permissions() -> Vec<Permission> {
+    let mut used = vec![Permission::ScreenRecording];
+    if cfg!(feature = "accessibility") {
+        used.push(Permission::Accessibility);
+    }
+    used.retain(|permission| !permission.is_granted());
+    used
+}
+
+pub fn screen_recording_granted() -> bool {
+    unsafe { CGPreflightScreenCaptureAccess() }
+}
+
+/// Asks for Screen Recording access, prompting the first time.
+pub fn request_screen_recording() -> bool {
+    unsafe { CGRequestScreenCaptureAccess() }
+}
+
+pub fn accessibility_granted() -> bool {
+    unsafe { AXIsProcessTrusted() }
+}
+
+/// Asks for Accessibility access, prompting if it hasn't been granted.
+pub fn request_accessibility() -> bool {
+    let Some(prompt) = (unsafe { CfString::from_get_rule(kAXTrustedCheckOptionPrompt) }) else {
+        return accessibility_granted();
+    };
+    match CfDictionary::new(&[(&prompt, &CfType::boolean(true))]) {
+        Some(options) => unsafe { AXIsProcessTrustedWithOptions(options.as_ptr()) },
+        None => accessibility_granted(),
+    }
+}
@@ -0,0 +1,398 @@
+use std::cell::RefCell;
+use std::os::raw::c_void;
+
+use objc2::MainThreadMarker;
+use objc2_app_kit::NSScreen;
+use objc2_foundation::{NSPoint, NSRect, NSSize};
+
+use crate::window_search::WindowBounds;
+
+// `kCGDisplayBeginConfigurationFlag`: set on the call made before a change.
+const K_CG_DISPLAY_BEGIN_CONFIGURATION_FLAG: u32 = 1;
+
+type DisplayReconfigurationCallback =
+    extern "C" fn(display: u32, flags: u32, user_info: *mut c_void);
+// `CGDisplayRegisterReconfigurationCallback` and its remove counterpart.
+type CallbackRegistration =
+    unsafe extern "C" fn(callback: DisplayReconfigurationCallback, user_info: *mut c_void) -> i32;
+
+#[link(name = "CoreGraphics", kind = "framework")]
+extern "C" {
+    fn CGDisplayRegisterReconfigurationCallback(
+        callback: DisplayReconfigurationCallback,
+        user_info: *mut c_void,
+    ) -> i32;
+    fn CGDisplayRemoveReconfigurationCallback(
+        callback: DisplayReconfigurationCallback,
+        user_info: *mut c_void,
+    ) -> i32;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScreenInfo {
+    pub frame: NSRect,
+    pub visible_frame: NSRect,
+    pub scale_factor: f64,
+}
+
+impl ScreenInfo {
+    pub fn new(frame: NSRect, visible_frame: NSRect, scale_factor: f64) -> Self {
+        Self {
+            frame,
+            visible_frame,
+            scale_factor,
+        }
+    }
+
+    pub fn cg_frame(&self, primary_height: f64) -> WindowBounds {
+        ns_rect_to_cg(self.frame, primary_height)
+    }
+
+    pub fn cg_visible_frame(&self, primary_height: f64) -> WindowBounds {
+        ns_rect_to_cg(self.visible_frame, primary_height)
+    }
+
+    fn from_ns_screen(screen: &NSScreen) -> Self {
+        Self::new(
+            screen.frame(),
+            screen.visibleFrame(),
+            screen.backingScaleFactor(),
+        )
+    }
+}
+
+/// CG (and AX) put the origin at the primary display's top-left corner and
+/// AppKit at its bottom-left, so flipping against the primary height maps every
+/// display, including ones at negative offsets, into AppKit's space.
+pub fn cg_rect_to_ns(bounds: &WindowBounds, primary_height: f64) -> NSRect {
+    NSRect::new(
+        NSPoint::new(bounds.x, primary_height - bounds.y - bounds.height),
+        NSSize::new(bounds.width, bounds.height),
+    )
+}
+
+/// The inverse of `cg_rect_to_ns`, e.g. to compare a panel's frame with CG
+/// window bounds.
+pub fn ns_rect_to_cg(rect: NSRect, primary_height: f64) -> WindowBounds {
+    WindowBounds::new(
+        rect.origin.x,
+        primary_height - rect.origin.y - rect.size.height,
+        rect.size.width,
+        rect.size.height,
+    )
+}
+
+/// Index of the screen sharing the largest area with `bounds` (CG coordinates).
+pub fn screen_index_for_bounds(screens: &[ScreenInfo], bounds: &WindowBounds) -> Option<usize> {
+    let primary_height = screens.first()?.frame.size.height;
+
+    screens
+        .iter()
+        .enumerate()
+        .filter_map(|(index, screen)| {
+            let overlap = screen.cg_frame(primary_height).intersection(bounds)?;
+            Some((index, overlap.area()))
+        })
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(index, _)| index)
+}
+
+pub fn screen_for_bounds(screens: &[ScreenInfo], bounds: &WindowBounds) -> Option<ScreenInfo> {
+    screen_index_for_bounds(screens, bounds).map(|index| screens[index])
+}
+
+/// Source of display geometry for panel placement. Placement code only ever
+/// sees screens through this trait, so fixed layouts can stand in for the
+/// attached hardware.
+pub trait ScreenProvider {
+    /// All displays, primary (menu bar) display first.
+    fn screens(&self) -> Vec<ScreenInfo>;
+
+    /// The display AppKit currently treats as main.
+    fn main_screen(&self) -> Option<ScreenInfo>;
+
+    /// Drops any cached geometry after the display layout changed.
+    fn invalidate(&self) {}
+}
+
+/// The attached displays. `screens` is cached until `invalidate`, which
+/// `watch_display_changes` callers should trigger on every reconfiguration;
+/// `main_screen` follows the key window, so it is always read live.
+pub struct SystemScreens {
+    mtm: MainThreadMarker,
+    cached: ScreenCache,
+}
+
+impl SystemScreens {
+    pub fn new(mtm: MainThreadMarker) -> Self {
+        Self {
+            mtm,
+            cached: ScreenCache::default(),
+        }
+    }
+}
+
+impl ScreenProvider for SystemScreens {
+    fn screens(&self) -> Vec<ScreenInfo> {
+        self.cached.get_or_load(|| {
+            NSScreen::screens(self.mtm)
+                .iter()
+                .map(|screen| ScreenInfo::from_ns_screen(&screen))
+                .collect()
+        })
+    }
+
+    fn invalidate(&self) {
+        self.cached.invalidate();
+    }
+
+    fn main_screen(&self) -> Option<ScreenInfo> {
+        NSScreen::mainScreen(self.mtm).map(|screen| ScreenInfo::from_ns_screen(&screen))
+    }
+}
+
+// The screens `SystemScreens` read last, until invalidated.
+#[derive(Default)]
+struct ScreenCache(RefCell<Option<Vec<ScreenInfo>>>);
+
+impl ScreenCache {
+    fn get_or_load(&self, load: impl FnOnce() -> Vec<ScreenInfo>) -> Vec<ScreenInfo> {
+        self.0.borrow_mut().get_or_insert_with(load).clone()
+    }
+
+    fn invalidate(&self) {
+        self.0.borrow_mut().take();
+    }
+}
+
+impl ScreenProvider for [ScreenInfo] {
+    fn screens(&self) -> Vec<ScreenInfo> {
+        self.to_vec()
+    }
+
+    fn main_screen(&self) -> Option<ScreenInfo> {
+        self.first().copied()
+    }
+}
+
+/// Keeps a display reconfiguration callback registered; dropping it
+/// unregisters the callback.
+pub struct DisplayWatch {
+    on_change: *mut Box<dyn Fn()>,
+    remove: CallbackRegistration,
+}
+
+/// Calls `on_change` on the main thread after displays are added, removed,
+/// rearranged or resized, or the primary display changes. Every CG-to-AppKit
+/// conversion flips against the primary display's height, so without this,
+/// cached screen geometry and the overlays placed with it drift after such a
+/// change until the next poll.
+pub fn watch_display_changes(on_change: impl Fn() + 'static) -> DisplayWatch {
+    watch_with(
+        on_change,
+        CGDisplayRegisterReconfigurationCallback,
+        CGDisplayRemoveReconfigurationCallback,
+    )
+}
+
+fn watch_with(
+    on_change: impl Fn() + 'static,
+    register: CallbackRegistration,
+    remove: CallbackRegistration,
+) -> DisplayWatch {
+    let on_change: *mut Box<dyn Fn()> = Box::into_raw(Box::new(Box::new(on_change)));
+    unsafe {
+        register(display_reconfigured, on_change.cast());
+    }
+    DisplayWatch { on_change, remove }
+}
+
+impl Drop for DisplayWatch {
+    fn drop(&mut self) {
+        unsafe {
+            (self.remove)(display_reconfigured, self.on_change.cast());
+            drop(Box::from_raw(self.on_change));
+        }
+    }
+}
+
+// CG calls this once per affected display before the change (with the begin
+// flag) and once after; only the latter sees the new layout.
+extern "C" fn display_reconfigured(_display: u32, flags: u32, user_info: *mut c_void) {
+    if flags & K_CG_DISPLAY_BEGIN_CONFIGURATION_FLAG != 0 || user_info.is_null() {
+        return;
+    }
+    let on_change = unsafe { &*(user_info as *const Box<dyn Fn()>) };
+    on_change();
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use super::*;
+
+    /// A fixed display layout standing in for the attached hardware.
+    pub(crate) struct FakeScreens {
+        pub(crate) screens: Vec<ScreenInfo>,
+        pub(crate) main: Option<usize>,
+    }
+
+    impl ScreenProvider for FakeScreens {
+        fn screens(&self) -> Vec<ScreenInfo> {
+            self.screens.clone()
+        }
+
+        fn main_screen(&self) -> Option<ScreenInfo> {
+            self.main.map(|index| self.screens[index])
+        }
+    }
+
+    pub(crate) fn rect(x: f64, y: f64, width: f64, height: f64) -> NSRect {
+        NSRect::new(NSPoint::new(x, y), NSSize::new(width, height))
+    }
+
+    /// A 1440x900 Retina primary with the menu bar, a 1920x1080 display above
+    /// and to its right, and one to its left, at negative x in both spaces.
+    pub(crate) fn three_screens() -> FakeScreens {
+        FakeScreens {
+            screens: vec![
+                ScreenInfo::new(
+                    rect(0.0, 0.0, 1440.0, 900.0),
+                    rect(0.0, 0.0, 1440.0, 875.0),
+                    2.0,
+                ),
+                ScreenInfo::new(
+                    rect(1440.0, 900.0, 1920.0, 1080.0),
+                    rect(1440.0, 900.0, 1920.0, 1080.0),
+                    1.0,
+                ),
+                ScreenInfo::new(
+                    rect(-1920.0, -180.0, 1920.0, 1080.0),
+                    rect(-1920.0, -180.0, 1920.0, 1080.0),
+                    1.0,
+                ),
+            ],
+            main: Some(0),
+        }
+    }
+
+    #[test]
+    fn display_frames_convert_to_cg_and_back() {
+        let screens = three_screens().screens();
+        let primary_height = screens[0].frame.size.height;
+
+        let cg_frames: Vec<WindowBounds> = screens
+            .iter()
+            .map(|screen| screen.cg_frame(primary_height))
+            .collect();
+        assert_eq!(
+            cg_frames,
+            [
+                WindowBounds::new(0.0, 0.0, 1440.0, 900.0),
+                WindowBounds::new(1440.0, -1080.0, 1920.0, 1080.0),
+                WindowBounds::new(-1920.0, 0.0, 1920.0, 1080.0),
+            ]
+        );
+        for (screen, cg_frame) in screens.iter().zip(&cg_frames) {
+            assert_eq!(cg_rect_to_ns(cg_frame, primary_height), screen.frame);
+        }
+        assert_eq!(
+            screens[0].cg_visible_frame(primary_height),
+            WindowBounds::new(0.0, 25.0, 1440.0, 875.0)
+        );
+    }
+
+    #[test]
+    fn windows_on_the_secondary_display_flip_against_the_primary() {
+        let bounds = WindowBounds::new(1500.0, -1000.0, 400.0, 300.0);
+
+        let frame = cg_rect_to_ns(&bounds, 900.0);
+
+        assert_eq!(frame, rect(1500.0, 1600.0, 400.0, 300.0));
+        assert_eq!(ns_rect_to_cg(frame, 900.0), bounds);
+        assert_eq!(
+            screen_index_for_bounds(&three_screens().screens(), &bounds),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn windows_go_to_the_display_they_overlap_most() {
+        let screens = three_screens().screens();
+
+        let straddling = WindowBounds::new(-300.0, 100.0, 400.0, 300.0);
+        assert_eq!(screen_index_for_bounds(&screens, &straddling), Some(2));
+        let mostly_primary = WindowBounds::new(1300.0, 100.0, 400.0, 300.0);
+        assert_eq!(screen_index_for_bounds(&screens, &mostly_primary), Some(0));
+        let nowhere = WindowBounds::new(5000.0, 5000.0, 100.0, 100.0);
+        assert_eq!(screen_index_for_bounds(&screens, &nowhere), None);
+        assert_eq!(screen_index_for_bounds(&[], &mostly_primary), None);
+    }
+
+    thread_local! {
+        // What the fake CG calls below were given, user info as an address.
+        static REGISTERED: Cell<Option<(DisplayReconfigurationCallback, usize)>> =
+            const { Cell::new(None) };
+        static REMOVED: Cell<Option<usize>> = const { Cell::new(None) };
+    }
+
+    unsafe extern "C" fn fake_register(
+        callback: DisplayReconfigurationCallback,
+        user_info: *mut c_void,
+    ) -> i32 {
+        REGISTERED.with(|registered| registered.set(Some((callback, user_info as usize))));
+        0
+    }
+
+    unsafe extern "C" fn fake_remove(
+        _callback: DisplayReconfigurationCallback,
+        user_info: *mut c_void,
+    ) -> i32 {
+        REMOVED.with(|removed| removed.set(Some(user_info as usize)));
+        0
+    }
+
+    #[test]
+    fn screens_are_cached_until_invalidated() {
+        let cache = ScreenCache::default();
+        let loads = Cell::new(0);
+        let load = || {
+            loads.set(loads.get() + 1);
+            three_screens().screens()
+        };
+
+        assert_eq!(cache.get_or_load(load), three_screens().screens());
+        assert_eq!(cache.get_or_load(load).len(), 3);
+        assert_eq!(loads.get(), 1);
+
+        cache.invalidate();
+        cache.get_or_load(load);
+        assert_eq!(loads.get(), 2);
+    }
+
+    #[test]
+    fn display_changes_invalidate_until_the_watch_is_dropped() {
+        let cache = Rc::new(ScreenCache::default());
+        cache.get_or_load(|| three_screens().screens());
+        let watched = Rc::clone(&cache);
+        let watch = watch_with(move || watched.invalidate(), fake_register, fake_remove);
+        let (callback, user_info) = REGISTERED.with(Cell::get).unwrap();
+
+        // Before the change the old layout still stands.
+        callback(
+            1,
+            K_CG_DISPLAY_BEGIN_CONFIGURATION_FLAG,
+            user_info as *mut c_void,
+        );
+        assert_eq!(cache.get_or_load(Vec::new).len(), 3);
+
+        callback(1, 0, user_info as *mut c_void);
+        assert!(cache.get_or_load(Vec::new).is_empty());
+
+        drop(watch);
+        assert_eq!(REMOVED.with(Cell::get), Some(user_info));
+        assert_eq!(Rc::strong_count(&cache), 1);
+    }
+}
@@ -0,0 +1,194 @@
+//! `PanelContent::WebView`: a WKWebView filling the panel, whose page can
+//! talk back to Rust with
+//!
+//! ```js
+//! window.webkit.messageHandlers.panel.postMessage("clicked")
+//! ```
+//!
+//! Messages reach the handler from `PanelConfig::on_web_message` along with
+//! the panel's window. Strings arrive as they are; other values as their
+//! Objective-C description.
+
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+use objc2::rc::Retained;
+use objc2::runtime::{AnyObject, NSObject, ProtocolObject};
+use objc2::{define_class, msg_send, DefinedClass, MainThreadMarker, MainThreadOnly, Message};
+use objc2_app_kit::NSAutoresizingMaskOptions;
+use objc2_foundation::{NSObjectProtocol, NSRect, NSString, NSURLRequest, NSURL};
+use objc2_web_kit::{
+    WKScriptMessage, WKScriptMessageHandler, WKUserContentController, WKWebView,
+    WKWebViewConfiguration,
+};
+
+use crate::window_search::WindowInfo;
+
+/// The name pages post to, as `window.webkit.messageHandlers.panel`.
+pub const MESSAGE_HANDLER_NAME: &str = "panel";
+// Associated-object key tying a `MessageBridge` to its web view.
+static MESSAGE_BRIDGE_KEY: u8 = 0;
+
+/// What a web view panel loads.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WebSource {
+    Url(String),
+    /// A page given inline; relative links have no base URL to resolve against.
+    Html(String),
+}
+
+type MessageCallback = dyn Fn(&WindowInfo, &str);
+
+#[derive(Clone)]
+pub(crate) struct MessageHandler(pub(crate) Rc<MessageCallback>);
+
+impl fmt::Debug for MessageHandler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("MessageHandler(..)")
+    }
+}
+
+/// A web view filling `frame` and following the panel's size, loading
+/// `source`. An unparsable URL leaves it blank.
+pub(crate) fn build_web_view(
+    source: &WebSource,
+    frame: NSRect,
+    mtm: MainThreadMarker,
+) -> Retained<WKWebView> {
+    unsafe {
+        let configuration = WKWebViewConfiguration::new(mtm);
+        configuration.setUserContentController(&WKUserContentController::new(mtm));
+        let web_view =
+            WKWebView::initWithFrame_configuration(WKWebView::alloc(mtm), frame, &configuration);
+        web_view.setAutoresizingMask(
+            NSAutoresizingMaskOptions::ViewWidthSizable
+                | NSAutoresizingMaskOptions::ViewHeightSizable,
+        );
+
+        match source {
+            WebSource::Url(url) => match NSURL::URLWithString(&NSString::from_str(url)) {
+                Some(url) => {
+                    web_view.loadRequest(&NSURLRequest::requestWithURL(&url));
+                }
+                None => log::warn!("Web view panel has an invalid URL: {}", url),
+            },
+            WebSource::Html(html) => {
+                web_view.loadHTMLString_baseURL(&NSString::from_str(html), None);
+            }
+        }
+
+        web_view
+    }
+}
+
+/// Routes the page's `MESSAGE_HANDLER_NAME` messages to `handler`. The web
+/// view's content controller keeps the bridge alive; the web view also holds
+/// it as an associated object so `update_message_window` can find it.
+pub(crate) fn attach_message_handler(
+    web_view: &WKWebView,
+    handler: MessageHandler,
+    window: &WindowInfo,
+    mtm: MainThreadMarker,
+) {
+    let bridge = MessageBridge::new(handler, window.clone(), mtm);
+    unsafe {
+        web_view
+            .configuration()
+            .userContentController()
+            .addScriptMessageHandler_name(
+                ProtocolObject::from_ref(&*bridge),
+                &NSString::from_str(MESSAGE_HANDLER_NAME),
+            );
+        objc2::ffi::objc_setAssociatedObject(
+            (web_view as *const WKWebView).cast_mut().cast(),
+            (&MESSAGE_BRIDGE_KEY as *const u8).cast(),
+            Retained::as_ptr(&bridge).cast_mut().cast(),
+            objc2::ffi::OBJC_ASSOCIATION_RETAIN_NONATOMIC,
+        );
+    }
+}
+
+/// Unregisters the web view's message handler and releases its bridge,
+/// dropping the handler closure.
+pub(crate) fn detach_message_handler(web_view: &WKWebView) {
+    unsafe {
+        web_view
+            .configuration()
+            .userContentController()
+            .removeScriptMessageHandlerForName(&NSString::from_str(MESSAGE_HANDLER_NAME));
+        objc2::ffi::objc_setAssociatedObject(
+            (web_view as *const WKWebView).cast_mut().cast(),
+            (&MESSAGE_BRIDGE_KEY as *const u8).cast(),
+            std::ptr::null_mut(),
+            objc2::ffi::OBJC_ASSOCIATION_RETAIN_NONATOMIC,
+        );
+    }
+}
+
+/// Points the web view's message handler at the refreshed `window`.
+pub(crate) fn update_message_window(web_view: &WKWebView, window: &WindowInfo) {
+    let bridge = unsafe {
+        objc2::ffi::objc_getAssociatedObject(
+            (web_view as *const WKWebView).cast(),
+            (&MESSAGE_BRIDGE_KEY as *const u8).cast(),
+        )
+    };
+    // Only `attach_message_handler` sets this key, always to a `MessageBridge`.
+    if let Some(bridge) = unsafe { bridge.cast::<MessageBridge>().as_ref() } {
+        *bridge.ivars().window.borrow_mut() = window.clone();
+    }
+}
+
+struct MessageBridgeIvars {
+    handler: MessageHandler,
+    window: RefCell<WindowInfo>,
+}
+
+define_class!(
+    // SAFETY: NSObject has no subclassing requirements and `MessageBridge`
+    // does not implement `Drop`.
+    #[unsafe(super(NSObject))]
+    #[thread_kind = MainThreadOnly]
+    #[name = "PanelDetectorMessageBridge"]
+    #[ivars = MessageBridgeIvars]
+    struct MessageBridge;
+
+    unsafe impl NSObjectProtocol for MessageBridge {}
+
+    unsafe impl WKScriptMessageHandler for MessageBridge {
+        #[unsafe(method(userContentController:didReceiveScriptMessage:))]
+        fn did_receive_script_message(
+            &self,
+            _controller: &WKUserContentController,
+            message: &WKScriptMessage,
+        ) {
+            let body = message_text(&unsafe { message.body() });
+            // Retained in case the handler closes the panel, which releases
+            // this bridge, and cloned so it can trigger a panel update.
+            let this = self.retain();
+            let window = this.ivars().window.borrow().clone();
+            (this.ivars().handler.0)(&window, &body);
+        }
+    }
+);
+
+impl MessageBridge {
+    fn new(handler: MessageHandler, window: WindowInfo, mtm: MainThreadMarker) -> Retained<Self> {
+        let this = Self::alloc(mtm).set_ivars(MessageBridgeIvars {
+            handler,
+            window: RefCell::new(window),
+        });
+        unsafe { msg_send![super(this), init] }
+    }
+}
+
+fn message_text(body: &Retained<AnyObject>) -> String {
+    match body.downcast_ref::<NSString>() {
+        Some(string) => string.to_string(),
+        None => {
+            let description: Retained<NSString> = unsafe { msg_send![body, description] };
+            description.to_string()
+        }
+    }
+}
@@ -1,303 +1,3553 @@
-use std::collections::HashSet;
-use std::ffi::{CStr, CString};
-use std::os::raw::{c_char, c_void};
-use std::ptr;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::os::raw::c_void;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
 
-use objc2_app_kit::NSRunningApplication;
+use objc2::rc::Retained;
+use objc2_app_kit::{NSImage, NSRunningApplication, NSWorkspace};
+use objc2_foundation::NSString;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
 
-const K_CF_STRING_ENCODING_UTF8: u32 = 0x08000100;
-const K_CG_WINDOW_LIST_OPTION_ON_SCREEN_ONLY: u32 = 1;
-const K_CF_NUMBER_DOUBLE_TYPE: i32 = 13;
+use crate::activation::ActivationTarget;
+use crate::cf::{CfArray, CfDictionary, CfString, CfType};
+use crate::dialog::{is_dialog, DialogDetection, KnownDialog};
+use crate::screen::{screen_index_for_bounds, ScreenInfo};
+
+// `CGWindowListOption` flags, public for `with_raw_cg_options`.
+pub const K_CG_WINDOW_LIST_OPTION_ALL: u32 = 0;
+pub const K_CG_WINDOW_LIST_OPTION_ON_SCREEN_ONLY: u32 = 1;
+pub const K_CG_WINDOW_LIST_OPTION_ON_SCREEN_ABOVE_WINDOW: u32 = 2;
+pub const K_CG_WINDOW_LIST_OPTION_ON_SCREEN_BELOW_WINDOW: u32 = 4;
+pub const K_CG_WINDOW_LIST_OPTION_INCLUDING_WINDOW: u32 = 8;
+pub const K_CG_WINDOW_LIST_EXCLUDE_DESKTOP_ELEMENTS: u32 = 16;
+const FULLSCREEN_TOLERANCE: f64 = 1.0;
+const NORMAL_MIN_VISIBLE_FRACTION: f64 = 0.1;
+const ESRCH: i32 = 3;
+pub const DEFAULT_APP_CACHE_CAPACITY: usize = 64;
+
+extern "C" {
+    fn kill(pid: i32, signal: i32) -> i32;
+}
 
 #[link(name = "CoreGraphics", kind = "framework")]
 extern "C" {
     fn CGWindowListCopyWindowInfo(option: u32, relative_to_window: u32) -> *const c_void;
-    fn CFArrayGetCount(array: *const c_void) -> isize;
-    fn CFArrayGetValueAtIndex(array: *const c_void, idx: isize) -> *const c_void;
-    fn CFDictionaryGetValue(dict: *const c_void, key: *const c_void) -> *const c_void;
-    fn CFStringCreateWithCString(
-        allocator: *const c_void,
-        cstr: *const c_char,
-        encoding: u32,
-    ) -> *const c_void;
-    fn CFStringGetLength(string: *const c_void) -> isize;
-    fn CFStringGetCString(
-        string: *const c_void,
-        buffer: *mut c_char,
-        buffer_size: isize,
-        encoding: u32,
-    ) -> bool;
-    fn CFRelease(cf: *const c_void);
-    fn CFNumberGetValue(number: *const c_void, number_type: i32, value_ptr: *mut c_void) -> bool;
 }
 
-#[derive(Debug, Clone)]
+/// Window frame in CG global coordinates: origin at the top-left of the primary
+/// display, y growing downward. Displays left of or above the primary one have
+/// negative origins, so no component except the size is assumed non-negative.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize)]
+pub struct WindowBounds {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl WindowBounds {
+    pub fn new(x: f64, y: f64, width: f64, height: f64) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    pub fn right(&self) -> f64 {
+        self.x + self.width
+    }
+
+    pub fn bottom(&self) -> f64 {
+        self.y + self.height
+    }
+
+    pub fn area(&self) -> f64 {
+        self.width * self.height
+    }
+
+    /// Finite, with a positive width and height. Some system windows report
+    /// all-zero or NaN bounds.
+    pub fn is_valid(&self) -> bool {
+        [self.x, self.y, self.width, self.height]
+            .iter()
+            .all(|value| value.is_finite())
+            && self.width > 0.0
+            && self.height > 0.0
+    }
+
+    pub fn intersection(&self, other: &WindowBounds) -> Option<WindowBounds> {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = self.right().min(other.right());
+        let bottom = self.bottom().min(other.bottom());
+
+        (right > x && bottom > y).then(|| WindowBounds::new(x, y, right - x, bottom - y))
+    }
+
+    /// Whether the bounds share any area with `other`; touching edges don't
+    /// count.
+    pub fn intersects(&self, other: &WindowBounds) -> bool {
+        self.intersection(other).is_some()
+    }
+
+    /// Whether `(x, y)` lies inside, counting the top and left edges but not
+    /// the bottom and right ones, so adjacent bounds never both contain a point.
+    pub fn contains(&self, (x, y): (f64, f64)) -> bool {
+        x >= self.x && x < self.right() && y >= self.y && y < self.bottom()
+    }
+
+    pub fn center(&self) -> (f64, f64) {
+        (self.x + self.width / 2.0, self.y + self.height / 2.0)
+    }
+}
+
+impl fmt::Display for WindowBounds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "x:{}, y:{}, w:{}, h:{}",
+            self.x, self.y, self.width, self.height
+        )
+    }
+}
+
+/// A CG window number (`kCGWindowNumber`). Kept distinct from `Pid` so the two
+/// can't be swapped by accident; formats as the plain integer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct WindowNumber(pub i64);
+
+/// The process id of a window's owning app; formats as the plain integer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Pid(pub i32);
+
+impl fmt::Display for WindowNumber {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<i64> for WindowNumber {
+    fn from(window_number: i64) -> Self {
+        Self(window_number)
+    }
+}
+
+impl From<WindowNumber> for i64 {
+    fn from(window_number: WindowNumber) -> Self {
+        window_number.0
+    }
+}
+
+impl fmt::Display for Pid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<i32> for Pid {
+    fn from(pid: i32) -> Self {
+        Self(pid)
+    }
+}
+
+impl From<Pid> for i32 {
+    fn from(pid: Pid) -> Self {
+        pid.0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct WindowInfo {
-    pub title: String,
+    /// `None` when the window has no `kCGWindowName` at all (typically because
+    /// screen recording permission is missing); `Some("")` when it is empty.
+    pub title: Option<String>,
+    /// `kCGWindowOwnerName`. When CG reported none, the running app's
+    /// localized name, its bundle id or, failing those, `"pid <pid>"`, so
+    /// scanned windows never have an empty app name.
     pub app_name: String,
+    /// The raw CG owner name, kept only when it differs from `app_name`.
+    pub cg_owner_name: Option<String>,
     pub bundle_identifier: Option<String>,
-    pub bounds: String,
-    pub window_number: i64,
-    pub pid: i32,
+    pub bounds: WindowBounds,
+    pub window_number: WindowNumber,
+    pub pid: Pid,
     pub layer: i32,
     pub alpha: f64,
     pub sharing_state: i32,
     pub memory_usage: i64,
     pub is_onscreen: bool,
+    /// For sheets, the window they are attached to. Only filled in when the
+    /// criteria asked for `resolve_parents` and the `accessibility` feature is
+    /// enabled, which needs the Accessibility permission; `None` otherwise, for
+    /// windows without a parent, or when AX can't see the window.
+    pub parent_window_number: Option<WindowNumber>,
+    /// `Some(false)` for on-screen windows. For off-screen ones (see
+    /// `include_offscreen`) whether they are minimized rather than, say, on
+    /// another space; that needs the `accessibility` feature and is `None`
+    /// without it or when AX can't see the window.
+    pub is_minimized: Option<bool>,
+    /// Position in the CG window list the window was scanned from, 0 being
+    /// frontmost. Only comparable between windows from the same scan.
+    pub z_index: usize,
 }
 
-#[derive(Debug)]
-pub struct WindowSearchResults {
-    pub total_windows: usize,
-    pub matched_windows: Vec<WindowInfo>,
-}
-
+/// Builds synthetic `WindowInfo`s, e.g. for tests or windows that don't come
+/// from a CG scan. Defaults: empty title, app "Test", no bundle id, 100×100
+/// bounds at the origin, window number and pid 0, layer 0, alpha 1.0, sharing
+/// state and memory usage 0, on screen and not minimized, no parent, z-index 0.
 #[derive(Debug, Clone)]
-pub struct WindowSearchCriteria {
-    title: Option<String>,
-    app_name: Option<String>,
-    ignored_apps: HashSet<String>,
+pub struct WindowInfoBuilder {
+    window: WindowInfo,
 }
 
-impl WindowSearchCriteria {
+impl WindowInfoBuilder {
     pub fn new() -> Self {
         Self {
-            title: None,
-            app_name: None,
-            ignored_apps: HashSet::new(),
+            window: WindowInfo {
+                title: Some(String::new()),
+                app_name: "Test".to_string(),
+                cg_owner_name: None,
+                bundle_identifier: None,
+                bounds: WindowBounds::new(0.0, 0.0, 100.0, 100.0),
+                window_number: WindowNumber(0),
+                pid: Pid(0),
+                layer: 0,
+                alpha: 1.0,
+                sharing_state: 0,
+                memory_usage: 0,
+                is_onscreen: true,
+                parent_window_number: None,
+                is_minimized: Some(false),
+                z_index: 0,
+            },
         }
     }
 
     pub fn with_title(mut self, title: impl Into<String>) -> Self {
-        self.title = Some(title.into());
+        self.window.title = Some(title.into());
+        self
+    }
+
+    /// Like a window CG reported without `kCGWindowName`.
+    pub fn without_title(mut self) -> Self {
+        self.window.title = None;
         self
     }
 
     pub fn with_app_name(mut self, app_name: impl Into<String>) -> Self {
-        self.app_name = Some(app_name.into());
+        self.window.app_name = app_name.into();
         self
     }
 
-    pub fn with_ignored_apps(mut self, ignored_apps: HashSet<String>) -> Self {
-        self.ignored_apps = ignored_apps;
+    pub fn with_bundle_identifier(mut self, bundle_identifier: impl Into<String>) -> Self {
+        self.window.bundle_identifier = Some(bundle_identifier.into());
         self
     }
 
-    pub fn add_ignored_app(mut self, app: impl Into<String>) -> Self {
-        self.ignored_apps.insert(app.into().to_lowercase());
+    pub fn with_bounds(mut self, bounds: WindowBounds) -> Self {
+        self.window.bounds = bounds;
         self
     }
 
-    fn matches(&self, window_title: &str, window_app_name: &str) -> bool {
-        if should_ignore_app(window_app_name, &self.ignored_apps) {
-            return false;
-        }
+    pub fn with_window_number(mut self, window_number: impl Into<WindowNumber>) -> Self {
+        self.window.window_number = window_number.into();
+        self
+    }
 
-        let title_matches = self
-            .title
-            .as_ref()
-            .map(|t| window_title == t)
-            .unwrap_or(true);
+    pub fn with_pid(mut self, pid: impl Into<Pid>) -> Self {
+        self.window.pid = pid.into();
+        self
+    }
 
-        let app_name_matches = self
-            .app_name
-            .as_ref()
-            .map(|a| window_app_name == a)
-            .unwrap_or(true);
+    pub fn with_layer(mut self, layer: i32) -> Self {
+        self.window.layer = layer;
+        self
+    }
+
+    pub fn with_alpha(mut self, alpha: f64) -> Self {
+        self.window.alpha = alpha;
+        self
+    }
+
+    pub fn with_sharing_state(mut self, sharing_state: i32) -> Self {
+        self.window.sharing_state = sharing_state;
+        self
+    }
+
+    pub fn with_memory_usage(mut self, memory_usage: i64) -> Self {
+        self.window.memory_usage = memory_usage;
+        self
+    }
+
+    pub fn onscreen(mut self, is_onscreen: bool) -> Self {
+        self.window.is_onscreen = is_onscreen;
+        self
+    }
+
+    pub fn with_parent_window_number(mut self, parent: impl Into<WindowNumber>) -> Self {
+        self.window.parent_window_number = Some(parent.into());
+        self
+    }
 
-        title_matches && app_name_matches
+    pub fn with_z_index(mut self, z_index: usize) -> Self {
+        self.window.z_index = z_index;
+        self
+    }
+
+    /// Also marks the window off screen when `is_minimized`.
+    pub fn minimized(mut self, is_minimized: bool) -> Self {
+        self.window.is_minimized = Some(is_minimized);
+        if is_minimized {
+            self.window.is_onscreen = false;
+        }
+        self
+    }
+
+    pub fn build(self) -> WindowInfo {
+        self.window
     }
 }
 
-impl Default for WindowSearchCriteria {
+impl Default for WindowInfoBuilder {
     fn default() -> Self {
         Self::new()
     }
 }
 
-pub fn find_windows(criteria: &WindowSearchCriteria) -> Result<WindowSearchResults, String> {
-    unsafe {
-        let window_list = CGWindowListCopyWindowInfo(K_CG_WINDOW_LIST_OPTION_ON_SCREEN_ONLY, 0);
-        if window_list.is_null() {
-            return Err("Failed to get window list".to_string());
+impl WindowInfo {
+    pub fn builder() -> WindowInfoBuilder {
+        WindowInfoBuilder::new()
+    }
+
+    /// Index into `screens` of the display showing most of this window.
+    pub fn screen_index(&self, screens: &[ScreenInfo]) -> Option<usize> {
+        screen_index_for_bounds(screens, &self.bounds)
+    }
+
+    /// Indices into `screens` (primary first) of every display whose visible
+    /// frame this window intersects, for windows straddling displays.
+    pub fn screens_touched(&self, screens: &[ScreenInfo]) -> Vec<usize> {
+        let Some(primary_height) = screens.first().map(|screen| screen.frame.size.height) else {
+            return Vec::new();
+        };
+
+        screens
+            .iter()
+            .enumerate()
+            .filter(|(_, screen)| {
+                screen
+                    .cg_visible_frame(primary_height)
+                    .intersection(&self.bounds)
+                    .is_some()
+            })
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    /// Width over height; `None` for zero-height or invalid bounds.
+    pub fn aspect_ratio(&self) -> Option<f64> {
+        self.bounds
+            .is_valid()
+            .then(|| self.bounds.width / self.bounds.height)
+    }
+
+    /// Whether the window covers `screen_frame` (CG coordinates, e.g. from
+    /// `ScreenInfo::cg_frame`), allowing a point of rounding on each edge.
+    pub fn is_fullscreen_on(&self, screen_frame: &WindowBounds) -> bool {
+        self.bounds.x <= screen_frame.x + FULLSCREEN_TOLERANCE
+            && self.bounds.y <= screen_frame.y + FULLSCREEN_TOLERANCE
+            && self.bounds.right() >= screen_frame.right() - FULLSCREEN_TOLERANCE
+            && self.bounds.bottom() >= screen_frame.bottom() - FULLSCREEN_TOLERANCE
+    }
+
+    /// Whether this is a normal-layer window covering a whole display, as
+    /// windows in their own fullscreen Space do. Their bounds are the full
+    /// screen frame, menu bar area included, which no regular window reaches.
+    pub fn is_fullscreen(&self, screens: &[ScreenInfo]) -> bool {
+        let Some(primary_height) = screens.first().map(|screen| screen.frame.size.height) else {
+            return false;
+        };
+        self.layer == 0
+            && screens
+                .iter()
+                .any(|screen| self.is_fullscreen_on(&screen.cg_frame(primary_height)))
+    }
+
+    pub fn top_left(&self) -> (f64, f64) {
+        (self.bounds.x, self.bounds.y)
+    }
+
+    pub fn bottom_right(&self) -> (f64, f64) {
+        (self.bounds.right(), self.bounds.bottom())
+    }
+
+    pub fn area(&self) -> f64 {
+        self.bounds.area()
+    }
+
+    pub fn display_title(&self) -> &str {
+        self.title.as_deref().unwrap_or("<no title>")
+    }
+
+    pub fn activation_target(&self) -> ActivationTarget {
+        ActivationTarget {
+            pid: self.pid,
+            bundle_id: self.bundle_identifier.clone(),
+            window_number: self.window_number,
+            title: self.title.clone(),
         }
+    }
+
+    /// The bundle id when known, otherwise the app name.
+    pub fn app_key(&self) -> &str {
+        self.bundle_identifier.as_deref().unwrap_or(&self.app_name)
+    }
 
-        let count = CFArrayGetCount(window_list);
-        let mut matched_windows = Vec::new();
-        let mut total_processed = 0;
+    /// The owning app's icon, from the running app or, once it has quit, from
+    /// its bundle through `NSWorkspace`. Main thread only, like other AppKit
+    /// image use.
+    pub fn app_icon(&self) -> Option<Retained<NSImage>> {
+        NSRunningApplication::runningApplicationWithProcessIdentifier(self.pid.0)
+            .and_then(|app| app.icon())
+            .or_else(|| {
+                let bundle_id = NSString::from_str(self.bundle_identifier.as_deref()?);
+                let workspace = NSWorkspace::sharedWorkspace();
+                let path = workspace
+                    .URLForApplicationWithBundleIdentifier(&bundle_id)?
+                    .path()?;
+                Some(workspace.iconForFile(&path))
+            })
+    }
 
-        for i in 0..count {
-            let window_dict = CFArrayGetValueAtIndex(window_list, i);
-            if window_dict.is_null() {
-                continue;
+    /// Heuristic identity for recognising the "same" window after its window
+    /// number changed, e.g. across an app restart. Built from the app (bundle id
+    /// when known), the title and the size rounded to whole points, so distinct
+    /// windows sharing all three collide. The value is the same on every
+    /// machine and release, so it can be saved.
+    pub fn fingerprint(&self) -> u64 {
+        let mut hasher = StableHasher::new();
+        hasher.write_str(self.app_key());
+        match &self.title {
+            Some(title) => {
+                hasher.write(&[1]);
+                hasher.write_str(title);
             }
+            None => hasher.write(&[0]),
+        }
+        hasher.write(&(self.bounds.width.round() as i64).to_le_bytes());
+        hasher.write(&(self.bounds.height.round() as i64).to_le_bytes());
+        hasher.finish()
+    }
 
-            let app_name =
-                get_dict_string_safe(window_dict, "kCGWindowOwnerName").unwrap_or_default();
+    pub fn matches_fingerprint(&self, fingerprint: u64) -> bool {
+        self.fingerprint() == fingerprint
+    }
 
-            if should_ignore_app(&app_name, &criteria.ignored_apps) {
-                continue;
-            }
+    /// Field-by-field equality, skipping the fields in `ignore`.
+    pub fn eq_ignoring(&self, other: &WindowInfo, ignore: &[WindowField]) -> bool {
+        WindowField::ALL
+            .iter()
+            .filter(|field| !ignore.contains(field))
+            .all(|field| match field {
+                WindowField::Title => self.title == other.title,
+                WindowField::AppName => self.app_name == other.app_name,
+                WindowField::CgOwnerName => self.cg_owner_name == other.cg_owner_name,
+                WindowField::BundleIdentifier => self.bundle_identifier == other.bundle_identifier,
+                WindowField::Bounds => self.bounds == other.bounds,
+                WindowField::WindowNumber => self.window_number == other.window_number,
+                WindowField::Pid => self.pid == other.pid,
+                WindowField::Layer => self.layer == other.layer,
+                WindowField::Alpha => self.alpha == other.alpha,
+                WindowField::SharingState => self.sharing_state == other.sharing_state,
+                WindowField::MemoryUsage => self.memory_usage == other.memory_usage,
+                WindowField::IsOnscreen => self.is_onscreen == other.is_onscreen,
+                WindowField::ParentWindowNumber => {
+                    self.parent_window_number == other.parent_window_number
+                }
+                WindowField::IsMinimized => self.is_minimized == other.is_minimized,
+                WindowField::ZIndex => self.z_index == other.z_index,
+            })
+    }
+}
 
-            total_processed += 1;
+/// A `WindowInfo` field, for comparisons that skip some of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WindowField {
+    Title,
+    AppName,
+    CgOwnerName,
+    BundleIdentifier,
+    Bounds,
+    WindowNumber,
+    Pid,
+    Layer,
+    Alpha,
+    SharingState,
+    MemoryUsage,
+    IsOnscreen,
+    ParentWindowNumber,
+    IsMinimized,
+    ZIndex,
+}
 
-            let title = get_dict_string_safe(window_dict, "kCGWindowName").unwrap_or_default();
+impl WindowField {
+    pub const ALL: [WindowField; 15] = [
+        WindowField::Title,
+        WindowField::AppName,
+        WindowField::CgOwnerName,
+        WindowField::BundleIdentifier,
+        WindowField::Bounds,
+        WindowField::WindowNumber,
+        WindowField::Pid,
+        WindowField::Layer,
+        WindowField::Alpha,
+        WindowField::SharingState,
+        WindowField::MemoryUsage,
+        WindowField::IsOnscreen,
+        WindowField::ParentWindowNumber,
+        WindowField::IsMinimized,
+        WindowField::ZIndex,
+    ];
 
-            if !criteria.matches(&title, &app_name) {
-                continue;
-            }
+    /// Fields that change between scans of an otherwise unchanged window; the
+    /// z-index shifts whenever any window in front opens, closes or reorders.
+    pub const VOLATILE: [WindowField; 3] = [
+        WindowField::MemoryUsage,
+        WindowField::Alpha,
+        WindowField::ZIndex,
+    ];
+}
 
-            let bounds = parse_bounds_from_dict(window_dict);
-            let window_number =
-                get_dict_number_safe(window_dict, "kCGWindowNumber").unwrap_or(0.0) as i64;
-            let pid = get_dict_number_safe(window_dict, "kCGWindowOwnerPID").unwrap_or(0.0) as i32;
-            let layer = get_dict_number_safe(window_dict, "kCGWindowLayer").unwrap_or(0.0) as i32;
-            let alpha = get_dict_number_safe(window_dict, "kCGWindowAlpha").unwrap_or(1.0);
-            let sharing_state =
-                get_dict_number_safe(window_dict, "kCGWindowSharingState").unwrap_or(0.0) as i32;
-            let memory_usage =
-                get_dict_number_safe(window_dict, "kCGWindowMemoryUsage").unwrap_or(0.0) as i64;
-            let is_onscreen =
-                get_dict_number_safe(window_dict, "kCGWindowIsOnscreen").unwrap_or(0.0) != 0.0;
-
-            let bundle_identifier = get_bundle_identifier(pid);
-
-            matched_windows.push(WindowInfo {
-                title,
-                app_name: app_name.clone(),
-                bundle_identifier,
-                bounds,
-                window_number,
-                pid,
-                layer,
-                alpha,
-                sharing_state,
-                memory_usage,
-                is_onscreen,
-            });
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WindowSearchError {
+    WindowListUnavailable,
+    WindowNotFound(WindowNumber),
+    /// A `MatchMode::Regex` pattern in the criteria failed to compile.
+    InvalidPattern(String),
+}
+
+impl fmt::Display for WindowSearchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WindowSearchError::WindowListUnavailable => write!(f, "Failed to get window list"),
+            WindowSearchError::WindowNotFound(window_number) => {
+                write!(f, "Window {} not found", window_number)
+            }
+            WindowSearchError::InvalidPattern(message) => {
+                write!(f, "Invalid match pattern: {}", message)
+            }
         }
+    }
+}
 
-        CFRelease(window_list);
+impl std::error::Error for WindowSearchError {}
 
-        Ok(WindowSearchResults {
-            total_windows: total_processed,
-            matched_windows,
-        })
+#[derive(Debug, PartialEq, Serialize)]
+pub struct WindowSearchResults {
+    pub total_windows: usize,
+    pub matched_windows: Vec<WindowInfo>,
+}
+
+/// Borrowed view of a `WindowInfo`. It cannot outlive the `WindowSearchResults`
+/// it came from; convert it with `WindowInfo::from` to keep a window past that.
+#[derive(Debug, Clone, Copy)]
+pub struct WindowInfoRef<'a> {
+    pub title: Option<&'a str>,
+    pub app_name: &'a str,
+    pub cg_owner_name: Option<&'a str>,
+    pub bundle_identifier: Option<&'a str>,
+    pub bounds: WindowBounds,
+    pub window_number: WindowNumber,
+    pub pid: Pid,
+    pub layer: i32,
+    pub alpha: f64,
+    pub sharing_state: i32,
+    pub memory_usage: i64,
+    pub is_onscreen: bool,
+    pub parent_window_number: Option<WindowNumber>,
+    pub is_minimized: Option<bool>,
+    pub z_index: usize,
+}
+
+impl<'a> From<&'a WindowInfo> for WindowInfoRef<'a> {
+    fn from(window: &'a WindowInfo) -> Self {
+        Self {
+            title: window.title.as_deref(),
+            app_name: &window.app_name,
+            cg_owner_name: window.cg_owner_name.as_deref(),
+            bundle_identifier: window.bundle_identifier.as_deref(),
+            bounds: window.bounds,
+            window_number: window.window_number,
+            pid: window.pid,
+            layer: window.layer,
+            alpha: window.alpha,
+            sharing_state: window.sharing_state,
+            memory_usage: window.memory_usage,
+            is_onscreen: window.is_onscreen,
+            parent_window_number: window.parent_window_number,
+            is_minimized: window.is_minimized,
+            z_index: window.z_index,
+        }
     }
 }
 
-fn parse_bounds_from_dict(dict: *const c_void) -> String {
-    if let Some(bounds_dict) = get_dict_value(dict, "kCGWindowBounds") {
-        let x = get_dict_number_safe(bounds_dict, "X").unwrap_or(0.0);
-        let y = get_dict_number_safe(bounds_dict, "Y").unwrap_or(0.0);
-        let width = get_dict_number_safe(bounds_dict, "Width").unwrap_or(0.0);
-        let height = get_dict_number_safe(bounds_dict, "Height").unwrap_or(0.0);
-        format!("x:{}, y:{}, w:{}, h:{}", x, y, width, height)
-    } else {
-        "bounds_not_found".to_string()
+impl From<WindowInfoRef<'_>> for WindowInfo {
+    fn from(window: WindowInfoRef<'_>) -> Self {
+        Self {
+            title: window.title.map(str::to_string),
+            app_name: window.app_name.to_string(),
+            cg_owner_name: window.cg_owner_name.map(str::to_string),
+            bundle_identifier: window.bundle_identifier.map(str::to_string),
+            bounds: window.bounds,
+            window_number: window.window_number,
+            pid: window.pid,
+            layer: window.layer,
+            alpha: window.alpha,
+            sharing_state: window.sharing_state,
+            memory_usage: window.memory_usage,
+            is_onscreen: window.is_onscreen,
+            parent_window_number: window.parent_window_number,
+            is_minimized: window.is_minimized,
+            z_index: window.z_index,
+        }
     }
 }
 
-fn get_dict_value(dict: *const c_void, key: &str) -> Option<*const c_void> {
-    unsafe {
-        let key_cstring = CString::new(key).ok()?;
-        let cf_key =
-            CFStringCreateWithCString(ptr::null(), key_cstring.as_ptr(), K_CF_STRING_ENCODING_UTF8);
+impl WindowSearchResults {
+    /// Matched windows grouped by display, one group per entry of `screens` (in
+    /// order, empty groups included), followed by a `None` group for windows not
+    /// on any of them.
+    pub fn by_screen(&self, screens: &[ScreenInfo]) -> Vec<(Option<ScreenInfo>, Vec<&WindowInfo>)> {
+        let mut groups: Vec<(Option<ScreenInfo>, Vec<&WindowInfo>)> = screens
+            .iter()
+            .map(|screen| (Some(*screen), Vec::new()))
+            .chain(std::iter::once((None, Vec::new())))
+            .collect();
 
-        if cf_key.is_null() {
-            return None;
+        for window in &self.matched_windows {
+            let index = window.screen_index(screens).unwrap_or(screens.len());
+            groups[index].1.push(window);
         }
 
-        let cf_value = CFDictionaryGetValue(dict, cf_key);
-        CFRelease(cf_key);
+        groups
+    }
+
+    /// Whether both results matched the same windows, in the same order, equal
+    /// in every field outside `ignore`. `total_windows` is not compared since it
+    /// counts unrelated windows too; `==` stays strict.
+    pub fn semantically_eq(&self, other: &WindowSearchResults, ignore: &[WindowField]) -> bool {
+        self.matched_windows.len() == other.matched_windows.len()
+            && self
+                .matched_windows
+                .iter()
+                .zip(&other.matched_windows)
+                .all(|(window, other)| window.eq_ignoring(other, ignore))
+    }
 
-        if cf_value.is_null() {
-            None
-        } else {
-            Some(cf_value)
+    pub fn iter_refs(&self) -> impl Iterator<Item = WindowInfoRef<'_>> {
+        self.matched_windows.iter().map(WindowInfoRef::from)
+    }
+
+    /// Unions two result sets, treating `other` as the more recent scan: a window
+    /// present in both keeps its position from `self` but takes `other`'s info.
+    /// `total_windows` is the larger of the two counts, since both scans saw
+    /// (mostly) the same desktop and summing would double count.
+    pub fn merge(mut self, other: WindowSearchResults) -> WindowSearchResults {
+        for window in other.matched_windows {
+            match self
+                .matched_windows
+                .iter_mut()
+                .find(|existing| existing.window_number == window.window_number)
+            {
+                Some(existing) => *existing = window,
+                None => self.matched_windows.push(window),
+            }
+        }
+
+        WindowSearchResults {
+            total_windows: self.total_windows.max(other.total_windows),
+            matched_windows: self.matched_windows,
         }
     }
-}
 
-fn should_ignore_app(app_name: &str, ignored_apps: &HashSet<String>) -> bool {
-    let app_lower = app_name.to_lowercase();
-    ignored_apps
-        .iter()
-        .any(|ignored| app_lower.contains(ignored))
-}
+    /// Orders the matched windows front to back. A scan already returns them
+    /// in that order; this restores it after `merge` or manual edits.
+    pub fn sort_by_z_order(&mut self) {
+        self.matched_windows.sort_by_key(|window| window.z_index);
+    }
+
+    /// The frontmost matched window.
+    pub fn topmost(&self) -> Option<&WindowInfo> {
+        self.matched_windows
+            .iter()
+            .min_by_key(|window| window.z_index)
+    }
+
+    /// Splits the matched windows into (visible, hidden). A window is visible
+    /// when it is on screen and at least `min_fraction` of it is uncovered by
+    /// the normal-layer matched windows in front of it. Occlusion only considers
+    /// the matched windows, in scan (front to back) order, so scan with broad
+    /// criteria such as `everything().with_raw_cg_options(K_CG_WINDOW_LIST_OPTION_ALL)`
+    /// to account for every occluder and see off-screen windows at all.
+    pub fn partition_visibility(&self, min_fraction: f64) -> (Vec<&WindowInfo>, Vec<&WindowInfo>) {
+        let mut occluders = Vec::new();
+        let mut visible = Vec::new();
+        let mut hidden = Vec::new();
 
-fn get_dict_string_safe(dict: *const c_void, key: &str) -> Option<String> {
-    unsafe {
-        let key_cstring = CString::new(key).ok()?;
-        let cf_key =
-            CFStringCreateWithCString(ptr::null(), key_cstring.as_ptr(), K_CF_STRING_ENCODING_UTF8);
+        for window in &self.matched_windows {
+            let is_visible =
+                window.is_onscreen && visible_fraction(&window.bounds, &occluders) >= min_fraction;
+            if is_visible {
+                visible.push(window);
+            } else {
+                hidden.push(window);
+            }
 
-        if cf_key.is_null() {
-            return None;
+            if window.is_onscreen
+                && window.layer == 0
+                && window.alpha > 0.0
+                && window.bounds.is_valid()
+            {
+                occluders.push(window.bounds);
+            }
         }
 
-        let cf_value = CFDictionaryGetValue(dict, cf_key);
-        CFRelease(cf_key);
+        (visible, hidden)
+    }
+
+    /// RFC 4180 CSV: a header row, then one CRLF-terminated row per matched
+    /// window. A missing title or bundle id is an empty field.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from(CSV_HEADER);
+        csv.push_str("\r\n");
 
-        if cf_value.is_null() {
-            return None;
+        for window in &self.matched_windows {
+            let fields = [
+                csv_field(window.title.as_deref().unwrap_or_default()),
+                csv_field(&window.app_name),
+                csv_field(window.bundle_identifier.as_deref().unwrap_or_default()),
+                window.pid.to_string(),
+                window.bounds.x.to_string(),
+                window.bounds.y.to_string(),
+                window.bounds.width.to_string(),
+                window.bounds.height.to_string(),
+                window.layer.to_string(),
+                window.alpha.to_string(),
+                window.is_onscreen.to_string(),
+            ];
+            csv.push_str(&fields.join(","));
+            csv.push_str("\r\n");
         }
 
-        let length = CFStringGetLength(cf_value);
-        if length == 0 {
-            return Some(String::new());
+        csv
+    }
+
+    /// Matched windows grouped by app (`WindowInfo::app_key`), sorted by app
+    /// name and then window number.
+    pub fn group_by_app(&self) -> Vec<Vec<&WindowInfo>> {
+        let mut windows: Vec<&WindowInfo> = self.matched_windows.iter().collect();
+        windows.sort_by(|a, b| {
+            (a.app_name.as_str(), a.app_key(), a.window_number).cmp(&(
+                b.app_name.as_str(),
+                b.app_key(),
+                b.window_number,
+            ))
+        });
+
+        let mut groups: Vec<Vec<&WindowInfo>> = Vec::new();
+        for window in windows {
+            match groups.last_mut() {
+                Some(group) if group[0].app_key() == window.app_key() => group.push(window),
+                _ => groups.push(vec![window]),
+            }
         }
+        groups
+    }
 
-        let mut buffer = vec![0u8; (length * 4 + 1) as usize];
-        let success = CFStringGetCString(
-            cf_value,
-            buffer.as_mut_ptr() as *mut c_char,
-            buffer.len() as isize,
-            K_CF_STRING_ENCODING_UTF8,
-        );
+    /// Indented overview, one header per app with its windows beneath:
+    ///
+    /// ```text
+    /// Preview (com.apple.Preview)
+    ///   ├─ "a.pdf" [#12 0,25 800×600]
+    ///   └─ "b.pdf" [#40 100,100 640×480]
+    /// ```
+    pub fn format_tree(&self) -> String {
+        let mut tree = String::new();
+        for group in self.group_by_app() {
+            let app = group[0];
+            match &app.bundle_identifier {
+                Some(bundle_identifier) => {
+                    tree.push_str(&format!("{} ({})\n", app.app_name, bundle_identifier))
+                }
+                None => tree.push_str(&format!("{}\n", app.app_name)),
+            }
 
-        if success {
-            let c_str = CStr::from_ptr(buffer.as_ptr() as *const c_char);
-            Some(c_str.to_string_lossy().into_owned())
-        } else {
-            None
+            for (index, window) in group.iter().enumerate() {
+                let branch = if index + 1 == group.len() {
+                    "└─"
+                } else {
+                    "├─"
+                };
+                tree.push_str(&format!(
+                    "  {} \"{}\" [#{} {},{} {}×{}]\n",
+                    branch,
+                    window.display_title(),
+                    window.window_number,
+                    window.bounds.x,
+                    window.bounds.y,
+                    window.bounds.width,
+                    window.bounds.height
+                ));
+            }
         }
+        tree
+    }
+}
+
+const CSV_HEADER: &str = "title,app,bundle_id,pid,x,y,w,h,layer,alpha,onscreen";
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
     }
 }
 
-fn get_dict_number_safe(dict: *const c_void, key: &str) -> Option<f64> {
-    unsafe {
-        let key_cstring = CString::new(key).ok()?;
-        let cf_key =
-            CFStringCreateWithCString(ptr::null(), key_cstring.as_ptr(), K_CF_STRING_ENCODING_UTF8);
+#[derive(Debug, Clone, PartialEq)]
+pub enum CfValue {
+    String(String),
+    Number(f64),
+    Boolean(bool),
+    Dictionary(Vec<(String, CfValue)>),
+    Other(String),
+}
+
+impl CfValue {
+    pub fn type_name(&self) -> &str {
+        match self {
+            CfValue::String(_) => "string",
+            CfValue::Number(_) => "number",
+            CfValue::Boolean(_) => "boolean",
+            CfValue::Dictionary(_) => "dictionary",
+            CfValue::Other(type_name) => type_name,
+        }
+    }
+}
 
-        if cf_key.is_null() {
-            return None;
+impl fmt::Display for CfValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CfValue::String(value) => write!(f, "{:?}", value),
+            CfValue::Number(value) => write!(f, "{}", value),
+            CfValue::Boolean(value) => write!(f, "{}", value),
+            CfValue::Dictionary(entries) => {
+                let inner: Vec<String> = entries
+                    .iter()
+                    .map(|(key, value)| format!("{} = {}", key, value))
+                    .collect();
+                write!(f, "{{{}}}", inner.join(", "))
+            }
+            CfValue::Other(_) => write!(f, "<unsupported>"),
         }
+    }
+}
+
+#[derive(Clone)]
+struct WindowTransform(Arc<dyn Fn(WindowInfo) -> WindowInfo + Send + Sync>);
+
+impl fmt::Debug for WindowTransform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("WindowTransform")
+    }
+}
+
+/// How `with_title_matching` and `with_app_name_matching` compare text. All
+/// modes are case-sensitive; use a `(?i)` regex for the rest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MatchMode {
+    Exact,
+    Contains,
+    StartsWith,
+    Regex,
+}
+
+#[derive(Debug, Clone)]
+struct TextMatch {
+    mode: MatchMode,
+    pattern: String,
+    regex: Option<Result<Regex, regex::Error>>,
+}
 
-        let cf_value = CFDictionaryGetValue(dict, cf_key);
-        CFRelease(cf_key);
+impl TextMatch {
+    fn describe(&self, field: &str) -> String {
+        match self.mode {
+            MatchMode::Exact => format!("{} {:?}", field, self.pattern),
+            MatchMode::Contains => format!("{} containing {:?}", field, self.pattern),
+            MatchMode::StartsWith => format!("{} starting with {:?}", field, self.pattern),
+            MatchMode::Regex => format!("{} matching /{}/", field, self.pattern),
+        }
+    }
+}
 
-        if cf_value.is_null() {
-            return None;
+impl TextMatch {
+    fn new(mode: MatchMode, pattern: String) -> Self {
+        let regex = (mode == MatchMode::Regex).then(|| Regex::new(&pattern));
+        Self {
+            mode,
+            pattern,
+            regex,
         }
+    }
 
-        let mut value: f64 = 0.0;
-        let success = CFNumberGetValue(
-            cf_value,
-            K_CF_NUMBER_DOUBLE_TYPE,
-            &mut value as *mut f64 as *mut c_void,
-        );
+    fn matches(&self, text: &str) -> bool {
+        match self.mode {
+            MatchMode::Exact => text == self.pattern,
+            MatchMode::Contains => text.contains(self.pattern.as_str()),
+            MatchMode::StartsWith => text.starts_with(self.pattern.as_str()),
+            MatchMode::Regex => matches!(&self.regex, Some(Ok(regex)) if regex.is_match(text)),
+        }
+    }
 
-        if success {
-            Some(value)
-        } else {
-            None
+    fn validate(&self) -> Result<(), WindowSearchError> {
+        match &self.regex {
+            Some(Err(e)) => Err(WindowSearchError::InvalidPattern(e.to_string())),
+            _ => Ok(()),
         }
     }
 }
 
-fn get_bundle_identifier(pid: i32) -> Option<String> {
-    let app = NSRunningApplication::runningApplicationWithProcessIdentifier(pid)?;
-    app.bundleIdentifier().map(|id| id.to_string())
+#[derive(Debug, Clone)]
+pub struct WindowSearchCriteria {
+    title: Option<TextMatch>,
+    title_document_part: Option<String>,
+    title_app_suffix: Option<String>,
+    app_name: Option<TextMatch>,
+    app_names: Option<HashSet<String>>,
+    bundle_identifier: Option<String>,
+    known_dialog: Option<KnownDialog>,
+    dialog_detection: DialogDetection,
+    ignored_apps: HashSet<String>,
+    transform: Option<WindowTransform>,
+    require_valid_bounds: bool,
+    min_size: Option<(f64, f64)>,
+    max_size: Option<(f64, f64)>,
+    within_rect: Option<WindowBounds>,
+    layer: Option<i32>,
+    onscreen_only: bool,
+    min_visible_fraction: Option<f64>,
+    require_dialog: bool,
+    resolve_parents: bool,
+    include_self: bool,
+    collapse_identical: bool,
+    frontmost_per_app: bool,
+    raw_cg_options: Option<u32>,
+    include_offscreen: bool,
+}
+
+impl WindowSearchCriteria {
+    pub fn new() -> Self {
+        Self {
+            title: None,
+            title_document_part: None,
+            title_app_suffix: None,
+            app_name: None,
+            app_names: None,
+            bundle_identifier: None,
+            known_dialog: None,
+            dialog_detection: DialogDetection::default(),
+            ignored_apps: HashSet::new(),
+            transform: None,
+            require_valid_bounds: false,
+            min_size: None,
+            max_size: None,
+            within_rect: None,
+            layer: None,
+            onscreen_only: false,
+            min_visible_fraction: None,
+            require_dialog: false,
+            resolve_parents: false,
+            include_self: false,
+            collapse_identical: false,
+            frontmost_per_app: false,
+            raw_cg_options: None,
+            include_offscreen: false,
+        }
+    }
+
+    /// Ordinary app windows a user would consider "open": normal layer (0),
+    /// on screen, at least `NORMAL_MIN_VISIBLE_FRACTION` not covered by other
+    /// normal windows, with valid bounds, and not owned by `system_apps()`.
+    pub fn normal_windows() -> Self {
+        Self::new()
+            .with_layer(0)
+            .onscreen_only(true)
+            .with_min_visible_fraction(NORMAL_MIN_VISIBLE_FRACTION)
+            .require_valid_bounds(true)
+            .with_ignored_apps(system_apps())
+    }
+
+    /// Sheets and dialogs of any kind (see `dialog::is_dialog`), with valid
+    /// bounds and not owned by `system_apps()`. Without the `accessibility`
+    /// feature only dialogs with a known title are found.
+    pub fn dialogs_only() -> Self {
+        Self::new()
+            .require_dialog(true)
+            .require_valid_bounds(true)
+            .with_ignored_apps(system_apps())
+    }
+
+    /// Every window CG reports, with no filters at all. The same as `new()`,
+    /// spelled out for call sites that mean it.
+    pub fn everything() -> Self {
+        Self::new()
+    }
+
+    pub fn with_title(self, title: impl Into<String>) -> Self {
+        self.with_title_matching(MatchMode::Exact, title)
+    }
+
+    /// Matches titles against `pattern` using `mode`, e.g.
+    /// `with_title_matching(MatchMode::Regex, r"^Open( File)?$")`. Windows
+    /// without a title never match. An invalid regex makes searches fail with
+    /// `WindowSearchError::InvalidPattern`.
+    pub fn with_title_matching(mut self, mode: MatchMode, pattern: impl Into<String>) -> Self {
+        self.title = Some(TextMatch::new(mode, pattern.into()));
+        self
+    }
+
+    /// Matches the document part of titles like "report.pdf — Preview", i.e.
+    /// everything before the last separator (see `split_title`). Titles without
+    /// a separator are compared whole.
+    pub fn with_title_document_part(mut self, document: impl Into<String>) -> Self {
+        self.title_document_part = Some(document.into());
+        self
+    }
+
+    /// Matches the part after the last title separator, typically the app name.
+    /// Titles without a separator are compared whole.
+    pub fn with_title_app_suffix(mut self, suffix: impl Into<String>) -> Self {
+        self.title_app_suffix = Some(suffix.into());
+        self
+    }
+
+    pub fn with_app_name(self, app_name: impl Into<String>) -> Self {
+        self.with_app_name_matching(MatchMode::Exact, app_name)
+    }
+
+    /// Like `with_title_matching`, for the app name.
+    pub fn with_app_name_matching(mut self, mode: MatchMode, pattern: impl Into<String>) -> Self {
+        self.app_name = Some(TextMatch::new(mode, pattern.into()));
+        self
+    }
+
+    /// Matches windows whose app name equals any of `names`, compared the same
+    /// way as `with_app_name` (exactly). Like every other filter it is combined
+    /// with `with_app_name` by AND, so setting both only matches that one app,
+    /// and only if it is also in the set.
+    pub fn with_app_names(mut self, names: impl IntoIterator<Item = String>) -> Self {
+        self.app_names = Some(names.into_iter().collect());
+        self
+    }
+
+    /// Matches the owning app's bundle id, such as `com.apple.finder`, ignoring
+    /// ASCII case. Unlike app names it doesn't vary with the system language.
+    /// Windows of processes without a bundle id never match.
+    pub fn with_bundle_identifier(mut self, bundle_identifier: impl Into<String>) -> Self {
+        self.bundle_identifier = Some(bundle_identifier.into());
+        self
+    }
+
+    pub fn with_known_dialog(mut self, dialog: KnownDialog) -> Self {
+        self.known_dialog = Some(dialog);
+        self
+    }
+
+    pub fn without_known_dialog(mut self) -> Self {
+        self.known_dialog = None;
+        self
+    }
+
+    /// How `with_known_dialog` recognises the dialog; title or role by
+    /// default. `DialogDetection::Role` ignores titles entirely.
+    pub fn with_dialog_detection(mut self, detection: DialogDetection) -> Self {
+        self.dialog_detection = detection;
+        self
+    }
+
+    /// Rewrites each matched window (e.g. to redact titles) before it is added to
+    /// the results. Runs after every filter, so filters always see the window as
+    /// CG reported it.
+    pub fn with_transform(
+        mut self,
+        transform: impl Fn(WindowInfo) -> WindowInfo + Send + Sync + 'static,
+    ) -> Self {
+        self.transform = Some(WindowTransform(Arc::new(transform)));
+        self
+    }
+
+    /// Drops windows whose bounds fail `WindowBounds::is_valid`.
+    pub fn require_valid_bounds(mut self, enabled: bool) -> Self {
+        self.require_valid_bounds = enabled;
+        self
+    }
+
+    /// Only windows at least this large in both dimensions, in points; drops
+    /// 1×1 helper windows and thin strips.
+    pub fn with_min_size(mut self, width: f64, height: f64) -> Self {
+        self.min_size = Some((width, height));
+        self
+    }
+
+    /// Only windows at most this large in both dimensions, in points.
+    pub fn with_max_size(mut self, width: f64, height: f64) -> Self {
+        self.max_size = Some((width, height));
+        self
+    }
+
+    /// Only windows lying entirely inside `rect`, in CG coordinates, e.g. one
+    /// display's `ScreenInfo::cg_frame`.
+    pub fn within_rect(mut self, rect: WindowBounds) -> Self {
+        self.within_rect = Some(rect);
+        self
+    }
+
+    /// Only windows at this CG window layer; 0 is the normal app window layer.
+    pub fn with_layer(mut self, layer: i32) -> Self {
+        self.layer = Some(layer);
+        self
+    }
+
+    pub fn onscreen_only(mut self, enabled: bool) -> Self {
+        self.onscreen_only = enabled;
+        self
+    }
+
+    /// Only windows with at least this fraction (0.0–1.0) of their area not
+    /// covered by normal-layer windows in front of them.
+    pub fn with_min_visible_fraction(mut self, fraction: f64) -> Self {
+        self.min_visible_fraction = Some(fraction);
+        self
+    }
+
+    /// Only sheets and dialogs, as decided by `dialog::is_dialog`.
+    pub fn require_dialog(mut self, enabled: bool) -> Self {
+        self.require_dialog = enabled;
+        self
+    }
+
+    /// Fills in `WindowInfo::parent_window_number` for matched windows. Costs an
+    /// accessibility round trip per match, and is a no-op without the
+    /// `accessibility` feature.
+    pub fn resolve_parents(mut self, enabled: bool) -> Self {
+        self.resolve_parents = enabled;
+        self
+    }
+
+    /// Windows owned by this process, such as our own overlay panels, are
+    /// skipped unless this is set, so scans don't feed back into themselves.
+    pub fn include_self(mut self, enabled: bool) -> Self {
+        self.include_self = enabled;
+        self
+    }
+
+    /// Keeps only the frontmost of matched windows sharing an app name, title
+    /// and bounds (rounded to whole points), for apps that leave "ghost"
+    /// duplicates behind under different window numbers.
+    pub fn collapse_identical(mut self, enabled: bool) -> Self {
+        self.collapse_identical = enabled;
+        self
+    }
+
+    /// Keeps only each app's frontmost matching window, e.g. for an app
+    /// switcher. Apps are told apart by bundle id, or by name without one.
+    /// Applied after every other filter.
+    pub fn frontmost_per_app(mut self, enabled: bool) -> Self {
+        self.frontmost_per_app = enabled;
+        self
+    }
+
+    /// Passes `options` to `CGWindowListCopyWindowInfo` instead of the flags the
+    /// search function would use, for combinations the typed functions don't
+    /// cover. Build it from the `K_CG_WINDOW_LIST_*` constants: `ALL` (0, every
+    /// window including off-screen ones), `ON_SCREEN_ONLY` (1),
+    /// `ON_SCREEN_ABOVE_WINDOW` (2) and `ON_SCREEN_BELOW_WINDOW` (4) relative to
+    /// the reference window, `INCLUDING_WINDOW` (8) to add the reference window
+    /// itself, and `EXCLUDE_DESKTOP_ELEMENTS` (16). The reference window is
+    /// still the one passed to e.g. `find_windows_including` (none for
+    /// `find_windows`). Searches with raw options bypass `WindowCache`.
+    pub fn with_raw_cg_options(mut self, options: u32) -> Self {
+        self.raw_cg_options = Some(options);
+        self
+    }
+
+    /// Also scans windows that aren't on screen, such as minimized windows and
+    /// those on other spaces, by listing with `K_CG_WINDOW_LIST_OPTION_ALL`
+    /// instead of `ON_SCREEN_ONLY`. Their `WindowInfo::is_minimized` tells the
+    /// two apart. `with_raw_cg_options` takes precedence, and like it this
+    /// bypasses `WindowCache`.
+    pub fn include_offscreen(mut self, enabled: bool) -> Self {
+        self.include_offscreen = enabled;
+        self
+    }
+
+    // The CG list option a scan that would otherwise use `default` runs with.
+    fn list_option(&self, default: u32) -> u32 {
+        match self.raw_cg_options {
+            Some(options) => options,
+            None if self.include_offscreen && default == K_CG_WINDOW_LIST_OPTION_ON_SCREEN_ONLY => {
+                K_CG_WINDOW_LIST_OPTION_ALL
+            }
+            None => default,
+        }
+    }
+
+    pub fn with_ignored_apps(mut self, ignored_apps: HashSet<String>) -> Self {
+        self.ignored_apps = ignored_apps;
+        self
+    }
+
+    pub fn ignored_apps(&self) -> &HashSet<String> {
+        &self.ignored_apps
+    }
+
+    pub fn add_ignored_app(mut self, app: impl Into<String>) -> Self {
+        self.ignored_apps.insert(app.into().to_lowercase());
+        self
+    }
+
+    fn validate(&self) -> Result<(), WindowSearchError> {
+        [&self.title, &self.app_name]
+            .into_iter()
+            .flatten()
+            .try_for_each(TextMatch::validate)
+    }
+
+    fn matches_geometry(&self, bounds: &WindowBounds) -> bool {
+        if let Some((width, height)) = self.min_size {
+            if bounds.width < width || bounds.height < height {
+                return false;
+            }
+        }
+        if let Some((width, height)) = self.max_size {
+            if bounds.width > width || bounds.height > height {
+                return false;
+            }
+        }
+        self.within_rect.is_none_or(|rect| {
+            bounds.x >= rect.x
+                && bounds.y >= rect.y
+                && bounds.right() <= rect.right()
+                && bounds.bottom() <= rect.bottom()
+        })
+    }
+
+    fn matches(&self, window_title: Option<&str>, window_app_name: &str) -> bool {
+        if should_ignore_app(window_app_name, &self.ignored_apps) {
+            return false;
+        }
+
+        let title_matches = self
+            .title
+            .as_ref()
+            .map(|t| window_title.is_some_and(|title| t.matches(title)))
+            .unwrap_or(true);
+
+        let parts = window_title.map(|title| split_title(title).unwrap_or((title, title)));
+        let document_matches = self
+            .title_document_part
+            .as_ref()
+            .map(|d| parts.is_some_and(|(document, _)| document == d))
+            .unwrap_or(true);
+        let suffix_matches = self
+            .title_app_suffix
+            .as_ref()
+            .map(|s| parts.is_some_and(|(_, suffix)| suffix == s))
+            .unwrap_or(true);
+
+        let app_name_matches = self
+            .app_name
+            .as_ref()
+            .map(|a| a.matches(window_app_name))
+            .unwrap_or(true);
+
+        let app_names_match = self
+            .app_names
+            .as_ref()
+            .map(|names| names.contains(window_app_name))
+            .unwrap_or(true);
+
+        title_matches && document_matches && suffix_matches && app_name_matches && app_names_match
+    }
+}
+
+/// Splits a document title such as "report.pdf — Preview" into its document
+/// and app parts at the last " — " or " – ", or failing those the last " - ",
+/// so hyphens inside the document name ("Q3 - Draft — Pages") stay put.
+/// `None` when the title has no separator.
+pub fn split_title(title: &str) -> Option<(&str, &str)> {
+    let dash = [" — ", " – "]
+        .iter()
+        .filter_map(|separator| title.rfind(separator).map(|index| (index, separator.len())))
+        .max_by_key(|(index, _)| *index);
+    let (index, len) = dash.or_else(|| title.rfind(" - ").map(|index| (index, 3)))?;
+    Some((&title[..index], &title[index + len..]))
+}
+
+impl Default for WindowSearchCriteria {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A short summary of what the criteria look for, such as `Open dialogs` or
+/// `title containing "Invoice", app "Preview"`, for logs. Only the filters
+/// that pick windows are listed, not size, layer or visibility limits.
+impl fmt::Display for WindowSearchCriteria {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(dialog) = self.known_dialog {
+            parts.push(format!("{:?} dialogs", dialog));
+        } else if self.require_dialog {
+            parts.push("dialogs".to_string());
+        }
+        if let Some(title) = &self.title {
+            parts.push(title.describe("title"));
+        }
+        if let Some(document) = &self.title_document_part {
+            parts.push(format!("document {:?}", document));
+        }
+        if let Some(suffix) = &self.title_app_suffix {
+            parts.push(format!("title suffix {:?}", suffix));
+        }
+        if let Some(app_name) = &self.app_name {
+            parts.push(app_name.describe("app"));
+        }
+        if let Some(app_names) = &self.app_names {
+            let mut app_names: Vec<&String> = app_names.iter().collect();
+            app_names.sort();
+            parts.push(format!("apps {:?}", app_names));
+        }
+        if let Some(bundle_identifier) = &self.bundle_identifier {
+            parts.push(format!("bundle id {:?}", bundle_identifier));
+        }
+
+        if parts.is_empty() {
+            f.write_str("any window")
+        } else {
+            f.write_str(&parts.join(", "))
+        }
+    }
+}
+
+pub fn find_windows(
+    criteria: &WindowSearchCriteria,
+) -> Result<WindowSearchResults, WindowSearchError> {
+    scan_windows(K_CG_WINDOW_LIST_OPTION_ON_SCREEN_ONLY, 0, criteria)
+}
+
+/// Number of on-screen windows matching `criteria`. Runs the same filters as
+/// `find_windows` but skips everything only needed to build a `WindowInfo`,
+/// such as bundle id lookups, the transform and the allocations.
+pub fn count_windows(criteria: &WindowSearchCriteria) -> Result<usize, WindowSearchError> {
+    criteria.validate()?;
+    let option = criteria.list_option(K_CG_WINDOW_LIST_OPTION_ON_SCREEN_ONLY);
+    let window_list = WindowList::copy(option, 0)?;
+    Ok(count_window_list(
+        &window_list,
+        criteria,
+        &mut ScanContext::new(),
+    ))
+}
+
+fn count_window_list(
+    window_list: &WindowList,
+    criteria: &WindowSearchCriteria,
+    context: &mut ScanContext,
+) -> usize {
+    let mut count = 0;
+    walk_window_list(window_list, criteria, context, |_, _| count += 1);
+    count
+}
+
+/// Scans `window_number` itself plus every on-screen window in front of it,
+/// front to back. `OnScreenAboveWindow` alone would exclude the reference
+/// window; adding `IncludingWindow` keeps it as the last entry (when it matches
+/// `criteria`), which makes this the primitive for occlusion checks.
+pub fn find_windows_including(
+    window_number: WindowNumber,
+    criteria: &WindowSearchCriteria,
+) -> Result<WindowSearchResults, WindowSearchError> {
+    scan_including(window_number, criteria, WindowList::copy)
+}
+
+fn scan_including(
+    window_number: WindowNumber,
+    criteria: &WindowSearchCriteria,
+    copy: impl FnOnce(u32, u32) -> Result<WindowList, WindowSearchError>,
+) -> Result<WindowSearchResults, WindowSearchError> {
+    scan_windows_with(
+        K_CG_WINDOW_LIST_OPTION_ON_SCREEN_ABOVE_WINDOW | K_CG_WINDOW_LIST_OPTION_INCLUDING_WINDOW,
+        window_number.0 as u32,
+        criteria,
+        copy,
+    )
+}
+
+/// The window the user is looking at: the frontmost app's focused window with
+/// the `accessibility` feature, otherwise its frontmost on-screen window at
+/// the normal layer. `None` when that app has no such window, e.g. Finder
+/// with only the desktop showing.
+pub fn frontmost_window() -> Result<Option<WindowInfo>, WindowSearchError> {
+    let Some(app) = NSWorkspace::sharedWorkspace().frontmostApplication() else {
+        return Ok(None);
+    };
+    let pid = Pid(app.processIdentifier());
+
+    let criteria = WindowSearchCriteria::new()
+        .with_layer(0)
+        .onscreen_only(true)
+        .require_valid_bounds(true);
+    // Front to back, so the first of the app's windows is its frontmost.
+    let mut windows = find_windows(&criteria)?
+        .matched_windows
+        .into_iter()
+        .filter(|window| window.pid == pid);
+
+    #[cfg(feature = "accessibility")]
+    {
+        let focused = crate::ax::AxElement::application(pid.0)
+            .and_then(|app| app.element_attribute("AXFocusedWindow"))
+            .and_then(|window| window.window_number());
+        if let Some(focused) = focused {
+            let windows: Vec<WindowInfo> = windows.collect();
+            let fallback = windows.first().cloned();
+            return Ok(windows
+                .into_iter()
+                .find(|window| window.window_number.0 == focused)
+                .or(fallback));
+        }
+    }
+
+    Ok(windows.next())
+}
+
+/// Like `find_windows`, but served from the process-wide `WINDOW_CACHE`: scans
+/// within its TTL reuse the last CG window list instead of fetching a new one.
+/// Criteria are still applied per call, so callers with different criteria can
+/// share the cache.
+pub fn find_windows_cached(
+    criteria: &WindowSearchCriteria,
+) -> Result<WindowSearchResults, WindowSearchError> {
+    WINDOW_CACHE.find_windows(criteria)
+}
+
+pub const DEFAULT_CACHE_TTL: Duration = Duration::from_millis(16);
+
+pub static WINDOW_CACHE: WindowCache = WindowCache::new(DEFAULT_CACHE_TTL);
+
+pub struct WindowCache {
+    state: Mutex<CacheState>,
+}
+
+struct CacheState {
+    ttl: Duration,
+    entry: Option<(Instant, Arc<WindowList>)>,
+}
+
+impl WindowCache {
+    pub const fn new(ttl: Duration) -> Self {
+        Self {
+            state: Mutex::new(CacheState { ttl, entry: None }),
+        }
+    }
+
+    pub fn set_ttl(&self, ttl: Duration) {
+        self.lock().ttl = ttl;
+    }
+
+    pub fn invalidate(&self) {
+        self.lock().entry = None;
+    }
+
+    pub fn find_windows(
+        &self,
+        criteria: &WindowSearchCriteria,
+    ) -> Result<WindowSearchResults, WindowSearchError> {
+        self.find_windows_at(Instant::now(), criteria)
+    }
+
+    /// `find_windows` against an explicit clock reading.
+    pub fn find_windows_at(
+        &self,
+        now: Instant,
+        criteria: &WindowSearchCriteria,
+    ) -> Result<WindowSearchResults, WindowSearchError> {
+        self.find_windows_with(now, criteria, WindowList::copy)
+    }
+
+    // `find_windows_at` listing windows through `copy` instead of
+    // `WindowList::copy`. Only the default on-screen list is cached.
+    fn find_windows_with(
+        &self,
+        now: Instant,
+        criteria: &WindowSearchCriteria,
+        copy: impl Fn(u32, u32) -> Result<WindowList, WindowSearchError>,
+    ) -> Result<WindowSearchResults, WindowSearchError> {
+        let option = criteria.list_option(K_CG_WINDOW_LIST_OPTION_ON_SCREEN_ONLY);
+        let window_list = if option == K_CG_WINDOW_LIST_OPTION_ON_SCREEN_ONLY {
+            self.window_list_at(now, || copy(option, 0))?
+        } else {
+            Arc::new(copy(option, 0)?)
+        };
+        filter_window_list(&window_list, criteria, &mut ScanContext::new())
+    }
+
+    fn window_list_at(
+        &self,
+        now: Instant,
+        copy: impl FnOnce() -> Result<WindowList, WindowSearchError>,
+    ) -> Result<Arc<WindowList>, WindowSearchError> {
+        let mut state = self.lock();
+        if let Some((fetched_at, window_list)) = &state.entry {
+            if now.saturating_duration_since(*fetched_at) < state.ttl {
+                return Ok(Arc::clone(window_list));
+            }
+        }
+
+        let window_list = Arc::new(copy()?);
+        state.entry = Some((now, Arc::clone(&window_list)));
+        Ok(window_list)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, CacheState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// The array of window dictionaries from `CGWindowListCopyWindowInfo`.
+struct WindowList(CfArray);
+
+impl WindowList {
+    fn copy(option: u32, relative_to_window: u32) -> Result<Self, WindowSearchError> {
+        unsafe { CfArray::from_create_rule(CGWindowListCopyWindowInfo(option, relative_to_window)) }
+            .map(Self)
+            .ok_or(WindowSearchError::WindowListUnavailable)
+    }
+}
+
+/// Scan state worth keeping between scans: the CF strings used as window
+/// dictionary keys and a bounded LRU of per-pid app metadata. One-off searches
+/// build a fresh context per scan; long-running scanners such as
+/// `WindowWatcher` keep one so that setup is paid once for their lifetime.
+///
+/// Cached metadata goes stale once its process exits and the pid is reused, so
+/// call `evict_exited` (or `evict`) when windows disappear.
+#[derive(Debug)]
+pub struct ScanContext {
+    keys: HashMap<&'static str, CfString>,
+    apps: HashMap<Pid, CachedApp>,
+    capacity: usize,
+    clock: u64,
+    lookup: AppLookup,
+    // Every key read, in order, for tests of what a scan skips.
+    #[cfg(test)]
+    reads: Vec<&'static str>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct AppMetadata {
+    localized_name: Option<String>,
+    bundle_identifier: Option<String>,
+}
+
+// Resolves a pid's app metadata; `lookup_app` outside tests.
+#[derive(Clone)]
+struct AppLookup(Arc<dyn Fn(Pid) -> AppMetadata + Send + Sync>);
+
+impl fmt::Debug for AppLookup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("AppLookup")
+    }
+}
+
+fn lookup_app(pid: Pid) -> AppMetadata {
+    AppMetadata {
+        localized_name: get_localized_app_name(pid.0),
+        bundle_identifier: get_bundle_identifier(pid.0),
+    }
+}
+
+#[derive(Debug)]
+struct CachedApp {
+    metadata: AppMetadata,
+    last_used: u64,
+}
+
+impl ScanContext {
+    pub fn new() -> Self {
+        Self::with_capacity(DEFAULT_APP_CACHE_CAPACITY)
+    }
+
+    /// Keeps metadata for at most `capacity` pids, dropping the least recently
+    /// used one beyond that. A capacity of 0 disables the app cache.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            keys: HashMap::new(),
+            apps: HashMap::new(),
+            capacity,
+            clock: 0,
+            lookup: AppLookup(Arc::new(lookup_app)),
+            #[cfg(test)]
+            reads: Vec::new(),
+        }
+    }
+
+    #[cfg(test)]
+    fn with_app_lookup(
+        mut self,
+        lookup: impl Fn(Pid) -> AppMetadata + Send + Sync + 'static,
+    ) -> Self {
+        self.lookup = AppLookup(Arc::new(lookup));
+        self
+    }
+
+    /// `find_windows` reusing this context's keys and app cache.
+    pub fn find_windows(
+        &mut self,
+        criteria: &WindowSearchCriteria,
+    ) -> Result<WindowSearchResults, WindowSearchError> {
+        let option = criteria.list_option(K_CG_WINDOW_LIST_OPTION_ON_SCREEN_ONLY);
+        let window_list = WindowList::copy(option, 0)?;
+        filter_window_list(&window_list, criteria, self)
+    }
+
+    pub fn cached_apps(&self) -> usize {
+        self.apps.len()
+    }
+
+    pub fn evict(&mut self, pid: Pid) {
+        self.apps.remove(&pid);
+    }
+
+    /// Drops metadata for every pid whose process has exited.
+    pub fn evict_exited(&mut self) {
+        self.evict_exited_with(process_exists);
+    }
+
+    fn evict_exited_with(&mut self, is_alive: impl Fn(i32) -> bool) {
+        self.apps.retain(|pid, _| is_alive(pid.0));
+    }
+
+    fn key(&mut self, key: &'static str) -> Option<&CfString> {
+        #[cfg(test)]
+        self.reads.push(key);
+        if !self.keys.contains_key(key) {
+            self.keys.insert(key, CfString::new(key)?);
+        }
+        self.keys.get(key)
+    }
+
+    fn string(&mut self, dict: &CfDictionary, key: &'static str) -> Option<String> {
+        dict.get_string(self.key(key)?)
+    }
+
+    fn number(&mut self, dict: &CfDictionary, key: &'static str) -> Option<f64> {
+        dict.get_number(self.key(key)?)
+    }
+
+    fn boolean(&mut self, dict: &CfDictionary, key: &'static str) -> Option<bool> {
+        dict.get_bool(self.key(key)?)
+    }
+
+    fn bounds(&mut self, dict: &CfDictionary) -> WindowBounds {
+        let bounds_dict = self
+            .key("kCGWindowBounds")
+            .and_then(|key| dict.get_dictionary(key));
+        match bounds_dict {
+            Some(bounds_dict) => WindowBounds::new(
+                self.number(&bounds_dict, "X").unwrap_or(0.0),
+                self.number(&bounds_dict, "Y").unwrap_or(0.0),
+                self.number(&bounds_dict, "Width").unwrap_or(0.0),
+                self.number(&bounds_dict, "Height").unwrap_or(0.0),
+            ),
+            None => WindowBounds::default(),
+        }
+    }
+
+    fn app(&mut self, pid: Pid) -> AppMetadata {
+        self.clock += 1;
+        if let Some(cached) = self.apps.get_mut(&pid) {
+            cached.last_used = self.clock;
+            return cached.metadata.clone();
+        }
+
+        let metadata = (self.lookup.0)(pid);
+        if self.capacity == 0 {
+            return metadata;
+        }
+
+        if self.apps.len() >= self.capacity {
+            let least_recent = self
+                .apps
+                .iter()
+                .min_by_key(|(_, cached)| cached.last_used)
+                .map(|(pid, _)| *pid);
+            if let Some(least_recent) = least_recent {
+                self.apps.remove(&least_recent);
+            }
+        }
+        self.apps.insert(
+            pid,
+            CachedApp {
+                metadata: metadata.clone(),
+                last_used: self.clock,
+            },
+        );
+        metadata
+    }
+}
+
+impl Default for ScanContext {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn scan_windows(
+    option: u32,
+    relative_to_window: u32,
+    criteria: &WindowSearchCriteria,
+) -> Result<WindowSearchResults, WindowSearchError> {
+    scan_windows_with(option, relative_to_window, criteria, WindowList::copy)
+}
+
+fn scan_windows_with(
+    option: u32,
+    relative_to_window: u32,
+    criteria: &WindowSearchCriteria,
+    copy: impl FnOnce(u32, u32) -> Result<WindowList, WindowSearchError>,
+) -> Result<WindowSearchResults, WindowSearchError> {
+    let window_list = copy(criteria.list_option(option), relative_to_window)?;
+    filter_window_list(&window_list, criteria, &mut ScanContext::new())
+}
+
+fn filter_window_list(
+    window_list: &WindowList,
+    criteria: &WindowSearchCriteria,
+    context: &mut ScanContext,
+) -> Result<WindowSearchResults, WindowSearchError> {
+    criteria.validate()?;
+    let mut matched_windows = Vec::new();
+    let total_windows = walk_window_list(window_list, criteria, context, |candidate, context| {
+        let window = candidate.into_window_info(criteria.resolve_parents, context);
+        matched_windows.push(match &criteria.transform {
+            Some(WindowTransform(transform)) => transform(window),
+            None => window,
+        });
+    });
+
+    Ok(WindowSearchResults {
+        total_windows,
+        matched_windows,
+    })
+}
+
+/// A window entry that passed every filter, holding only what filtering had to
+/// read. The remaining fields, including the comparatively expensive bundle id,
+/// are resolved by `into_window_info`.
+struct WindowCandidate {
+    window_dict: CfDictionary,
+    title: Option<String>,
+    app_name: String,
+    cg_owner_name: Option<String>,
+    bounds: WindowBounds,
+    window_number: WindowNumber,
+    pid: Pid,
+    layer: i32,
+    alpha: f64,
+    is_onscreen: bool,
+    z_index: usize,
+}
+
+impl WindowCandidate {
+    fn into_window_info(self, resolve_parent: bool, context: &mut ScanContext) -> WindowInfo {
+        let sharing_state = context
+            .number(&self.window_dict, "kCGWindowSharingState")
+            .unwrap_or(0.0) as i32;
+        let memory_usage = context
+            .number(&self.window_dict, "kCGWindowMemoryUsage")
+            .unwrap_or(0.0) as i64;
+
+        WindowInfo {
+            title: self.title,
+            app_name: self.app_name,
+            cg_owner_name: self.cg_owner_name,
+            bundle_identifier: context.app(self.pid).bundle_identifier,
+            bounds: self.bounds,
+            window_number: self.window_number,
+            pid: self.pid,
+            layer: self.layer,
+            alpha: self.alpha,
+            sharing_state,
+            memory_usage,
+            is_onscreen: self.is_onscreen,
+            parent_window_number: if resolve_parent {
+                parent_window_number(self.pid, self.window_number)
+            } else {
+                None
+            },
+            is_minimized: if self.is_onscreen {
+                Some(false)
+            } else {
+                is_window_minimized(self.pid, self.window_number)
+            },
+            z_index: self.z_index,
+        }
+    }
+}
+
+#[cfg(feature = "accessibility")]
+fn parent_window_number(pid: Pid, window_number: WindowNumber) -> Option<WindowNumber> {
+    crate::ax::parent_window_number(pid.0, window_number.0).map(WindowNumber)
+}
+
+#[cfg(not(feature = "accessibility"))]
+fn parent_window_number(_pid: Pid, _window_number: WindowNumber) -> Option<WindowNumber> {
+    None
+}
+
+#[cfg(feature = "accessibility")]
+fn is_window_minimized(pid: Pid, window_number: WindowNumber) -> Option<bool> {
+    crate::ax::is_window_minimized(pid.0, window_number.0)
+}
+
+#[cfg(not(feature = "accessibility"))]
+fn is_window_minimized(_pid: Pid, _window_number: WindowNumber) -> Option<bool> {
+    None
+}
+
+/// Runs `criteria` over `window_list`, front to back, calling `on_match` for
+/// each window that passes. Returns the number of windows considered, i.e. not
+/// owned by an ignored app.
+fn walk_window_list(
+    window_list: &WindowList,
+    criteria: &WindowSearchCriteria,
+    context: &mut ScanContext,
+    mut on_match: impl FnMut(WindowCandidate, &mut ScanContext),
+) -> usize {
+    let mut total_processed = 0;
+    let own_pid = Pid(std::process::id() as i32);
+    let mut seen_identical = HashSet::new();
+    let mut seen_apps = HashSet::new();
+    let mut owners: HashMap<Pid, Option<(String, Option<String>)>> = HashMap::new();
+    // Normal-layer windows seen so far, i.e. everything in front of the
+    // current one, for visibility checks.
+    let mut occluders = Vec::new();
+
+    for i in 0..window_list.0.len() {
+        let Some(window_dict) = window_list.0.dictionary_at(i) else {
+            continue;
+        };
+
+        // Owner checks first, so windows of ignored apps and our own cost
+        // one number read each (plus one name copy per pid).
+        let pid = Pid(context
+            .number(&window_dict, "kCGWindowOwnerPID")
+            .unwrap_or(0.0) as i32);
+        let owner = if !criteria.include_self && pid == own_pid {
+            None
+        } else {
+            // Every window of a process shares its owner name, so each pid's
+            // name is copied out (and checked against the ignore list) once.
+            owners
+                .entry(pid)
+                .or_insert_with(|| {
+                    let cg_owner_name = context.string(&window_dict, "kCGWindowOwnerName");
+                    let app_name = match cg_owner_name.as_deref() {
+                        Some(name) if !name.is_empty() => name.to_string(),
+                        _ => fallback_app_name(pid, context.app(pid)),
+                    };
+                    let cg_owner_name = cg_owner_name.filter(|name| *name != app_name);
+                    (!should_ignore_app(&app_name, &criteria.ignored_apps))
+                        .then_some((app_name, cg_owner_name))
+                })
+                .as_ref()
+        };
+        // Rejected windows still cover the ones behind them, which only
+        // matters with a visibility filter.
+        if owner.is_none() && criteria.min_visible_fraction.is_none() {
+            continue;
+        }
+
+        let bounds = context.bounds(&window_dict);
+        let layer = context
+            .number(&window_dict, "kCGWindowLayer")
+            .unwrap_or(0.0) as i32;
+        let alpha = context
+            .number(&window_dict, "kCGWindowAlpha")
+            .unwrap_or(1.0);
+        let in_front = occluders.len();
+        if layer == 0 && alpha > 0.0 && bounds.is_valid() {
+            occluders.push(bounds);
+        }
+
+        let Some((app_name, cg_owner_name)) = owner else {
+            continue;
+        };
+
+        total_processed += 1;
+
+        // Number reads and comparisons first; the title copy and the
+        // visibility computation only for windows that get past them.
+        if criteria.require_valid_bounds && !bounds.is_valid() {
+            continue;
+        }
+
+        if !criteria.matches_geometry(&bounds) {
+            continue;
+        }
+
+        if criteria.layer.is_some_and(|wanted| wanted != layer) {
+            continue;
+        }
+
+        // A CFBoolean, which the number reads reject.
+        let is_onscreen = context
+            .boolean(&window_dict, "kCGWindowIsOnscreen")
+            .unwrap_or(false);
+        if criteria.onscreen_only && !is_onscreen {
+            continue;
+        }
+
+        let title = context.string(&window_dict, "kCGWindowName");
+
+        if !criteria.matches(title.as_deref(), app_name) {
+            continue;
+        }
+
+        // Resolved per pid through the context's app cache.
+        if let Some(wanted) = &criteria.bundle_identifier {
+            let bundle_identifier = context.app(pid).bundle_identifier;
+            if !bundle_identifier.is_some_and(|id| id.eq_ignore_ascii_case(wanted)) {
+                continue;
+            }
+        }
+
+        if let Some(min_fraction) = criteria.min_visible_fraction {
+            if visible_fraction(&bounds, &occluders[..in_front]) < min_fraction {
+                continue;
+            }
+        }
+
+        let window_number = WindowNumber(
+            context
+                .number(&window_dict, "kCGWindowNumber")
+                .unwrap_or(0.0) as i64,
+        );
+
+        if let Some(dialog) = criteria.known_dialog {
+            if !dialog.matches_with(
+                criteria.dialog_detection,
+                title.as_deref().unwrap_or_default(),
+                pid,
+                window_number,
+            ) {
+                continue;
+            }
+        }
+
+        if criteria.require_dialog
+            && !is_dialog(title.as_deref().unwrap_or_default(), pid, window_number)
+        {
+            continue;
+        }
+
+        // The list is front to back, so the frontmost of a ghost group wins.
+        if criteria.collapse_identical
+            && !seen_identical.insert(identity_key(app_name, title.as_deref(), &bounds))
+        {
+            continue;
+        }
+
+        // Also front to back, so the first match per app is its frontmost.
+        if criteria.frontmost_per_app {
+            let app_key = context
+                .app(pid)
+                .bundle_identifier
+                .unwrap_or_else(|| app_name.clone());
+            if !seen_apps.insert(app_key) {
+                continue;
+            }
+        }
+
+        on_match(
+            WindowCandidate {
+                window_dict,
+                title,
+                app_name: app_name.clone(),
+                cg_owner_name: cg_owner_name.clone(),
+                bounds,
+                window_number,
+                pid,
+                layer,
+                alpha,
+                is_onscreen,
+                z_index: i,
+            },
+            context,
+        );
+    }
+
+    total_processed
+}
+
+// The name for an app CG reported no owner name for.
+fn fallback_app_name(pid: Pid, app: AppMetadata) -> String {
+    app.localized_name
+        .filter(|name| !name.is_empty())
+        .or(app.bundle_identifier)
+        .unwrap_or_else(|| format!("pid {}", pid))
+}
+
+fn identity_key(
+    app_name: &str,
+    title: Option<&str>,
+    bounds: &WindowBounds,
+) -> (String, Option<String>, [i64; 4]) {
+    (
+        app_name.to_string(),
+        title.map(str::to_string),
+        [bounds.x, bounds.y, bounds.width, bounds.height].map(|value| value.round() as i64),
+    )
+}
+
+/// Fraction of `bounds` not covered by any of `occluders`. Overlapping
+/// occluders are only counted once: the area is split into the cells formed by
+/// every occluder edge, and each cell is either fully covered or not.
+pub fn visible_fraction(bounds: &WindowBounds, occluders: &[WindowBounds]) -> f64 {
+    if !bounds.is_valid() {
+        return 0.0;
+    }
+
+    let clipped: Vec<WindowBounds> = occluders
+        .iter()
+        .filter_map(|occluder| bounds.intersection(occluder))
+        .collect();
+    if clipped.is_empty() {
+        return 1.0;
+    }
+
+    let mut xs = vec![bounds.x, bounds.right()];
+    let mut ys = vec![bounds.y, bounds.bottom()];
+    for rect in &clipped {
+        xs.extend([rect.x, rect.right()]);
+        ys.extend([rect.y, rect.bottom()]);
+    }
+    for edges in [&mut xs, &mut ys] {
+        edges.sort_by(f64::total_cmp);
+        edges.dedup();
+    }
+
+    let mut covered = 0.0;
+    for column in xs.windows(2) {
+        for row in ys.windows(2) {
+            let (mid_x, mid_y) = ((column[0] + column[1]) / 2.0, (row[0] + row[1]) / 2.0);
+            if clipped.iter().any(|rect| {
+                rect.x <= mid_x && mid_x < rect.right() && rect.y <= mid_y && mid_y < rect.bottom()
+            }) {
+                covered += (column[1] - column[0]) * (row[1] - row[0]);
+            }
+        }
+    }
+
+    (1.0 - covered / bounds.area()).clamp(0.0, 1.0)
+}
+
+/// Apps whose windows are system chrome (menu bar, Dock, notifications) or
+/// popular overlay utilities rather than documents, lowercased.
+pub fn system_apps() -> HashSet<String> {
+    [
+        "notification center",
+        "notificationcenter",
+        "sketchybar",
+        "borders",
+        "control center",
+        "controlcenter",
+        "dock",
+        "menubar",
+        "spotlight",
+    ]
+    .into_iter()
+    .map(String::from)
+    .collect()
+}
+
+pub fn inspect_window(
+    window_number: WindowNumber,
+) -> Result<Vec<(String, CfValue)>, WindowSearchError> {
+    let window_list = WindowList::copy(
+        K_CG_WINDOW_LIST_OPTION_INCLUDING_WINDOW,
+        window_number.0 as u32,
+    )?;
+    window_list
+        .0
+        .dictionary_at(0)
+        .map(|window_dict| read_dict_entries(&window_dict))
+        .ok_or(WindowSearchError::WindowNotFound(window_number))
+}
+
+pub fn format_window_entries(entries: &[(String, CfValue)]) -> String {
+    entries
+        .iter()
+        .map(|(key, value)| format!("{} = {} ({})", key, value, value.type_name()))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn read_dict_entries(dict: &CfDictionary) -> Vec<(String, CfValue)> {
+    let mut entries: Vec<(String, CfValue)> = dict
+        .entries()
+        .into_iter()
+        .map(|(key, value)| {
+            let key = key.unwrap_or_else(|| "<non-string key>".into());
+            (key, read_cf_value(&value))
+        })
+        .collect();
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    entries
+}
+
+fn read_cf_value(value: &CfType) -> CfValue {
+    if let Some(string) = value.to_string_value() {
+        CfValue::String(string)
+    } else if let Some(boolean) = value.to_bool() {
+        CfValue::Boolean(boolean)
+    } else if let Some(dict) = value.to_dictionary() {
+        CfValue::Dictionary(read_dict_entries(&dict))
+    } else if let Some(number) = value.to_f64() {
+        CfValue::Number(number)
+    } else {
+        CfValue::Other(value.type_name())
+    }
+}
+
+// FNV-1a: unlike `DefaultHasher`, its output is fixed across Rust releases, so
+// values derived from it can be persisted between runs. Callers feed it
+// explicit byte encodings rather than `Hash` impls, whose bytes depend on the
+// platform's endianness and the standard library's choices.
+pub(crate) struct StableHasher(u64);
+
+impl StableHasher {
+    pub(crate) fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+
+    // Length-prefixed UTF-8, so adjacent strings can't run into each other.
+    pub(crate) fn write_str(&mut self, value: &str) {
+        self.write(&(value.len() as u64).to_le_bytes());
+        self.write(value.as_bytes());
+    }
+}
+
+impl Hasher for StableHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+fn should_ignore_app(app_name: &str, ignored_apps: &HashSet<String>) -> bool {
+    let app_lower = app_name.to_lowercase();
+    ignored_apps
+        .iter()
+        .any(|ignored| app_lower.contains(ignored))
+}
+
+pub(crate) fn get_localized_app_name(pid: i32) -> Option<String> {
+    let app = NSRunningApplication::runningApplicationWithProcessIdentifier(pid)?;
+    app.localizedName().map(|name| name.to_string())
+}
+
+/// The app can quit between the CG listing and this lookup, so an exited pid,
+/// a nil result or a panic inside the Objective-C call all yield `None`.
+fn get_bundle_identifier(pid: i32) -> Option<String> {
+    resolve_bundle_identifier(pid, process_exists, |pid| {
+        let app = NSRunningApplication::runningApplicationWithProcessIdentifier(pid)?;
+        app.bundleIdentifier().map(|id| id.to_string())
+    })
+}
+
+fn resolve_bundle_identifier(
+    pid: i32,
+    is_alive: impl Fn(i32) -> bool,
+    lookup: impl Fn(i32) -> Option<String>,
+) -> Option<String> {
+    if pid <= 0 || !is_alive(pid) {
+        return None;
+    }
+
+    panic::catch_unwind(AssertUnwindSafe(|| lookup(pid)))
+        .ok()
+        .flatten()
+}
+
+fn process_exists(pid: i32) -> bool {
+    // Signal 0 only checks for the process; EPERM still means it exists.
+    let result = unsafe { kill(pid, 0) };
+    result == 0 || io::Error::last_os_error().raw_os_error() != Some(ESRCH)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::cf::CfNumber;
+
+    // One entry of a CG window list. CG leaves out the keys that are `None`
+    // here.
+    struct Entry {
+        window_number: i64,
+        pid: i32,
+        owner: Option<&'static str>,
+        title: Option<&'static str>,
+        bounds: WindowBounds,
+        layer: i32,
+    }
+
+    impl Entry {
+        fn new(window_number: i64, owner: &'static str, title: &'static str) -> Self {
+            Self {
+                window_number,
+                pid: 0,
+                owner: Some(owner),
+                title: Some(title),
+                bounds: WindowBounds::new(100.0, 100.0, 400.0, 300.0),
+                layer: 0,
+            }
+        }
+
+        fn to_dictionary(&self) -> CfDictionary {
+            let number = |value: f64| CfNumber::new(value).unwrap().to_cf_type();
+            let string = |value: &str| CfString::new(value).unwrap().to_cf_type();
+            let bounds = dictionary(&[
+                ("X", number(self.bounds.x)),
+                ("Y", number(self.bounds.y)),
+                ("Width", number(self.bounds.width)),
+                ("Height", number(self.bounds.height)),
+            ]);
+
+            let mut entries = vec![
+                ("kCGWindowNumber", number(self.window_number as f64)),
+                ("kCGWindowOwnerPID", number(self.pid as f64)),
+                ("kCGWindowLayer", number(self.layer as f64)),
+                ("kCGWindowAlpha", number(1.0)),
+                ("kCGWindowIsOnscreen", CfType::boolean(true)),
+                ("kCGWindowBounds", bounds.to_cf_type()),
+            ];
+            if let Some(owner) = self.owner {
+                entries.push(("kCGWindowOwnerName", string(owner)));
+            }
+            if let Some(title) = self.title {
+                entries.push(("kCGWindowName", string(title)));
+            }
+            dictionary(&entries)
+        }
+    }
+
+    fn dictionary(entries: &[(&str, CfType)]) -> CfDictionary {
+        let keys: Vec<CfString> = entries
+            .iter()
+            .map(|(key, _)| CfString::new(key).unwrap())
+            .collect();
+        let pairs: Vec<(&CfString, &CfType)> = keys
+            .iter()
+            .zip(entries.iter().map(|(_, value)| value))
+            .collect();
+        CfDictionary::new(&pairs).unwrap()
+    }
+
+    fn window_list(entries: &[Entry]) -> WindowList {
+        let dictionaries: Vec<CfDictionary> = entries.iter().map(Entry::to_dictionary).collect();
+        WindowList(CfArray::new(&dictionaries).unwrap())
+    }
+
+    fn titles(results: &WindowSearchResults) -> Vec<&str> {
+        results
+            .matched_windows
+            .iter()
+            .map(WindowInfo::display_title)
+            .collect()
+    }
+
+    #[test]
+    fn builder_defaults_match_the_docs() {
+        let window = WindowInfo::builder().build();
+        assert_eq!(window.title.as_deref(), Some(""));
+        assert_eq!(window.app_name, "Test");
+        assert_eq!(window.cg_owner_name, None);
+        assert_eq!(window.bundle_identifier, None);
+        assert_eq!(window.bounds, WindowBounds::new(0.0, 0.0, 100.0, 100.0));
+        assert_eq!(window.window_number, WindowNumber(0));
+        assert_eq!(window.pid, Pid(0));
+        assert_eq!(window.layer, 0);
+        assert_eq!(window.alpha, 1.0);
+        assert_eq!(window.sharing_state, 0);
+        assert_eq!(window.memory_usage, 0);
+        assert!(window.is_onscreen);
+        assert_eq!(window.is_minimized, Some(false));
+        assert_eq!(window.parent_window_number, None);
+        assert_eq!(window.z_index, 0);
+        assert_eq!(WindowInfoBuilder::default().build(), window);
+    }
+
+    #[test]
+    fn builder_setters_fill_their_fields() {
+        let window = WindowInfo::builder()
+            .with_title("Open")
+            .with_app_name("TextEdit")
+            .with_bundle_identifier("com.apple.TextEdit")
+            .with_bounds(WindowBounds::new(-1440.0, 20.0, 640.0, 480.0))
+            .with_window_number(42)
+            .with_pid(314)
+            .with_layer(8)
+            .with_alpha(0.5)
+            .with_parent_window_number(41)
+            .with_z_index(3)
+            .build();
+        assert_eq!(window.title.as_deref(), Some("Open"));
+        assert_eq!(window.app_key(), "com.apple.TextEdit");
+        assert_eq!(window.bounds.x, -1440.0);
+        assert_eq!(window.window_number, WindowNumber(42));
+        assert_eq!(window.pid, Pid(314));
+        assert_eq!(window.layer, 8);
+        assert_eq!(window.alpha, 0.5);
+        assert_eq!(window.parent_window_number, Some(WindowNumber(41)));
+        assert_eq!(window.z_index, 3);
+
+        let untitled = WindowInfo::builder().without_title().build();
+        assert_eq!(untitled.title, None);
+        assert_eq!(untitled.display_title(), "<no title>");
+
+        let minimized = WindowInfo::builder().minimized(true).build();
+        assert_eq!(minimized.is_minimized, Some(true));
+        assert!(!minimized.is_onscreen);
+    }
+
+    #[test]
+    fn ignored_windows_skip_every_read_after_the_owner() {
+        let dock = Entry {
+            pid: 10,
+            ..Entry::new(1, "Dock", "Dock")
+        };
+        let preview = Entry {
+            pid: 11,
+            ..Entry::new(2, "Preview", "a.pdf")
+        };
+        let criteria = WindowSearchCriteria::new().add_ignored_app("dock");
+        let mut context = ScanContext::new();
+
+        let total = walk_window_list(&window_list(&[dock]), &criteria, &mut context, |_, _| {});
+        assert_eq!(total, 0);
+        assert_eq!(context.reads, ["kCGWindowOwnerPID", "kCGWindowOwnerName"]);
+
+        // Checked once per pid: a second window of the app costs one read.
+        context.reads.clear();
+        let docks = [
+            Entry {
+                pid: 10,
+                ..Entry::new(1, "Dock", "Dock")
+            },
+            Entry {
+                pid: 10,
+                ..Entry::new(3, "Dock", "Dock")
+            },
+        ];
+        walk_window_list(&window_list(&docks), &criteria, &mut context, |_, _| {});
+        assert_eq!(
+            context.reads,
+            [
+                "kCGWindowOwnerPID",
+                "kCGWindowOwnerName",
+                "kCGWindowOwnerPID"
+            ]
+        );
+
+        context.reads.clear();
+        let mut matched = Vec::new();
+        walk_window_list(
+            &window_list(&[preview]),
+            &criteria,
+            &mut context,
+            |candidate, _| matched.push(candidate.window_number),
+        );
+        assert_eq!(matched, [WindowNumber(2)]);
+        assert!(context.reads.contains(&"kCGWindowBounds"));
+        assert!(context.reads.contains(&"kCGWindowName"));
+
+        // Our own windows are told apart by pid alone, without a string copy.
+        let own = Entry {
+            pid: std::process::id() as i32,
+            ..Entry::new(4, "panel_detector", "PANEL DETECTOR OVERLAY")
+        };
+        let mut context = ScanContext::new();
+        let total = walk_window_list(
+            &window_list(&[own]),
+            &WindowSearchCriteria::new(),
+            &mut context,
+            |_, _| {},
+        );
+        assert_eq!(total, 0);
+        assert_eq!(context.reads, ["kCGWindowOwnerPID"]);
+    }
+
+    #[test]
+    fn owner_name_falls_back_to_the_app_then_bundle_id_then_pid() {
+        let lookup = |pid: Pid| match pid.0 {
+            20 => AppMetadata {
+                localized_name: Some("Finder".to_string()),
+                bundle_identifier: Some("com.apple.finder".to_string()),
+            },
+            21 => AppMetadata {
+                localized_name: None,
+                bundle_identifier: Some("com.example.helper".to_string()),
+            },
+            _ => AppMetadata::default(),
+        };
+        let entries = [
+            Entry {
+                pid: 19,
+                ..Entry::new(1, "Preview", "a.pdf")
+            },
+            Entry {
+                pid: 20,
+                ..Entry::new(2, "", "Desktop")
+            },
+            Entry {
+                pid: 21,
+                owner: None,
+                ..Entry::new(3, "", "Helper")
+            },
+            Entry {
+                pid: 22,
+                owner: None,
+                ..Entry::new(4, "", "Gone")
+            },
+        ];
+        let mut context = ScanContext::new().with_app_lookup(lookup);
+
+        let results = filter_window_list(
+            &window_list(&entries),
+            &WindowSearchCriteria::new(),
+            &mut context,
+        )
+        .unwrap();
+        let names: Vec<(&str, Option<&str>)> = results
+            .matched_windows
+            .iter()
+            .map(|window| (window.app_name.as_str(), window.cg_owner_name.as_deref()))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                ("Preview", None),
+                ("Finder", Some("")),
+                ("com.example.helper", None),
+                ("pid 22", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn frontmost_per_app_keeps_each_apps_first_match() {
+        let lookup = |pid: Pid| AppMetadata {
+            localized_name: None,
+            bundle_identifier: match pid.0 {
+                30 => Some("com.apple.Preview".to_string()),
+                31 => Some("com.apple.TextEdit".to_string()),
+                _ => None,
+            },
+        };
+        let entries = [
+            Entry {
+                pid: 30,
+                ..Entry::new(1, "Preview", "a.pdf")
+            },
+            Entry {
+                pid: 31,
+                ..Entry::new(2, "TextEdit", "Notes.txt")
+            },
+            Entry {
+                pid: 30,
+                ..Entry::new(3, "Preview", "b.pdf")
+            },
+        ];
+        let window_list = window_list(&entries);
+        let scan = |criteria: WindowSearchCriteria| {
+            let mut context = ScanContext::new().with_app_lookup(lookup);
+            let results = filter_window_list(
+                &window_list,
+                &criteria.frontmost_per_app(true),
+                &mut context,
+            )
+            .unwrap();
+            results
+                .matched_windows
+                .iter()
+                .map(|window| window.window_number.0)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(scan(WindowSearchCriteria::new()), [1, 2]);
+        // The other filters run first, so a later window can be the app's
+        // frontmost match.
+        assert_eq!(
+            scan(WindowSearchCriteria::new().with_title_matching(MatchMode::Contains, "b.")),
+            [3]
+        );
+    }
+
+    #[test]
+    fn bundle_lookup_panics_resolve_to_none() {
+        let resolved = resolve_bundle_identifier(
+            42,
+            |_| true,
+            |_| -> Option<String> { panic!("nil bundle identifier") },
+        );
+        assert_eq!(resolved, None);
+    }
+
+    #[test]
+    fn vanished_pids_skip_the_bundle_lookup() {
+        let lookups = Cell::new(0);
+        let lookup = |_| {
+            lookups.set(lookups.get() + 1);
+            Some("com.example.app".to_string())
+        };
+
+        assert_eq!(resolve_bundle_identifier(42, |_| false, lookup), None);
+        assert_eq!(resolve_bundle_identifier(0, |_| true, lookup), None);
+        assert_eq!(lookups.get(), 0);
+        assert_eq!(
+            resolve_bundle_identifier(42, |pid| pid == 42, lookup).as_deref(),
+            Some("com.example.app")
+        );
+        assert_eq!(lookups.get(), 1);
+    }
+
+    #[test]
+    fn counting_matches_find_without_app_lookups() {
+        let entries = [
+            Entry::new(1, "TextEdit", "Open"),
+            Entry::new(2, "TextEdit", "Untitled"),
+            Entry {
+                pid: 7,
+                ..Entry::new(3, "Preview", "Open")
+            },
+            Entry {
+                pid: 8,
+                ..Entry::new(4, "Dock", "Open")
+            },
+        ];
+        let window_list = window_list(&entries);
+        let criteria = WindowSearchCriteria::new()
+            .with_title("Open")
+            .with_ignored_apps(HashSet::from(["dock".to_string()]));
+        let lookups = Arc::new(AtomicUsize::new(0));
+        let counted = Arc::clone(&lookups);
+        let mut context = ScanContext::new().with_app_lookup(move |_| {
+            counted.fetch_add(1, Ordering::SeqCst);
+            AppMetadata::default()
+        });
+
+        let count = count_window_list(&window_list, &criteria, &mut context);
+        assert_eq!(lookups.load(Ordering::SeqCst), 0);
+
+        let found = filter_window_list(&window_list, &criteria, &mut context).unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(count, found.matched_windows.len());
+        assert!(lookups.load(Ordering::SeqCst) > 0);
+    }
+
+    #[test]
+    fn raw_cg_options_reach_the_window_list_call() {
+        let raw = K_CG_WINDOW_LIST_OPTION_ALL | K_CG_WINDOW_LIST_EXCLUDE_DESKTOP_ELEMENTS;
+        let calls = RefCell::new(Vec::new());
+        let copy = |option, relative_to_window| {
+            calls.borrow_mut().push((option, relative_to_window));
+            Ok(window_list(&[Entry::new(1, "TextEdit", "Open")]))
+        };
+
+        let criteria = WindowSearchCriteria::new().with_title("Open");
+        scan_windows_with(K_CG_WINDOW_LIST_OPTION_ON_SCREEN_ONLY, 0, &criteria, copy).unwrap();
+        let raw_criteria = criteria.with_raw_cg_options(raw);
+        let results = scan_windows_with(
+            K_CG_WINDOW_LIST_OPTION_ON_SCREEN_ONLY,
+            0,
+            &raw_criteria,
+            copy,
+        )
+        .unwrap();
+        scan_windows_with(
+            K_CG_WINDOW_LIST_OPTION_ON_SCREEN_ABOVE_WINDOW,
+            9,
+            &raw_criteria,
+            copy,
+        )
+        .unwrap();
+
+        assert_eq!(results.matched_windows.len(), 1);
+        assert_eq!(
+            *calls.borrow(),
+            [
+                (K_CG_WINDOW_LIST_OPTION_ON_SCREEN_ONLY, 0),
+                (raw, 0),
+                (raw, 9),
+            ]
+        );
+    }
+
+    #[test]
+    fn app_cache_evicts_the_least_recently_used_pid() {
+        let lookups = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&lookups);
+        let mut context = ScanContext::with_capacity(2).with_app_lookup(move |pid| {
+            recorded.lock().unwrap().push(pid.0);
+            AppMetadata::default()
+        });
+
+        context.app(Pid(1));
+        context.app(Pid(2));
+        context.app(Pid(1));
+        context.app(Pid(3));
+        assert_eq!(context.cached_apps(), 2);
+        context.app(Pid(1));
+        context.app(Pid(2));
+
+        assert_eq!(*lookups.lock().unwrap(), [1, 2, 3, 2]);
+    }
+
+    #[test]
+    fn zero_capacity_disables_the_app_cache() {
+        let lookups = Arc::new(AtomicUsize::new(0));
+        let counted = Arc::clone(&lookups);
+        let mut context = ScanContext::with_capacity(0).with_app_lookup(move |_| {
+            counted.fetch_add(1, Ordering::SeqCst);
+            AppMetadata::default()
+        });
+
+        context.app(Pid(1));
+        context.app(Pid(1));
+
+        assert_eq!(context.cached_apps(), 0);
+        assert_eq!(lookups.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn evicting_drops_the_given_and_exited_pids() {
+        let mut context = ScanContext::new().with_app_lookup(|_| AppMetadata::default());
+        for pid in 1..=4 {
+            context.app(Pid(pid));
+        }
+
+        context.evict(Pid(1));
+        assert_eq!(context.cached_apps(), 3);
+        context.evict_exited_with(|pid| pid != 3);
+
+        let mut cached: Vec<i32> = context.apps.keys().map(|pid| pid.0).collect();
+        cached.sort();
+        assert_eq!(cached, [2, 4]);
+    }
+
+    #[test]
+    fn window_entries_print_sorted_with_their_types() {
+        let window_dict = Entry::new(5, "Finder", "Desktop").to_dictionary();
+
+        let text = format_window_entries(&read_dict_entries(&window_dict));
+
+        assert_eq!(
+            text,
+            [
+                "kCGWindowAlpha = 1 (number)",
+                "kCGWindowBounds = {Height = 300, Width = 400, X = 100, Y = 100} (dictionary)",
+                "kCGWindowIsOnscreen = true (boolean)",
+                "kCGWindowLayer = 0 (number)",
+                "kCGWindowName = \"Desktop\" (string)",
+                "kCGWindowNumber = 5 (number)",
+                "kCGWindowOwnerName = \"Finder\" (string)",
+                "kCGWindowOwnerPID = 0 (number)",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn window_entries_quote_strings_and_name_unsupported_types() {
+        let entries = [
+            (
+                "kCGWindowName".to_string(),
+                CfValue::String("say \"hi\"\n".to_string()),
+            ),
+            (
+                "kCGWindowBackingLocationVideoMemory".to_string(),
+                CfValue::Other("CFData".to_string()),
+            ),
+            (
+                "kCGWindowBounds".to_string(),
+                CfValue::Dictionary(Vec::new()),
+            ),
+        ];
+
+        assert_eq!(
+            format_window_entries(&entries),
+            "kCGWindowName = \"say \\\"hi\\\"\\n\" (string)\n\
+             kCGWindowBackingLocationVideoMemory = <unsupported> (CFData)\n\
+             kCGWindowBounds = {} (dictionary)"
+        );
+        assert_eq!(format_window_entries(&[]), "");
+    }
+
+    fn text_edit_window(window_number: i64, bounds: WindowBounds) -> WindowInfo {
+        WindowInfo::builder()
+            .with_window_number(window_number)
+            .with_app_name("TextEdit")
+            .with_bundle_identifier("com.apple.TextEdit")
+            .with_title("Untitled")
+            .with_bounds(bounds)
+            .build()
+    }
+
+    #[test]
+    fn fingerprints_are_pinned_across_platforms() {
+        let window = text_edit_window(1, WindowBounds::new(10.0, 20.0, 640.4, 480.6));
+        assert_eq!(window.fingerprint(), 0x68b5_8b42_d4ff_7edb);
+
+        let untitled = WindowInfo {
+            title: None,
+            ..window
+        };
+        assert_eq!(untitled.fingerprint(), 0x05b2_7e96_100c_8f41);
+    }
+
+    #[test]
+    fn identical_windows_share_a_fingerprint() {
+        let window = text_edit_window(1, WindowBounds::new(10.0, 20.0, 640.4, 480.6));
+        let restored = text_edit_window(97, WindowBounds::new(300.0, 200.0, 640.0, 481.0));
+        assert!(restored.matches_fingerprint(window.fingerprint()));
+
+        let retitled = WindowInfo {
+            title: Some("Notes".to_string()),
+            ..restored.clone()
+        };
+        let resized = text_edit_window(98, WindowBounds::new(10.0, 20.0, 800.0, 481.0));
+        assert!(!retitled.matches_fingerprint(window.fingerprint()));
+        assert!(!resized.matches_fingerprint(window.fingerprint()));
+    }
+
+    #[test]
+    fn criteria_summaries_list_the_window_filters() {
+        assert_eq!(WindowSearchCriteria::new().to_string(), "any window");
+        assert_eq!(
+            WindowSearchCriteria::new()
+                .with_known_dialog(KnownDialog::Open)
+                .require_valid_bounds(true)
+                .to_string(),
+            "Open dialogs"
+        );
+        assert_eq!(
+            WindowSearchCriteria::new()
+                .with_title_matching(MatchMode::Contains, "Invoice")
+                .with_app_name_matching(MatchMode::Regex, "^Pre")
+                .with_bundle_identifier("com.apple.Preview")
+                .to_string(),
+            "title containing \"Invoice\", app matching /^Pre/, bundle id \"com.apple.Preview\""
+        );
+    }
+
+    // Front to back on the display left of the primary, at negative x.
+    fn left_display_entries() -> [Entry; 3] {
+        [
+            Entry {
+                bounds: WindowBounds::new(-1500.0, 100.0, 200.0, 300.0),
+                ..Entry::new(1, "Finder", "Front")
+            },
+            Entry {
+                bounds: WindowBounds::new(-1600.0, 100.0, 400.0, 300.0),
+                ..Entry::new(2, "Finder", "Half covered")
+            },
+            Entry {
+                bounds: WindowBounds::new(-1900.0, -200.0, 300.0, 300.0),
+                ..Entry::new(3, "Finder", "Above the top")
+            },
+        ]
+    }
+
+    #[test]
+    fn negative_origins_parse_and_pick_the_left_display() {
+        let results = filter_window_list(
+            &window_list(&left_display_entries()),
+            &WindowSearchCriteria::new(),
+            &mut ScanContext::new(),
+        )
+        .unwrap();
+        let screens = crate::screen::tests::three_screens().screens;
+
+        let front = &results.matched_windows[0];
+        assert_eq!(
+            front.bounds,
+            WindowBounds::new(-1500.0, 100.0, 200.0, 300.0)
+        );
+        assert!(front.bounds.is_valid());
+        assert_eq!(front.screen_index(&screens), Some(2));
+        assert_eq!(
+            results.matched_windows[2].bounds,
+            WindowBounds::new(-1900.0, -200.0, 300.0, 300.0)
+        );
+    }
+
+    #[test]
+    fn visibility_and_rect_filters_work_at_negative_origins() {
+        let window_list = window_list(&left_display_entries());
+        let scan = |criteria: WindowSearchCriteria| {
+            let results =
+                filter_window_list(&window_list, &criteria, &mut ScanContext::new()).unwrap();
+            titles(&results)
+                .into_iter()
+                .map(String::from)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            scan(WindowSearchCriteria::new().with_min_visible_fraction(0.6)),
+            ["Front", "Above the top"]
+        );
+        assert_eq!(
+            scan(WindowSearchCriteria::new().with_min_visible_fraction(0.5)),
+            ["Front", "Half covered", "Above the top"]
+        );
+        assert_eq!(
+            scan(
+                WindowSearchCriteria::new()
+                    .within_rect(WindowBounds::new(-1920.0, 0.0, 1920.0, 1080.0))
+            ),
+            ["Front", "Half covered"]
+        );
+    }
+
+    fn results(total_windows: usize, windows: &[(i64, &str)]) -> WindowSearchResults {
+        WindowSearchResults {
+            total_windows,
+            matched_windows: windows
+                .iter()
+                .map(|&(window_number, title)| {
+                    WindowInfo::builder()
+                        .with_window_number(window_number)
+                        .with_title(title)
+                        .build()
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn merge_unions_and_prefers_the_newer_scan() {
+        let older = results(12, &[(1, "Open"), (2, "Save")]);
+        let newer = results(10, &[(3, "Print"), (2, "Save As")]);
+
+        let merged = older.merge(newer);
+
+        assert_eq!(merged.total_windows, 12);
+        assert_eq!(titles(&merged), ["Open", "Save As", "Print"]);
+        let numbers: Vec<i64> = merged
+            .matched_windows
+            .iter()
+            .map(|window| window.window_number.0)
+            .collect();
+        assert_eq!(numbers, [1, 2, 3]);
+    }
+
+    #[test]
+    fn merge_with_itself_or_nothing_changes_nothing() {
+        let scan = results(5, &[(1, "Open"), (2, "Save")]);
+
+        assert_eq!(scan_merge(&scan, &scan), scan);
+        assert_eq!(scan_merge(&scan, &results(0, &[])), scan);
+        assert_eq!(scan_merge(&results(0, &[]), &scan), scan);
+    }
+
+    fn scan_merge(a: &WindowSearchResults, b: &WindowSearchResults) -> WindowSearchResults {
+        let copy = |results: &WindowSearchResults| WindowSearchResults {
+            total_windows: results.total_windows,
+            matched_windows: results.matched_windows.clone(),
+        };
+        copy(a).merge(copy(b))
+    }
+
+    #[test]
+    fn bounds_validity_rejects_empty_and_non_finite() {
+        assert!(WindowBounds::new(-10.0, -20.0, 1.0, 1.0).is_valid());
+        assert!(!WindowBounds::new(0.0, 0.0, 0.0, 100.0).is_valid());
+        assert!(!WindowBounds::new(0.0, 0.0, 100.0, 0.0).is_valid());
+        assert!(!WindowBounds::new(0.0, 0.0, -100.0, 50.0).is_valid());
+        assert!(!WindowBounds::new(0.0, 0.0, 100.0, -50.0).is_valid());
+        assert!(!WindowBounds::new(f64::NAN, 0.0, 100.0, 50.0).is_valid());
+        assert!(!WindowBounds::new(0.0, 0.0, f64::NAN, 50.0).is_valid());
+        assert!(!WindowBounds::new(0.0, f64::INFINITY, 100.0, 50.0).is_valid());
+        assert!(!WindowBounds::new(0.0, 0.0, f64::INFINITY, 50.0).is_valid());
+        assert!(!WindowBounds::new(0.0, 0.0, 100.0, f64::NEG_INFINITY).is_valid());
+        assert!(!WindowBounds::default().is_valid());
+    }
+
+    #[test]
+    fn csv_quotes_fields_with_commas_quotes_and_newlines() {
+        let results = WindowSearchResults {
+            total_windows: 2,
+            matched_windows: vec![
+                WindowInfo::builder()
+                    .with_title("Report, \"final\"\nv2")
+                    .with_app_name("Acme, Inc.")
+                    .with_pid(42)
+                    .with_bounds(WindowBounds::new(-10.5, 20.0, 300.0, 200.0))
+                    .build(),
+                WindowInfo::builder()
+                    .without_title()
+                    .with_app_name("Finder")
+                    .with_bundle_identifier("com.apple.finder")
+                    .onscreen(false)
+                    .build(),
+            ],
+        };
+
+        assert_eq!(
+            results.to_csv(),
+            "title,app,bundle_id,pid,x,y,w,h,layer,alpha,onscreen\r\n\
+             \"Report, \"\"final\"\"\nv2\",\"Acme, Inc.\",,42,-10.5,20,300,200,0,1,true\r\n\
+             ,Finder,com.apple.finder,0,0,0,100,100,0,1,false\r\n"
+        );
+    }
+
+    #[test]
+    fn csv_fields_without_special_characters_stay_bare() {
+        assert_eq!(csv_field("Untitled — TextEdit"), "Untitled — TextEdit");
+        assert_eq!(csv_field(""), "");
+        assert_eq!(csv_field("a\rb"), "\"a\rb\"");
+        assert_eq!(csv_field("\""), "\"\"\"\"");
+    }
+
+    #[test]
+    fn identical_ghost_windows_collapse_to_the_frontmost() {
+        let at = |x: f64, y: f64| WindowBounds::new(x, y, 400.0, 300.0);
+        let entries = [
+            Entry {
+                bounds: at(100.0, 100.0),
+                ..Entry::new(1, "TextEdit", "Untitled")
+            },
+            Entry {
+                bounds: at(100.2, 99.8),
+                ..Entry::new(2, "TextEdit", "Untitled")
+            },
+            Entry {
+                bounds: at(130.0, 100.0),
+                ..Entry::new(3, "TextEdit", "Untitled")
+            },
+            Entry {
+                bounds: at(100.0, 100.0),
+                ..Entry::new(4, "TextEdit", "Untitled 2")
+            },
+            Entry {
+                pid: 1,
+                bounds: at(100.0, 100.0),
+                ..Entry::new(5, "Preview", "Untitled")
+            },
+        ];
+        let window_list = window_list(&entries);
+        let scan = |collapse| {
+            let criteria = WindowSearchCriteria::new().collapse_identical(collapse);
+            filter_window_list(&window_list, &criteria, &mut ScanContext::new())
+                .unwrap()
+                .matched_windows
+                .iter()
+                .map(|window| window.window_number.0)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(scan(true), [1, 3, 4, 5]);
+        assert_eq!(scan(false), [1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn iter_refs_borrows_the_owned_windows() {
+        let results = WindowSearchResults {
+            total_windows: 5,
+            matched_windows: vec![
+                text_edit_window(1, WindowBounds::new(10.0, 20.0, 640.0, 480.0)),
+                WindowInfo::builder()
+                    .with_window_number(2)
+                    .with_app_name("Finder")
+                    .without_title()
+                    .build(),
+            ],
+        };
+
+        let refs: Vec<_> = results.iter_refs().collect();
+
+        assert_eq!(refs.len(), 2);
+        for (window, view) in results.matched_windows.iter().zip(&refs) {
+            assert_eq!(&WindowInfo::from(*view), window);
+            assert!(std::ptr::eq(view.app_name, window.app_name.as_str()));
+        }
+        assert_eq!(refs[0].title, Some("Untitled"));
+        assert_eq!(refs[0].bundle_identifier, Some("com.apple.TextEdit"));
+        assert_eq!(refs[1].title, None);
+    }
+
+    #[test]
+    fn absent_and_empty_titles_stay_distinct() {
+        let entries = [
+            Entry {
+                title: None,
+                ..Entry::new(1, "Finder", "")
+            },
+            Entry::new(2, "Finder", ""),
+        ];
+        let window_list = window_list(&entries);
+        let scan = |criteria: &WindowSearchCriteria| {
+            filter_window_list(&window_list, criteria, &mut ScanContext::new()).unwrap()
+        };
+
+        let all = scan(&WindowSearchCriteria::new());
+        let found: Vec<_> = all
+            .matched_windows
+            .iter()
+            .map(|w| w.title.as_deref())
+            .collect();
+        assert_eq!(found, [None, Some("")]);
+        assert_eq!(titles(&all), ["<no title>", ""]);
+
+        let empty = scan(&WindowSearchCriteria::new().with_title(""));
+        assert_eq!(empty.matched_windows.len(), 1);
+        assert_eq!(empty.matched_windows[0].window_number, WindowNumber(2));
+    }
+
+    #[test]
+    fn by_screen_groups_windows_per_display_in_order() {
+        let screens = crate::screen::tests::three_screens().screens;
+        let window = |window_number: i64, x: f64, y: f64| {
+            WindowInfo::builder()
+                .with_window_number(window_number)
+                .with_bounds(WindowBounds::new(x, y, 400.0, 300.0))
+                .build()
+        };
+        let results = WindowSearchResults {
+            total_windows: 5,
+            matched_windows: vec![
+                window(1, 1500.0, -1000.0),
+                window(2, 100.0, 100.0),
+                window(3, 5000.0, 5000.0),
+                window(4, 1300.0, -300.0),
+                window(5, 1000.0, 100.0),
+            ],
+        };
+
+        let groups = results.by_screen(&screens);
+
+        let summary: Vec<(Option<ScreenInfo>, Vec<i64>)> = groups
+            .iter()
+            .map(|(screen, windows)| {
+                let numbers = windows.iter().map(|w| w.window_number.0).collect();
+                (*screen, numbers)
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (Some(screens[0]), vec![2, 5]),
+                (Some(screens[1]), vec![1, 4]),
+                (Some(screens[2]), vec![]),
+                (None, vec![3]),
+            ]
+        );
+    }
+
+    #[test]
+    fn transforms_rewrite_matches_after_filtering() {
+        let window_list = window_list(&[
+            Entry::new(1, "TextEdit", "/Users/me/notes.txt"),
+            Entry::new(2, "TextEdit", "readme.md"),
+            Entry::new(3, "Finder", "/Users/me"),
+        ]);
+        let criteria = WindowSearchCriteria::new()
+            .with_title_matching(MatchMode::StartsWith, "/Users/")
+            .with_transform(|mut window| {
+                window.title = window
+                    .title
+                    .map(|title| title.rsplit('/').next().unwrap_or_default().to_string());
+                window
+            });
+
+        let results = filter_window_list(&window_list, &criteria, &mut ScanContext::new()).unwrap();
+
+        assert_eq!(titles(&results), ["notes.txt", "me"]);
+    }
+
+    #[test]
+    fn including_scans_keep_the_reference_window_last() {
+        // Front to back, as CG orders on-screen windows.
+        let screen = [
+            Entry::new(10, "Finder", "Front"),
+            Entry::new(7, "TextEdit", "Target"),
+            Entry::new(3, "Safari", "Behind"),
+        ];
+        // Answers like CG: the windows in front of the reference one, plus the
+        // reference window itself with `INCLUDING_WINDOW`.
+        let calls = RefCell::new(Vec::new());
+        let copy = |option: u32, relative_to_window: u32| {
+            calls.borrow_mut().push((option, relative_to_window));
+            let reference = screen
+                .iter()
+                .position(|entry| entry.window_number == i64::from(relative_to_window))
+                .unwrap();
+            let end = if option & K_CG_WINDOW_LIST_OPTION_INCLUDING_WINDOW != 0 {
+                reference + 1
+            } else {
+                reference
+            };
+            Ok(window_list(&screen[..end]))
+        };
+
+        let results = scan_including(WindowNumber(7), &WindowSearchCriteria::new(), copy).unwrap();
+        assert_eq!(titles(&results), ["Front", "Target"]);
+
+        let front_only = WindowSearchCriteria::new().with_title("Front");
+        let results = scan_including(WindowNumber(7), &front_only, copy).unwrap();
+        assert_eq!(titles(&results), ["Front"]);
+
+        let option = K_CG_WINDOW_LIST_OPTION_ON_SCREEN_ABOVE_WINDOW
+            | K_CG_WINDOW_LIST_OPTION_INCLUDING_WINDOW;
+        assert_eq!(*calls.borrow(), [(option, 7), (option, 7)]);
+    }
+
+    #[test]
+    fn derived_geometry_follows_the_bounds() {
+        let window = WindowInfo::builder()
+            .with_bounds(WindowBounds::new(-100.0, 50.0, 800.0, 400.0))
+            .build();
+
+        assert_eq!(window.aspect_ratio(), Some(2.0));
+        assert_eq!(window.top_left(), (-100.0, 50.0));
+        assert_eq!(window.bottom_right(), (700.0, 450.0));
+        assert_eq!(window.area(), 320_000.0);
+
+        let empty = WindowInfo::builder()
+            .with_bounds(WindowBounds::new(0.0, 0.0, 800.0, 0.0))
+            .build();
+        assert_eq!(empty.aspect_ratio(), None);
+        assert_eq!(empty.area(), 0.0);
+    }
+
+    #[test]
+    fn fullscreen_detection_allows_rounding_but_not_the_menu_bar() {
+        let screen = WindowBounds::new(1440.0, -1080.0, 1920.0, 1080.0);
+        let window = |x: f64, y: f64, width: f64, height: f64| {
+            WindowInfo::builder()
+                .with_bounds(WindowBounds::new(x, y, width, height))
+                .build()
+        };
+
+        assert!(window(1440.0, -1080.0, 1920.0, 1080.0).is_fullscreen_on(&screen));
+        assert!(window(1440.5, -1079.5, 1919.0, 1079.0).is_fullscreen_on(&screen));
+        assert!(!window(1440.0, -1055.0, 1920.0, 1055.0).is_fullscreen_on(&screen));
+        assert!(!window(0.0, 0.0, 1920.0, 1080.0).is_fullscreen_on(&screen));
+
+        let screens = crate::screen::tests::three_screens().screens;
+        let covering = window(1440.0, -1080.0, 1920.0, 1080.0);
+        assert!(covering.is_fullscreen(&screens));
+        let floating = WindowInfo {
+            layer: 3,
+            ..covering
+        };
+        assert!(!floating.is_fullscreen(&screens));
+    }
+
+    #[test]
+    fn fullscreen_windows_are_found_on_every_display() {
+        let screens = crate::screen::tests::three_screens().screens;
+        let window = |x: f64, y: f64, width: f64, height: f64| {
+            WindowInfo::builder()
+                .with_bounds(WindowBounds::new(x, y, width, height))
+                .build()
+        };
+
+        assert!(window(0.0, 0.0, 1440.0, 900.0).is_fullscreen(&screens));
+        assert!(window(-1920.0, 0.0, 1920.0, 1080.0).is_fullscreen(&screens));
+        // Zoomed to the primary's visible frame, below the menu bar.
+        assert!(!window(0.0, 25.0, 1440.0, 875.0).is_fullscreen(&screens));
+        // Spanning two displays without covering either.
+        assert!(!window(-960.0, 0.0, 1920.0, 900.0).is_fullscreen(&screens));
+        assert!(!window(0.0, 0.0, 1440.0, 900.0).is_fullscreen(&[]));
+    }
+
+    #[test]
+    fn presets_set_exactly_their_documented_filters() {
+        let normal = WindowSearchCriteria::normal_windows();
+        assert_eq!(normal.layer, Some(0));
+        assert!(normal.onscreen_only);
+        assert_eq!(
+            normal.min_visible_fraction,
+            Some(NORMAL_MIN_VISIBLE_FRACTION)
+        );
+        assert!(normal.require_valid_bounds);
+        assert_eq!(normal.ignored_apps, system_apps());
+        assert!(!normal.require_dialog);
+
+        let dialogs = WindowSearchCriteria::dialogs_only();
+        assert!(dialogs.require_dialog);
+        assert!(dialogs.require_valid_bounds);
+        assert_eq!(dialogs.ignored_apps, system_apps());
+        assert_eq!(dialogs.layer, None);
+        assert!(!dialogs.onscreen_only);
+        assert_eq!(dialogs.min_visible_fraction, None);
+
+        let everything = WindowSearchCriteria::everything();
+        assert_eq!(everything.to_string(), "any window");
+        assert!(everything.ignored_apps.is_empty());
+        assert_eq!(everything.layer, None);
+        assert!(!everything.onscreen_only);
+        assert!(!everything.require_valid_bounds);
+        assert!(!everything.require_dialog);
+    }
+
+    #[test]
+    fn handles_format_and_serialize_as_plain_integers() {
+        assert_eq!(WindowNumber(4127).to_string(), "4127");
+        assert_eq!(Pid(-1).to_string(), "-1");
+        assert_eq!(serde_json::to_string(&WindowNumber(4127)).unwrap(), "4127");
+        assert_eq!(serde_json::to_string(&Pid(314)).unwrap(), "314");
+        assert_eq!(
+            serde_json::from_str::<WindowNumber>("4127").unwrap(),
+            WindowNumber(4127)
+        );
+        assert_eq!(serde_json::from_str::<Pid>("314").unwrap(), Pid(314));
+    }
+
+    #[test]
+    fn handles_convert_to_and_from_their_integers() {
+        assert_eq!(WindowNumber::from(7_i64), WindowNumber(7));
+        assert_eq!(i64::from(WindowNumber(7)), 7);
+        assert_eq!(Pid::from(314), Pid(314));
+        assert_eq!(i32::from(Pid(314)), 314);
+
+        let window = WindowInfo::builder()
+            .with_window_number(7)
+            .with_pid(314)
+            .build();
+        let json = serde_json::to_value(&window).unwrap();
+        assert_eq!(json["window_number"], 7);
+        assert_eq!(json["pid"], 314);
+    }
+
+    #[test]
+    fn our_own_panels_are_skipped_unless_included() {
+        let own_pid = std::process::id() as i32;
+        let window_list = window_list(&[
+            Entry {
+                pid: own_pid,
+                ..Entry::new(1, "panel_detector", "PANEL DETECTOR OVERLAY")
+            },
+            Entry {
+                pid: own_pid + 1,
+                ..Entry::new(2, "TextEdit", "Open")
+            },
+        ]);
+        let scan = |criteria: &WindowSearchCriteria| {
+            filter_window_list(&window_list, criteria, &mut ScanContext::new()).unwrap()
+        };
+
+        assert_eq!(titles(&scan(&WindowSearchCriteria::new())), ["Open"]);
+        assert_eq!(
+            titles(&scan(&WindowSearchCriteria::new().include_self(true))),
+            ["PANEL DETECTOR OVERLAY", "Open"]
+        );
+    }
+
+    #[test]
+    fn app_name_sets_match_any_listed_app() {
+        let window_list = window_list(&[
+            Entry {
+                pid: 1,
+                ..Entry::new(1, "Safari", "Apple")
+            },
+            Entry {
+                pid: 2,
+                ..Entry::new(2, "Google Chrome", "Google")
+            },
+            Entry {
+                pid: 3,
+                ..Entry::new(3, "Mail", "Inbox")
+            },
+            Entry {
+                pid: 4,
+                ..Entry::new(4, "safari", "Lowercase")
+            },
+        ]);
+        let browsers = || {
+            WindowSearchCriteria::new()
+                .with_app_names(["Safari", "Google Chrome", "Firefox"].map(String::from))
+        };
+        let scan = |criteria: &WindowSearchCriteria| {
+            titles(&filter_window_list(&window_list, criteria, &mut ScanContext::new()).unwrap())
+                .into_iter()
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(scan(&browsers()), ["Apple", "Google"]);
+        assert_eq!(scan(&browsers().with_app_name("Safari")), ["Apple"]);
+        assert!(scan(&browsers().with_app_name("Mail")).is_empty());
+    }
+
+    #[test]
+    fn visibility_partitions_visible_occluded_and_offscreen_windows() {
+        let window = |window_number: i64, x: f64, width: f64| {
+            WindowInfo::builder()
+                .with_window_number(window_number)
+                .with_bounds(WindowBounds::new(x, 0.0, width, 300.0))
+        };
+        let results = WindowSearchResults {
+            total_windows: 5,
+            matched_windows: vec![
+                window(1, 0.0, 400.0).build(),
+                window(2, 200.0, 400.0).build(),
+                window(3, 2000.0, 400.0).minimized(true).build(),
+                window(4, 1000.0, 400.0).build(),
+                window(5, 250.0, 100.0).build(),
+            ],
+        };
+        let partition = |min_fraction| {
+            let numbers = |windows: Vec<&WindowInfo>| {
+                windows
+                    .iter()
+                    .map(|window| window.window_number.0)
+                    .collect::<Vec<_>>()
+            };
+            let (visible, hidden) = results.partition_visibility(min_fraction);
+            (numbers(visible), numbers(hidden))
+        };
+
+        assert_eq!(partition(0.6), (vec![1, 4], vec![2, 3, 5]));
+        assert_eq!(partition(0.5), (vec![1, 2, 4], vec![3, 5]));
+    }
+
+    #[test]
+    fn volatile_fields_can_be_ignored_when_comparing_results() {
+        let earlier = results(40, &[(1, "Open"), (2, "Save")]);
+        let mut later = results(42, &[(1, "Open"), (2, "Save")]);
+        later.matched_windows[0].memory_usage = 4096;
+        later.matched_windows[1].alpha = 0.5;
+
+        assert_ne!(earlier, later);
+        assert!(!earlier.semantically_eq(&later, &[WindowField::MemoryUsage]));
+        assert!(earlier.semantically_eq(&later, &[WindowField::MemoryUsage, WindowField::Alpha]));
+        assert!(later.semantically_eq(&earlier, &[WindowField::MemoryUsage, WindowField::Alpha]));
+    }
+
+    #[test]
+    fn ignoring_fields_still_compares_the_windows_matched() {
+        let ignore = [WindowField::MemoryUsage, WindowField::Alpha];
+        let scan = results(5, &[(1, "Open"), (2, "Save")]);
+
+        assert!(scan.semantically_eq(&scan, &[]));
+        assert!(!scan.semantically_eq(&results(5, &[(1, "Open")]), &ignore));
+        assert!(!scan.semantically_eq(&results(5, &[(2, "Save"), (1, "Open")]), &ignore));
+        assert!(!scan.semantically_eq(&results(5, &[(1, "Open"), (2, "Save As")]), &ignore));
+        assert!(scan.semantically_eq(
+            &results(5, &[(1, "Open"), (2, "Save As")]),
+            &[WindowField::Title]
+        ));
+    }
+
+    #[test]
+    fn titles_split_at_the_last_separator() {
+        assert_eq!(
+            split_title("report.pdf — Preview"),
+            Some(("report.pdf", "Preview"))
+        );
+        assert_eq!(split_title("Notes – TextEdit"), Some(("Notes", "TextEdit")));
+        assert_eq!(
+            split_title("main.rs - relative-panel - Visual Studio Code"),
+            Some(("main.rs - relative-panel", "Visual Studio Code"))
+        );
+        assert_eq!(
+            split_title("Q3 - Draft — Pages"),
+            Some(("Q3 - Draft", "Pages"))
+        );
+        assert_eq!(
+            split_title("Inbox — 3 unread — Mail"),
+            Some(("Inbox — 3 unread", "Mail"))
+        );
+        assert_eq!(split_title("well-known-name.txt"), None);
+        assert_eq!(split_title("Finder"), None);
+    }
+
+    #[test]
+    fn document_and_suffix_match_the_title_parts() {
+        let document = WindowSearchCriteria::new().with_title_document_part("Q3 - Draft");
+        let suffix = WindowSearchCriteria::new().with_title_app_suffix("Pages");
+        let both = WindowSearchCriteria::new()
+            .with_title_document_part("report.pdf")
+            .with_title_app_suffix("Preview");
+
+        assert!(document.matches(Some("Q3 - Draft — Pages"), "Pages"));
+        assert!(!document.matches(Some("Q3 — Pages"), "Pages"));
+        assert!(suffix.matches(Some("Q3 - Draft — Pages"), "Pages"));
+        assert!(!suffix.matches(Some("Pages — Keynote"), "Keynote"));
+        assert!(both.matches(Some("report.pdf — Preview"), "Preview"));
+        assert!(!both.matches(Some("report.pdf — Skim"), "Skim"));
+        assert!(!both.matches(None, "Preview"));
+    }
+
+    #[test]
+    fn titles_without_a_separator_match_whole() {
+        let document = WindowSearchCriteria::new().with_title_document_part("Downloads");
+        let suffix = WindowSearchCriteria::new().with_title_app_suffix("Downloads");
+
+        assert!(document.matches(Some("Downloads"), "Finder"));
+        assert!(suffix.matches(Some("Downloads"), "Finder"));
+        assert!(!document.matches(Some("Downloads-old"), "Finder"));
+    }
+
+    #[test]
+    fn trees_list_windows_under_their_app_in_order() {
+        let window = |app: &str, window_number: i64, title: Option<&str>, x: f64| {
+            let builder = WindowInfo::builder()
+                .with_app_name(app)
+                .with_window_number(window_number)
+                .with_bounds(WindowBounds::new(x, 25.0, 800.0, 600.0));
+            match title {
+                Some(title) => builder.with_title(title),
+                None => builder.without_title(),
+            }
+        };
+        let scan = WindowSearchResults {
+            total_windows: 4,
+            matched_windows: vec![
+                window("Preview", 40, Some("b.pdf"), 100.0)
+                    .with_bundle_identifier("com.apple.Preview")
+                    .build(),
+                window("Finder", 7, None, 0.0).build(),
+                window("Preview", 12, Some("a.pdf"), 0.0)
+                    .with_bundle_identifier("com.apple.Preview")
+                    .build(),
+                window("Finder", 3, Some("Downloads"), -1920.0).build(),
+            ],
+        };
+
+        assert_eq!(
+            scan.format_tree(),
+            "Finder\n\
+             \x20 ├─ \"Downloads\" [#3 -1920,25 800×600]\n\
+             \x20 └─ \"<no title>\" [#7 0,25 800×600]\n\
+             Preview (com.apple.Preview)\n\
+             \x20 ├─ \"a.pdf\" [#12 0,25 800×600]\n\
+             \x20 └─ \"b.pdf\" [#40 100,25 800×600]\n"
+        );
+        assert_eq!(results(0, &[]).format_tree(), "");
+    }
+
+    #[test]
+    fn cache_hits_within_the_ttl_and_misses_after() {
+        let cache = WindowCache::new(Duration::from_millis(16));
+        let entries = [Entry::new(1, "Preview", "a.pdf")];
+        let fetches = Cell::new(0);
+        let copy = |_, _| {
+            fetches.set(fetches.get() + 1);
+            Ok(window_list(&entries))
+        };
+        let criteria = WindowSearchCriteria::new();
+        let start = Instant::now();
+
+        let first = cache.find_windows_with(start, &criteria, copy).unwrap();
+        let hit = cache
+            .find_windows_with(start + Duration::from_millis(15), &criteria, copy)
+            .unwrap();
+        assert_eq!(fetches.get(), 1);
+        assert_eq!(first, hit);
+
+        cache
+            .find_windows_with(start + Duration::from_millis(16), &criteria, copy)
+            .unwrap();
+        assert_eq!(fetches.get(), 2);
+    }
+
+    #[test]
+    fn cache_applies_changed_criteria_to_the_cached_list() {
+        let cache = WindowCache::new(Duration::from_secs(1));
+        let entries = [
+            Entry::new(1, "Preview", "a.pdf"),
+            Entry::new(2, "Preview", "b.pdf"),
+        ];
+        let fetches = Cell::new(0);
+        let copy = |_, _| {
+            fetches.set(fetches.get() + 1);
+            Ok(window_list(&entries))
+        };
+        let now = Instant::now();
+
+        let a = cache
+            .find_windows_with(now, &WindowSearchCriteria::new().with_title("a.pdf"), copy)
+            .unwrap();
+        let b = cache
+            .find_windows_with(now, &WindowSearchCriteria::new().with_title("b.pdf"), copy)
+            .unwrap();
+        assert_eq!(titles(&a), ["a.pdf"]);
+        assert_eq!(titles(&b), ["b.pdf"]);
+        assert_eq!(fetches.get(), 1);
+    }
+
+    #[test]
+    fn cache_bypasses_other_list_options_and_invalidates() {
+        let cache = WindowCache::new(Duration::from_secs(1));
+        let entries = [Entry::new(1, "Preview", "a.pdf")];
+        let options = RefCell::new(Vec::new());
+        let copy = |option, _| {
+            options.borrow_mut().push(option);
+            Ok(window_list(&entries))
+        };
+        let on_screen = WindowSearchCriteria::new();
+        let offscreen = WindowSearchCriteria::new().include_offscreen(true);
+        let now = Instant::now();
+
+        cache.find_windows_with(now, &on_screen, copy).unwrap();
+        cache.find_windows_with(now, &offscreen, copy).unwrap();
+        cache.find_windows_with(now, &offscreen, copy).unwrap();
+        cache.find_windows_with(now, &on_screen, copy).unwrap();
+        assert_eq!(
+            *options.borrow(),
+            [
+                K_CG_WINDOW_LIST_OPTION_ON_SCREEN_ONLY,
+                K_CG_WINDOW_LIST_OPTION_ALL,
+                K_CG_WINDOW_LIST_OPTION_ALL,
+            ]
+        );
+
+        cache.invalidate();
+        cache.find_windows_with(now, &on_screen, copy).unwrap();
+        assert_eq!(options.borrow().len(), 4);
+    }
 }
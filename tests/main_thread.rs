@@ -0,0 +1,156 @@
+//! Panel tests that create AppKit objects, which is only allowed on the main
+//! thread. The default test harness runs tests on worker threads, so this
+//! target has none: `main` runs each test in turn.
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use objc2::rc::{autoreleasepool, Retained};
+use objc2::{MainThreadMarker, MainThreadOnly};
+use objc2_app_kit::{NSApplication, NSPanel, NSView};
+use objc2_foundation::{NSPoint, NSRect, NSSize};
+use panel_detector::panel::{
+    create_overlay_panel_with_frame, release_panel_callbacks, update_panel, ChangedFields,
+    PanelConfig, PanelContent, PanelContentProvider,
+};
+use panel_detector::window_search::WindowInfo;
+
+const TESTS: &[(&str, fn())] = &[
+    (
+        "click_handlers_are_dropped_with_their_panel",
+        click_handlers_are_dropped_with_their_panel,
+    ),
+    (
+        "releasing_callbacks_drops_click_handlers_early",
+        releasing_callbacks_drops_click_handlers_early,
+    ),
+    (
+        "content_providers_build_and_refresh_panel_content",
+        content_providers_build_and_refresh_panel_content,
+    ),
+];
+
+fn main() {
+    let mtm = MainThreadMarker::new().expect("the test binary's main thread");
+    NSApplication::sharedApplication(mtm);
+
+    for (name, test) in TESTS {
+        test();
+        println!("test {} ... ok", name);
+    }
+    println!("\ntest result: ok. {} passed", TESTS.len());
+}
+
+fn untitled() -> WindowInfo {
+    WindowInfo::builder()
+        .with_app_name("TextEdit")
+        .with_title("Untitled")
+        .build()
+}
+
+fn panel_for(window: &WindowInfo, config: &PanelConfig) -> Retained<NSPanel> {
+    let frame = NSRect::new(NSPoint::new(100.0, 100.0), NSSize::new(200.0, 40.0));
+    let panel = create_overlay_panel_with_frame(window, frame, config).expect("a valid frame");
+    panel.orderOut(None);
+    panel
+}
+
+struct DropCounter(Rc<Cell<usize>>);
+
+impl Drop for DropCounter {
+    fn drop(&mut self) {
+        self.0.set(self.0.get() + 1);
+    }
+}
+
+// A button panel whose click handler counts its drops.
+fn counted_click_config(drops: &Rc<Cell<usize>>) -> PanelConfig {
+    let counter = DropCounter(Rc::clone(drops));
+    PanelConfig::new()
+        .with_content(PanelContent::Button)
+        .on_click(move |_| {
+            let _counter = &counter;
+        })
+}
+
+fn click_handlers_are_dropped_with_their_panel() {
+    let drops = Rc::new(Cell::new(0));
+    autoreleasepool(|_| {
+        let config = counted_click_config(&drops);
+        let panel = panel_for(&untitled(), &config);
+        drop(config);
+        assert_eq!(drops.get(), 0);
+        drop(panel);
+    });
+    assert_eq!(drops.get(), 1);
+}
+
+fn releasing_callbacks_drops_click_handlers_early() {
+    let drops = Rc::new(Cell::new(0));
+    let config = counted_click_config(&drops);
+    let panel = panel_for(&untitled(), &config);
+    drop(config);
+
+    autoreleasepool(|_| release_panel_callbacks(&panel));
+    assert_eq!(drops.get(), 1);
+    drop(panel);
+    assert_eq!(drops.get(), 1);
+}
+
+// Logs the windows it builds and updates content for.
+struct RecordingProvider(Rc<RefCell<Vec<String>>>);
+
+impl PanelContentProvider for RecordingProvider {
+    fn build_content(
+        &self,
+        window: &WindowInfo,
+        frame: NSRect,
+        mtm: MainThreadMarker,
+    ) -> Retained<NSView> {
+        self.0.borrow_mut().push(format!(
+            "build {} in {}x{}",
+            window.display_title(),
+            frame.size.width,
+            frame.size.height
+        ));
+        NSView::initWithFrame(NSView::alloc(mtm), frame)
+    }
+
+    fn update_content(&self, view: &NSView, window: &WindowInfo, changed: ChangedFields) {
+        self.0.borrow_mut().push(format!(
+            "update {} in {}x{}, {:?}",
+            window.display_title(),
+            view.frame().size.width,
+            view.frame().size.height,
+            changed
+        ));
+    }
+}
+
+fn content_providers_build_and_refresh_panel_content() {
+    let calls = Rc::new(RefCell::new(Vec::new()));
+    let config = PanelConfig::new()
+        .with_content(PanelContent::Button)
+        .with_content_provider(RecordingProvider(Rc::clone(&calls)));
+    let window = untitled();
+    let retitled = WindowInfo::builder()
+        .with_app_name("TextEdit")
+        .with_title("Notes.txt")
+        .build();
+
+    let panel = panel_for(&window, &config);
+    assert!(update_panel(&panel, &window, &retitled, &config));
+    assert!(!update_panel(&panel, &retitled, &retitled, &config));
+
+    let size = panel.contentView().expect("a content view").frame().size;
+    assert_eq!(
+        *calls.borrow(),
+        [
+            format!("build Untitled in {}x{}", size.width, size.height),
+            format!(
+                "update Notes.txt in {}x{}, ChangedFields {{ title: true, app: false }}",
+                size.width, size.height
+            ),
+        ]
+    );
+}